@@ -0,0 +1,31 @@
+#![feature(test)]
+
+extern crate test;
+extern crate bf;
+
+use bf::ast;
+
+use bf::config::Config;
+use bf::traits::{Interpretable, CompactCompilable};
+use bf::test_helpers;
+
+use test::Bencher;
+
+#[bench]
+fn compile_factor(b: &mut Bencher) {
+    let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
+
+    b.iter(|| {
+        program.compact_compile(&Config::new())
+    });
+}
+
+#[bench]
+fn interpret_factor_million(b: &mut Bencher) {
+    let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
+    let program = program.compact_compile(&Config::new());
+
+    b.iter(|| {
+        program.interpret_memory(&Config::new(), b"1000000\n").unwrap()
+    });
+}
@@ -6,6 +6,7 @@ extern crate bf;
 #[cfg(feature = "jit")]
 mod jit_only {
     use bf::ast;
+    use bf::config::Config;
 
     use bf::traits::{Interpretable, JitCompilable};
     use bf::test_helpers;
@@ -17,7 +18,7 @@ mod jit_only {
         let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
 
         b.iter(|| {
-            program.jit_compile(true)
+            program.jit_compile(&Config::new())
         });
     }
 
@@ -26,27 +27,47 @@ mod jit_only {
         let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
 
         b.iter(|| {
-            program.jit_compile(false)
+            program.jit_compile(&Config::new().checked(false))
         });
     }
 
     #[bench]
     fn run_factor_million(b: &mut Bencher) {
         let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
-        let program = program.jit_compile(true);
+        let program = program.jit_compile(&Config::new());
 
         b.iter(|| {
-            program.interpret_memory(None, b"1000000\n").unwrap()
+            program.interpret_memory(&Config::new(), b"1000000\n").unwrap()
         });
     }
 
     #[bench]
     fn run_factor_million_unchecked(b: &mut Bencher) {
         let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
-        let program = program.jit_compile(false);
+        let program = program.jit_compile(&Config::new().checked(false));
 
         b.iter(|| {
-            program.interpret_memory(None, b"1000000\n").unwrap()
+            program.interpret_memory(&Config::new(), b"1000000\n").unwrap()
         });
     }
-}
\ No newline at end of file
+
+    #[bench]
+    fn run_mandelbrot(b: &mut Bencher) {
+        let program = ast::parse_program(test_helpers::MANDELBROT_SRC).unwrap();
+        let program = program.jit_compile(&Config::new());
+
+        b.iter(|| {
+            program.interpret_memory(&Config::new(), b"").unwrap()
+        });
+    }
+
+    #[bench]
+    fn run_mandelbrot_unchecked(b: &mut Bencher) {
+        let program = ast::parse_program(test_helpers::MANDELBROT_SRC).unwrap();
+        let program = program.jit_compile(&Config::new().checked(false));
+
+        b.iter(|| {
+            program.interpret_memory(&Config::new(), b"").unwrap()
+        });
+    }
+}
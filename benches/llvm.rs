@@ -0,0 +1,64 @@
+#![feature(test)]
+
+extern crate test;
+extern crate bf;
+
+#[cfg(feature = "llvm")]
+mod llvm_only {
+    use bf::ast;
+    use bf::config::Config;
+
+    use bf::traits::{Interpretable, LlvmCompilable};
+    use bf::test_helpers;
+
+    use test::Bencher;
+
+    #[bench]
+    fn compile_factor(b: &mut Bencher) {
+        let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
+
+        b.iter(|| {
+            program.llvm_compile(&Config::new())
+        });
+    }
+
+    #[bench]
+    fn run_factor_million(b: &mut Bencher) {
+        let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
+        let program = program.llvm_compile(&Config::new());
+
+        b.iter(|| {
+            program.interpret_memory(&Config::new(), b"1000000\n").unwrap()
+        });
+    }
+
+    #[bench]
+    fn run_factor_million_unchecked(b: &mut Bencher) {
+        let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
+        let program = program.llvm_compile(&Config::new().checked(false));
+
+        b.iter(|| {
+            program.interpret_memory(&Config::new(), b"1000000\n").unwrap()
+        });
+    }
+
+    #[bench]
+    fn run_mandelbrot(b: &mut Bencher) {
+        let program = ast::parse_program(test_helpers::MANDELBROT_SRC).unwrap();
+        let program = program.llvm_compile(&Config::new());
+
+        b.iter(|| {
+            program.interpret_memory(&Config::new(), b"").unwrap()
+        });
+    }
+
+    #[bench]
+    fn run_mandelbrot_unchecked(b: &mut Bencher) {
+        let program = ast::parse_program(test_helpers::MANDELBROT_SRC).unwrap();
+        let program = program.llvm_compile(&Config::new().checked(false));
+
+        b.iter(|| {
+            program.interpret_memory(&Config::new(), b"").unwrap()
+        });
+    }
+}
@@ -4,6 +4,8 @@ extern crate test;
 extern crate bf;
 
 use bf::ast;
+
+use bf::config::Config;
 use bf::traits::{Interpretable, PeepholeCompilable};
 use bf::test_helpers;
 
@@ -14,16 +16,16 @@ fn compile_factor(b: &mut Bencher) {
     let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
 
     b.iter(|| {
-        program.peephole_compile()
+        program.peephole_compile(&Config::new())
     });
 }
 
 #[bench]
 fn interpret_factor_million(b: &mut Bencher) {
     let program = ast::parse_program(test_helpers::FACTOR_SRC).unwrap();
-    let program = program.peephole_compile();
+    let program = program.peephole_compile(&Config::new());
 
     b.iter(|| {
-        program.interpret_memory(None, b"1000000\n").unwrap()
+        program.interpret_memory(&Config::new(), b"1000000\n").unwrap()
     });
 }
\ No newline at end of file
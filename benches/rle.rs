@@ -4,6 +4,8 @@ extern crate test;
 extern crate bf;
 
 use bf::ast;
+
+use bf::config::Config;
 use bf::traits::{Interpretable, RleCompilable};
 use bf::test_helpers;
 
@@ -24,6 +26,6 @@ fn interpret_factor_million(b: &mut Bencher) {
     let program = program.rle_compile();
 
     b.iter(|| {
-        program.interpret_memory(None, b"1000000\n").unwrap()
+        program.interpret_memory(&Config::new(), b"1000000\n").unwrap()
     });
 }
\ No newline at end of file
@@ -0,0 +1,92 @@
+// Generated by `bf::rust::compile`. Do not edit.
+
+/// Runs the compiled Brainfuck program.
+#[allow(dead_code)]
+pub fn run<R, W>(state: &mut ::state::State, input: R, output: W)
+    -> ::common::BfResult<()>
+    where R: ::std::io::Read, W: ::std::io::Write
+{
+    #[allow(unused_imports)]
+    use ::channel::Channel;
+
+    if state.cell_width() != ::config::CellWidth::Eight {
+        return Err(::common::Error::Unsupported);
+    }
+
+    #[allow(unused_mut, unused_variables)]
+    let mut channel = ::channel::BufferedChannel::new(input, output);
+
+    state.up(6);
+    while state.load() != 0 {
+        state.step()?;
+        state.right(1usize)?;
+        state.up(12);
+        state.left(1usize)?;
+        state.up(255);
+    }
+    state.right(1usize)?;
+    state.write(&mut channel);
+    state.right(1usize)?;
+    state.up(10);
+    while state.load() != 0 {
+        state.step()?;
+        state.right(1usize)?;
+        state.up(10);
+        state.left(1usize)?;
+        state.up(255);
+    }
+    state.right(1usize)?;
+    state.up(1);
+    state.write(&mut channel);
+    state.up(7);
+    state.write(&mut channel);
+    state.write(&mut channel);
+    state.up(3);
+    state.write(&mut channel);
+    state.right(1usize)?;
+    state.up(4);
+    while state.load() != 0 {
+        state.step()?;
+        state.right(1usize)?;
+        state.up(11);
+        state.left(1usize)?;
+        state.up(255);
+    }
+    state.right(1usize)?;
+    state.write(&mut channel);
+    state.left(1usize)?;
+    state.up(3);
+    while state.load() != 0 {
+        state.step()?;
+        state.right(1usize)?;
+        state.up(252);
+        state.left(1usize)?;
+        state.up(255);
+    }
+    state.right(1usize)?;
+    state.write(&mut channel);
+    state.left(5usize)?;
+    state.up(3);
+    while state.load() != 0 {
+        state.step()?;
+        state.right(1usize)?;
+        state.up(5);
+        state.left(1usize)?;
+        state.up(255);
+    }
+    state.right(1usize)?;
+    state.write(&mut channel);
+    state.right(2usize)?;
+    state.write(&mut channel);
+    state.up(3);
+    state.write(&mut channel);
+    state.up(250);
+    state.write(&mut channel);
+    state.up(248);
+    state.write(&mut channel);
+    state.right(2usize)?;
+    state.up(1);
+    state.write(&mut channel);
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ BIG_PRIME=179424691
 
 make -C cpp
 
-rustup run nightly cargo build --release --features=jit
+rustup run nightly cargo build --release --features=jit,llvm
 
 heading () {
     echo "$1"
@@ -34,8 +34,12 @@ bench () {
 
 bench "bfi peephole AST"                target/release/bfi --peep
 bench "bfi bytecode"                    target/release/bfi --byte
+bench "bfi threaded bytecode"           target/release/bfi --threaded
+bench "bfi compact bytecode"            target/release/bfi --compact
 bench "bfi native JIT"                  target/release/bfi --jit
 bench "bfi native JIT (unchecked)"      target/release/bfi --jit -u
+bench "bfi LLVM JIT"                    target/release/bfi --llvm
+bench "bfi LLVM JIT (unchecked)"        target/release/bfi --llvm -u
 bench "Bendersky's optinterp3"          cpp/optinterp3
 bench "Bendersky's optasmjit"           cpp/optasmjit
 
@@ -64,7 +64,7 @@ impl LoopBalance {
 
 impl LoopIndex {
     /// Gets the loop index from a boxed loop.
-    fn from_loop_body(body: &LoopBody) -> Self {
+    fn from_loop_body(body: &[Statement]) -> Self {
         LoopIndex(body.as_ptr() as usize)
     }
 }
@@ -76,7 +76,7 @@ impl LoopBalanceMap {
 
         for statement in program {
             match *statement {
-                Statement::Instr(_) => (),
+                Statement::Instr(_) | Statement::OutConst(_) => (),
                 Statement::Loop(ref body) => {
                     result.analyze_loop(body);
                 }
@@ -87,21 +87,21 @@ impl LoopBalanceMap {
     }
 
     /// Gets the balance of the given loop body.
-    pub fn get(&self, body: &LoopBody) -> LoopBalance {
+    pub fn get(&self, body: &[Statement]) -> LoopBalance {
         *self.0.get(&LoopIndex::from_loop_body(body)).unwrap_or(&LoopBalance::Unknown)
     }
 
     /// Performs the analysis for the given loop body and any sub-loops.
     ///
     /// Stores the result of the analysis in `self`.
-    fn analyze_loop(&mut self, body: &LoopBody) -> LoopBalance {
+    fn analyze_loop(&mut self, body: &[Statement]) -> LoopBalance {
         use peephole::Statement::*;
         use common::Instruction::*;
         use self::LoopBalance::*;
 
         let mut net = Exact(0);
 
-        for statement in &**body {
+        for statement in body {
             match *statement {
                 Instr(Right(count)) => net = match net {
                     Exact(disp) => Exact(disp + count as isize),
@@ -115,11 +115,13 @@ impl LoopBalanceMap {
                     _           => Unknown,
                 },
 
-                Instr(Add(_)) | Instr(In) | Instr(Out) |
+                Instr(Add(_)) | Instr(In) | Instr(Out) | Instr(OutByte(_)) | OutConst(_) |
                 Instr(SetZero) | Instr(OffsetAddRight(_)) | Instr(OffsetAddLeft(_)) => (),
 
                 Instr(JumpZero(_)) | Instr(JumpNotZero(_)) =>
                     panic!("unexpected jump instruction"),
+                Instr(OutString(_)) =>
+                    panic!("unexpected string instruction"),
 
                 Instr(FindZeroRight(_)) =>
                     net = if net.is_right_only() { RightOnly } else { Unknown },
@@ -145,3 +147,30 @@ impl LoopBalanceMap {
         net
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::LoopBalance::*;
+    use config::Config;
+
+    #[test]
+    fn balances() {
+        assert_eq!(balance_of(b"[-<+>.]"), Exact(0));
+        assert_eq!(balance_of(b"[>>+<]"), Exact(1));
+        assert_eq!(balance_of(b"[<+[>]]"), Unknown);
+        assert_eq!(balance_of(b"[>[>]]"), RightOnly);
+        assert_eq!(balance_of(b"[<[<]]"), LeftOnly);
+    }
+
+    fn balance_of(program: &[u8]) -> LoopBalance {
+        let program = ::ast::parse_program(program).unwrap();
+        let program = ::peephole::compile(&::rle::compile(&program), &Config::new());
+        let map = LoopBalanceMap::new(&program);
+
+        match program[0] {
+            Statement::Loop(ref body) => map.get(body),
+            _ => panic!("expected a loop"),
+        }
+    }
+}
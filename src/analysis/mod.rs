@@ -1,5 +1,13 @@
-use super::loop_balance::LoopBalanceMap;
+//! Bounds-checking analysis for the native code generators.
+//!
+//! The JIT and LLVM backends use this to omit bounds checks for pointer movements that provably
+//! stay inside memory. The analysis works on peephole-optimized AST.
+
+pub mod loop_balance;
+
+use self::loop_balance::LoopBalanceMap;
 use common::Count;
+use traits::IntoUsize;
 use peephole::{Statement, Program};
 
 /// Interface for bounds checking analysis.
@@ -38,7 +46,7 @@ pub trait BoundsAnalysis {
     fn reset_right(&mut self);
 
     /// Updates the marks upon entering a loop.
-    fn enter_loop(&mut self, body: &Box<[Statement]>);
+    fn enter_loop(&mut self, body: &[Statement]);
 
     /// Updates the marks upon leaving a loop.
     fn leave_loop(&mut self);
@@ -79,7 +87,7 @@ impl BoundsAnalysis for AbstractInterpreter {
     ///
     /// Returns whether we can prove that this move will not underflow.
     fn move_left(&mut self, count: Count) -> bool {
-        let count = count as usize;
+        let count = count.into_usize();
 
         self.right_mark += count;
         if count <= self.left_mark {
@@ -95,7 +103,7 @@ impl BoundsAnalysis for AbstractInterpreter {
     ///
     /// Returns whether we can prove that this move will not overflow.
     fn move_right(&mut self, count: Count) -> bool {
-        let count = count as usize;
+        let count = count.into_usize();
 
         self.left_mark += count;
         if count <= self.right_mark {
@@ -108,11 +116,11 @@ impl BoundsAnalysis for AbstractInterpreter {
     }
 
     fn check_left(&self, count: Count) -> bool {
-        count <= self.left_mark
+        count.into_usize() <= self.left_mark
     }
 
     fn check_right(&self, count: Count) -> bool {
-        count <= self.right_mark
+        count.into_usize() <= self.right_mark
     }
 
     /// Resets the left mark.
@@ -130,7 +138,7 @@ impl BoundsAnalysis for AbstractInterpreter {
     }
 
     /// Updates the marks upon entering a loop.
-    fn enter_loop(&mut self, body: &Box<[Statement]>) {
+    fn enter_loop(&mut self, body: &[Statement]) {
         let balance = self.loop_balances.get(body);
 
         if balance.is_balanced() {
@@ -169,6 +177,25 @@ impl BoundsAnalysis for NoAnalysis {
     fn check_right(&self, _count: Count) -> bool { false }
     fn reset_left(&mut self) { }
     fn reset_right(&mut self) { }
-    fn enter_loop(&mut self, _body: &Box<[Statement]>) { }
+    fn enter_loop(&mut self, _body: &[Statement]) { }
     fn leave_loop(&mut self) { }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_within_marks_are_proved() {
+        let mut interpreter = AbstractInterpreter::new(&[]);
+
+        assert!(!interpreter.move_right(3));
+        assert!(interpreter.move_left(2));
+        assert!(interpreter.check_left(1));
+        assert!(!interpreter.check_left(2));
+        assert!(interpreter.check_right(2));
+
+        interpreter.reset_right();
+        assert!(!interpreter.move_right(1));
+    }
+}
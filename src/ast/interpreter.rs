@@ -1,35 +1,32 @@
-use std::io::{Read, Write};
-
+use channel::Channel;
 use state::State;
 use common::BfResult;
 use traits::Interpretable;
 use super::*;
 
 impl Interpretable for Program {
-    fn interpret_state<R: Read, W: Write>(
-        &self, mut state: State, mut input: R, mut output: W) -> BfResult<()>
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, channel: &mut C)
+        -> BfResult<()>
     {
-        interpret(self, &mut state, &mut input, &mut output)
+        interpret(self, state, channel)
     }
 }
 
-fn interpret<R, W>(instructions: &Program, state: &mut State,
-                   input: &mut R, output: &mut W)
-                   -> BfResult<()>
-    where R: Read, W: Write
+fn interpret<C>(instructions: &Program, state: &mut State, channel: &mut C)
+               -> BfResult<()>
+    where C: Channel + ?Sized
 {
     for instruction in instructions {
-        interpret_instruction(instruction, state, input, output)?;
+        interpret_instruction(instruction, state, channel)?;
     }
 
     Ok(())
 }
 
 #[inline]
-fn interpret_instruction<R, W>(instruction: &Statement, state: &mut State,
-                               input: &mut R, output: &mut W)
-                               -> BfResult<()>
-    where R: Read, W: Write
+fn interpret_instruction<C>(instruction: &Statement, state: &mut State, channel: &mut C)
+                           -> BfResult<()>
+    where C: Channel + ?Sized
 {
     use super::Statement::*;
     use super::Command::*;
@@ -37,15 +34,16 @@ fn interpret_instruction<R, W>(instruction: &Statement, state: &mut State,
     match *instruction {
         Cmd(Left) => state.left(1usize)?,
         Cmd(Right) => state.right(1usize)?,
-        Cmd(Up) => state.up(1),
-        Cmd(Down) => state.down(1),
-        Cmd(In) => state.read(input),
-        Cmd(Out) => state.write(output),
+        Cmd(Up) => state.up_cell(1),
+        Cmd(Down) => state.down_cell(1),
+        Cmd(In) => state.read(channel)?,
+        Cmd(Out) => state.write(channel),
         Cmd(Begin) | Cmd(End) =>
             panic!("Invalid instruction: Begin or End"),
         Loop(ref program) => {
-            while state.load() != 0  {
-                interpret(program, state, input, output)?;
+            while state.load_cell() != 0  {
+                state.step()?;
+                interpret(program, state, channel)?;
             }
         }
     }
@@ -22,7 +22,7 @@ pub fn parse_program(input: &[u8]) -> BfResult<Box<Program>> {
 /// remaining input. A failed parse returns `Err`.
 type Parser<'a, R> = BfResult<(R, &'a [u8])>;
 
-fn parse_instruction<'a>(mut input: &'a [u8]) -> Parser<Option<Statement>> {
+fn parse_instruction<'a>(mut input: &'a [u8]) -> Parser<'a, Option<Statement>> {
     use common::Command::*;
 
     let ok = |cmd, inp: &'a [u8]| Ok((Some(Statement::Cmd(cmd)), inp));
@@ -65,7 +65,7 @@ fn parse_instruction<'a>(mut input: &'a [u8]) -> Parser<Option<Statement>> {
     }
 }
 
-fn parse_instructions(mut input: &[u8]) -> Parser<Box<Program>> {
+fn parse_instructions(mut input: &[u8]) -> Parser<'_, Box<Program>> {
     let mut instructions = Vec::new();
 
     loop {
@@ -5,24 +5,72 @@
 //!     bfi [FLAGS] [OPTIONS] [--] [FILE]...
 //!
 //! FLAGS:
-//!         --ast          Interpret the unoptimized AST
-//!         --byte         Compile AST to bytecode
-//!     -h, --help         Prints help information
-//!         --jit          JIT to native x64 (default)
-//!         --llvm         JIT using LLVM
-//!         --peep         Interpret the peephole-optimized AST
-//!         --rle          Interpret the run-length encoded the AST
-//!     -u, --unchecked    Omit memory bounds checks in JIT
-//!     -V, --version      Prints version information
+//!         --ast            Interpret the unoptimized AST
+//!         --byte           Compile AST to bytecode
+//!         --compile        Compile to a standalone x64 Linux executable
+//!         --compact        Compile AST to compact bytecode
+//!     -h, --help           Prints help information
+//!     -i, --interactive    Don’t buffer input or output
+//!         --jit            JIT to native x64 (default)
+//!         --llvm           JIT using LLVM
+//!         --peep           Interpret the peephole-optimized AST
+//!         --repl           Run code a line at a time, keeping memory between lines (the default with no program)
+//!         --rle            Interpret the run-length encoded the AST
+//!     -u, --unchecked      Omit memory bounds checks in JIT
+//!         --threaded       Interpret threaded code compiled from bytecode
+//!     -V, --version        Prints version information
 //!
 //! OPTIONS:
-//!     -e, --expr <CODE>...    BF code to execute
-//!     -s, --size <SIZE>       Memory size in bytes (default 30,000)
+//!         --cache-dir <DIR>           Reuse JIT-compiled code saved in this directory
+//!         --cell-width <BITS>         Bits per cell (default 8); wider cells need --ast or --rle [values: 8, 16, 32]
+//!         --checkpoint <FILE>         Where to save snapshots for --checkpoint-every
+//!         --checkpoint-every <N>      Save a snapshot every N loop iterations (uses the bytecode interpreter)
+//!         --disable-pass <NAME>...    Skip an optimization pass [values: rle, set-zero, find-zero, offset-add, const-output]
+//!         --dump-tape[=<WHEN>]        Print the used part of memory to stderr when the program stops (default on-error) [values: on-error, always]
+//!         --emit <FORMAT>             Print compiled code instead of running it [values: c, rust, wasm, wat, bytecode]
+//!                                     With LLVM, also [values: ll, bc, asm, obj, runtime]
+//!         --eof <MODE>                What `,` stores at end of input (default zero) [values: zero, minus-one, unchanged]
+//!     -e, --expr <CODE>...            BF code to execute
+//!         --input <FILE>              Read the program’s input from FILE instead of stdin
+//!     -O <LEVEL>                      Optimization level (default 3) [values: 0, 1, 2, 3]
+//!     -o <FILE>                       Where to write compiled output
+//!         --output <FILE>             Write the program’s output to FILE instead of stdout
+//!         --pointer <N>               Start with the pointer at cell N
+//!         --resume <FILE>             Continue from a snapshot saved by --checkpoint
+//!     -s, --size <SIZE>               Memory size in bytes (default 30,000)
+//!         --step-limit <N>            Stop with an error after N loop iterations
+//!         --tape <MODE>               Whether memory grows to the right (default fixed) [values: fixed, growable]
+//!         --tape-init <FILE>          Start with memory loaded from a tape image, such as `0 0 [5] 12 0`
 //!
 //! ARGS:
-//!     <FILE>...    The source file(s) to interpret
+//!     <FILE>...    The source file(s) to interpret, or a bytecode file from `--emit bytecode`
 //! ```
 //!
+//! A snapshot records the memory, the pointer, and where the program was, along with a hash of
+//! the program’s bytecode, so `--resume` needs the same program and options that produced it.
+//! The memory contents and the memory size, tape, EOF, step limit, and pointer options come from
+//! the snapshot. Input that the program read before the snapshot is not replayed.
+//!
+//! A tape image for `--tape-init` lists cells from the start of memory as numbers from 0 to 255,
+//! with the cell under the pointer in brackets; see [`bf::tape`](../bf/tape/index.html). The
+//! LLVM backend can only start with the pointer at cell 0. Bytecode emitted with the
+//! `const-output` pass assumes that memory starts out zero, so it refuses to run with
+//! `--tape-init`.
+//!
+//! Cells wider than 8 bits wrap around at their width, and `,` at end of input with
+//! `--eof minus-one` stores the largest cell value; `.` writes the low byte. Only the AST and RLE
+//! interpreters support them. They can’t be emitted, and since tape images and memory views
+//! show bytes, they don’t go with `--tape-init`, `--dump-tape`, or the REPL.
+//!
+//! `--dump-tape` shows memory in hex and ASCII, with the cell under the pointer in brackets,
+//! from the start through the highest address that the program used or the last non-zero cell.
+//!
+//! With `--repl`, or with no program, `bfi` reads Brainfuck a line at a time, running each line
+//! as soon as its loops are closed and then showing the cells around the pointer. Memory and the
+//! pointer carry over from line to line. Lines starting with `:` are commands: `:reset`,
+//! `:tape`, `:load FILE`, `:backend [NAME]`, and `:help`; see
+//! [`bf::repl`](../bf/repl/index.html). Prompts and the memory view go to stderr.
+//!
 //! See [the library crate documentation](../bf/index.html) for more.
 
 extern crate bf;
@@ -30,96 +78,382 @@ extern crate bf;
 #[macro_use]
 extern crate clap;
 
-use std::io::Read;
-use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write, stderr, stdin, stdout};
+use std::fs::{self, File};
 use std::process::exit;
 
 use clap::{Arg, App};
 
 use bf::ast;
+use bf::bytecode::resumable::{Machine, Status, Suspension};
+use bf::bytecode::snapshot;
+use bf::channel::{BufferedChannel, Buffering, Channel};
+use bf::common::{BfResult, Error};
+use bf::config::{self, CellWidth, Config, EofMode, Pass, TapeMode};
+use bf::engine::Backend;
+use bf::repl::{Response, Session};
+use bf::state::State;
+use bf::tape::TapeImage;
 use bf::traits::*;
 
 #[derive(Debug, Clone)]
 struct Options {
     program_text:  Vec<u8>,
-    memory_size:   Option<usize>,
-    compiler_pass: Pass,
-    unchecked:     bool,
+    config:        Config,
+    backend:       Backend,
+    buffering:     Buffering,
+    compile_to:    Option<String>,
+    cache_dir:     Option<String>,
+    emit:          Option<Emit>,
+    out_file:      Option<String>,
+    checkpoint_every: Option<u64>,
+    checkpoint:    Option<String>,
+    resume:        Option<String>,
+    tape_init:     Option<TapeImage>,
+    pointer:       Option<usize>,
+    input_file:    Option<String>,
+    output_file:   Option<String>,
+    dump_tape:     DumpTape,
+    repl:          bool,
+}
+
+/// When to print memory after running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DumpTape {
+    Never,
+    OnError,
+    Always,
 }
 
 #[derive(Debug, Clone, Copy)]
-enum Pass {
-    Ast,
-    Rle,
+enum Emit {
+    C,
+    Rust,
+    Wasm,
+    Wat,
     Bytecode,
-    Peephole,
-    #[cfg(feature = "jit")]
-    Jit,
     #[cfg(feature = "llvm")]
-    Llvm,
+    Llvm(bf::llvm::OutputFormat),
+    #[cfg(feature = "llvm")]
+    LlvmRuntime,
 }
 
+#[cfg(feature = "llvm")]
+const EMIT_FORMATS: &[&str] = &["c", "rust", "wasm", "wat", "bytecode",
+                                 "ll", "bc", "asm", "obj", "runtime"];
+
+#[cfg(not(feature = "llvm"))]
+const EMIT_FORMATS: &[&str] = &["c", "rust", "wasm", "wat", "bytecode"];
+
+/// Options that only make sense with a program, so they don’t go with `--repl`.
+const PROGRAM_OPTIONS: &[&str] = &["emit", "compile", "cache-dir", "checkpoint-every",
+                                   "checkpoint", "resume", "input", "output", "dump-tape"];
+
 fn main() {
     let options = get_options();
 
+    if options.repl {
+        repl(&options);
+        return;
+    }
+
+    if options.checkpoint.is_some() || options.resume.is_some() {
+        run_checkpointed(&options);
+        return;
+    }
+
+    if bf::bytecode::format::is_bytecode(&options.program_text) {
+        run_bytecode_file(&options);
+        return;
+    }
+
     let program = parse(&options);
 
-    match options.compiler_pass {
-        Pass::Ast => {
-            interpret(&*program, &options);
-        }
+    if let Some(format) = options.emit {
+        emit(&program, format, &options);
+        return;
+    }
 
-        Pass::Rle => {
-            let program = program.rle_compile();
-            interpret(&*program, &options);
+    #[cfg(feature = "jit")]
+    {
+        if let Some(ref path) = options.compile_to {
+            compile_executable(&program, path, &options);
+            return;
         }
+    }
 
-        Pass::Peephole => {
-            let program = program.peephole_compile();
-            interpret(&*program, &options);
+    #[cfg(feature = "jit")]
+    {
+        if let (Backend::Jit, Some(ref dir)) = (options.backend, &options.cache_dir) {
+            let program = bf::jit::compile_cached(&options.program_text, &options.config, dir)
+                .unwrap_or_else(|e| error_exit(1, &format!("error: {}.", e)));
+            interpret(&program, &options);
+            return;
         }
+    }
+
+    let program = options.backend.compile_ast(program, &options.config);
+    interpret(&*program, &options);
+}
+
+/// Runs a program saved with `--emit bytecode`.
+///
+/// Passes that start from bytecode are honored; the others use the bytecode interpreter.
+fn run_bytecode_file(options: &Options) {
+    let program = load_bytecode(options);
+
+    match options.backend {
+        Backend::Threaded => interpret(&program.threaded_compile(&options.config), options),
+        Backend::Compact => interpret(&program.compact_compile(&options.config), options),
+        _ => interpret(&program, options),
+    }
+}
+
+/// Loads a program saved with `--emit bytecode`, refusing one whose output depends on memory
+/// starting out zero if `--tape-init` says otherwise.
+fn load_bytecode(options: &Options) -> Box<bf::bytecode::Program> {
+    let program = bf::bytecode::load(&*options.program_text)
+        .unwrap_or_else(|e| error_exit(2, &format!("bad bytecode file: {}.", e)));
+
+    if program.assumes_fresh_memory() && options.tape_init.is_some() {
+        error_exit(1, "error: this bytecode assumes that memory starts out zero, so it cannot \
+                       run with --tape-init; emit it again with --disable-pass const-output.");
+    }
+
+    program
+}
+
+/// Runs the program with the resumable bytecode interpreter, starting from the `--resume`
+/// snapshot if given, and saving a snapshot to the `--checkpoint` file every
+/// `--checkpoint-every` steps.
+fn run_checkpointed(options: &Options) {
+    let program = if bf::bytecode::format::is_bytecode(&options.program_text) {
+        load_bytecode(options)
+    } else {
+        parse(options).bytecode_compile(&options.config)
+    };
 
-        Pass::Bytecode => {
-            let program = program.bytecode_compile();
-            interpret(&*program, &options);
+    let mut machine = match options.resume {
+        Some(ref path) => File::open(path)
+            .and_then(|file| snapshot::load(&program, BufReader::new(file)))
+            .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path))),
+        None => Machine::new(initial_state(options)),
+    };
+
+    let (input, output) = open_io(options);
+    let mut channel = BufferedChannel::with_buffering(input, output, options.buffering);
+
+    loop {
+        let status = match options.checkpoint_every {
+            Some(steps) => machine.run_steps(&program, &mut channel, steps),
+            None => machine.run(&program, &mut channel),
+        };
+
+        let result = match status {
+            Status::Finished => Ok(()),
+            Status::Suspended(Suspension::Paused) => {
+                // Output up to the snapshot should survive a crash after it.
+                channel.flush();
+                let path = options.checkpoint.as_ref().expect("clap requires --checkpoint");
+                save_snapshot(&machine, &program, path)
+                    .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path)));
+                continue;
+            }
+            Status::Suspended(Suspension::NeedInput) => Err(Error::WouldBlock),
+            Status::Error(e) => Err(e),
+        };
+
+        channel.flush();
+        finish(result, machine.state(), options);
+        return;
+    }
+}
+
+/// Runs code from stdin a line at a time, with prompts and results on stderr.
+///
+/// Program input also comes from stdin, unbuffered so that it doesn’t read ahead into the lines
+/// that follow.
+fn repl(options: &Options) {
+    let mut session = Session::with_state(options.backend, options.config, initial_state(options));
+    let mut channel = BufferedChannel::with_buffering(stdin(), stdout(), Buffering::interactive());
+
+    loop {
+        eprint!("{}", if session.is_incomplete() { "... " } else { "bf> " });
+
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) => {
+                eprintln!();
+                return;
+            }
+            Ok(_) => (),
+            Err(e) => error_exit(1, &format!("error: could not read input: {}.", e)),
         }
 
-        #[cfg(feature = "jit")]
-        Pass::Jit => {
-            let program = program.jit_compile(!options.unchecked);
-            interpret(&program, &options);
+        let result = session.eval_line(line.trim_end_matches(&['\r', '\n'][..]), &mut channel);
+        channel.flush();
+        if session.take_open_line() {
+            eprintln!();
         }
 
-        #[cfg(feature = "llvm")]
-        Pass::Llvm => {
-            program.llvm_run(options.memory_size)
-                .unwrap_or_else(|e| error_exit(3, &format!("runtime error: {}.", e)));
+        match result {
+            Ok(Response::Incomplete) => (),
+            Ok(Response::Done) => eprintln!("{}", session.window()),
+            Ok(Response::Message(message)) => eprint!("{}", message),
+            Err(e) => {
+                eprintln!("bfi: {}.", e);
+                eprintln!("{}", session.window());
+            }
         }
     }
 }
 
+/// Saves a snapshot by writing a temporary file and renaming it over `path`, so that a crash
+/// while saving leaves the previous snapshot intact.
+fn save_snapshot(machine: &Machine, program: &bf::bytecode::Program, path: &str)
+                 -> std::io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    {
+        let mut file = BufWriter::new(File::create(&temp_path)?);
+        snapshot::save(machine, program, &mut file)?;
+        file.into_inner()?.sync_all()?;
+    }
+    fs::rename(&temp_path, path)
+}
+
 fn parse(options: &Options) -> Box<ast::Program> {
     ast::parse_program(&options.program_text)
         .unwrap_or_else(|e| error_exit(2, &format!("syntax error: {}.", e)))
 }
 
-fn interpret<P: Interpretable + ?Sized>(program: &P, options: &Options) {
-    program.interpret_stdin(options.memory_size)
-        .unwrap_or_else(|e| error_exit(3, &format!("runtime error: {}.", e)))
+fn interpret(program: &dyn Runnable, options: &Options) {
+    let mut state = initial_state(options);
+    let (input, output) = open_io(options);
+    let mut channel = BufferedChannel::with_buffering(input, output, options.buffering);
+
+    let result = program.run_in_place(&mut state, &mut channel);
+    channel.flush();
+    finish(result, &state, options);
+}
+
+/// Prints memory if `--dump-tape` asks for it, and then exits with an error if the program
+/// failed.
+fn finish(result: BfResult<()>, state: &State, options: &Options) {
+    let dump = match options.dump_tape {
+        DumpTape::Never => false,
+        DumpTape::OnError => result.is_err(),
+        DumpTape::Always => true,
+    };
+
+    if dump {
+        let stderr = stderr();
+        let mut stderr = stderr.lock();
+        let _ = writeln!(stderr, "bfi: memory, with the pointer at {}:", state.pointer())
+            .and_then(|_| bf::tape::dump(state, &mut stderr));
+    }
+
+    result.unwrap_or_else(|e| error_exit(3, &format!("runtime error: {}.", e)))
+}
+
+/// The state to start from, with memory from `--tape-init` and the pointer from `--pointer`,
+/// if given.
+fn initial_state(options: &Options) -> State {
+    let mut image = options.tape_init.clone().unwrap_or_default();
+    if let Some(pointer) = options.pointer {
+        image.pointer = pointer;
+    }
+
+    image.to_state(&options.config)
+}
+
+/// Opens the `--input` and `--output` files, defaulting to stdin and stdout.
+fn open_io(options: &Options) -> (Box<dyn Read>, Box<dyn Write>) {
+    let input: Box<dyn Read> = match options.input_file {
+        Some(ref path) => Box::new(File::open(path)
+            .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path)))),
+        None => Box::new(stdin()),
+    };
+
+    let output: Box<dyn Write> = match options.output_file {
+        Some(ref path) => Box::new(File::create(path)
+            .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path)))),
+        None => Box::new(stdout()),
+    };
+
+    (input, output)
+}
+
+fn emit(program: &ast::Program, format: Emit, options: &Options) {
+    let config = &options.config;
+
+    let code = match format {
+        Emit::C => program.c_compile(config).into_bytes(),
+        Emit::Rust => program.rust_compile(config).into_bytes(),
+        Emit::Wasm => program.wasm_compile(config).to_binary(),
+        Emit::Wat => program.wasm_compile(config).to_text().into_bytes(),
+        Emit::Bytecode => {
+            let mut bytes = Vec::new();
+            bf::bytecode::save(&program.bytecode_compile(config), &mut bytes)
+                .expect("writing to a Vec cannot fail");
+            bytes
+        }
+        #[cfg(feature = "llvm")]
+        Emit::Llvm(format) => program.llvm_emit(config, format)
+            .unwrap_or_else(|e| error_exit(1, &format!("LLVM error: {}", e))),
+        #[cfg(feature = "llvm")]
+        Emit::LlvmRuntime => bf::llvm::RUNTIME_SOURCE.as_bytes().to_vec(),
+    };
+
+    write_output(&code, options);
+}
+
+/// Writes compiled output to the `-o` file, or to stdout if none was given.
+fn write_output(bytes: &[u8], options: &Options) {
+    let result = match options.out_file {
+        Some(ref path) => File::create(path).and_then(|mut file| file.write_all(bytes)),
+        None => stdout().write_all(bytes),
+    };
+
+    result.unwrap_or_else(|e| error_exit(1, &format!("error: could not write output: {}.", e)));
 }
 
 #[cfg(feature = "jit")]
-const DEFAULT_PASS: Pass = Pass::Jit;
+fn compile_executable(program: &ast::Program, path: &str, options: &Options) {
+    let file = File::create(path)
+        .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path)));
 
-#[cfg(not(feature = "jit"))]
-const DEFAULT_PASS: Pass = Pass::Peephole;
+    program.aot_compile(&options.config, BufWriter::new(file))
+        .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path)));
+
+    #[cfg(unix)]
+    {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path)));
+    }
+}
 
 fn get_options() -> Options {
     let mut result = Options {
         program_text:  Vec::new(),
-        memory_size:   None,
-        compiler_pass: DEFAULT_PASS,
-        unchecked:     false,
+        config:        Config::new(),
+        backend:       Backend::default(),
+        buffering:     Buffering::default(),
+        compile_to:    None,
+        cache_dir:     None,
+        emit:          None,
+        out_file:      None,
+        checkpoint_every: None,
+        checkpoint:    None,
+        resume:        None,
+        tape_init:     None,
+        pointer:       None,
+        input_file:    None,
+        output_file:   None,
+        dump_tape:     DumpTape::Never,
+        repl:          false,
     };
 
     let matches = build_clap_app().get_matches();
@@ -131,27 +465,137 @@ fn get_options() -> Options {
         if size == 0 {
             error_exit(1, "error: memory size must be at least 1.");
         }
-        result.memory_size = Some(size);
+        result.config = result.config.memory_size(size);
+    }
+
+    if matches.value_of("tape") == Some("growable") {
+        result.config = result.config.tape_mode(TapeMode::Growable);
+    }
+
+    if let Some(bits) = matches.value_of("cell-width") {
+        let width = bits.parse().ok().and_then(CellWidth::from_bits)
+            .unwrap_or_else(|| error_exit(1, "error: cell width must be 8, 16, or 32."));
+        result.config = result.config.cell_width(width);
+    }
+
+    match matches.value_of("eof") {
+        Some("minus-one") => result.config = result.config.eof(EofMode::MinusOne),
+        Some("unchanged") => result.config = result.config.eof(EofMode::Unchanged),
+        _ => (),
+    }
+
+    if let Some(limit) = matches.value_of("step-limit") {
+        let limit = limit.parse()
+            .unwrap_or_else(|e|
+                error_exit(1, &format!("error: could not parse step limit: {}.", e)));
+        result.config = result.config.step_limit(limit);
     }
 
     if matches.is_present("jit") {
         #[cfg(feature = "jit")]
-        let _ = result.compiler_pass = Pass::Jit;
+        let _ = result.backend = Backend::Jit;
     } else if matches.is_present("llvm") {
         #[cfg(feature = "llvm")]
-        let _ = result.compiler_pass = Pass::Llvm;
+        let _ = result.backend = Backend::Llvm;
+    } else if matches.is_present("compact") {
+        result.backend = Backend::Compact;
+    } else if matches.is_present("threaded") {
+        result.backend = Backend::Threaded;
     } else if matches.is_present("byte") {
-        result.compiler_pass = Pass::Bytecode;
+        result.backend = Backend::Bytecode;
     } else if matches.is_present("peep") {
-        result.compiler_pass = Pass::Peephole;
+        result.backend = Backend::Peephole;
     } else if matches.is_present("rle") {
-        result.compiler_pass = Pass::Rle;
+        result.backend = Backend::Rle;
     } else if matches.is_present("ast") {
-        result.compiler_pass = Pass::Ast;
+        result.backend = Backend::Ast;
     }
 
     if matches.is_present("unchecked") {
-        result.unchecked = true;
+        result.config = result.config.checked(false);
+    }
+
+    if let Some(level) = matches.value_of("opt-level") {
+        let level = level.parse().expect("clap checks the possible values");
+        result.config = result.config.opt_level(level);
+    }
+
+    if let Some(names) = matches.values_of("disable-pass") {
+        for name in names {
+            let pass = Pass::from_name(name).expect("clap checks the possible values");
+            result.config = result.config.disable(pass);
+        }
+    }
+
+    if let Some(steps) = matches.value_of("checkpoint-every") {
+        let steps = steps.parse()
+            .unwrap_or_else(|e|
+                error_exit(1, &format!("error: could not parse checkpoint interval: {}.", e)));
+        if steps == 0 {
+            error_exit(1, "error: checkpoint interval must be at least 1.");
+        }
+        result.checkpoint_every = Some(steps);
+    }
+
+    result.checkpoint = matches.value_of("checkpoint").map(String::from);
+    result.resume = matches.value_of("resume").map(String::from);
+
+    if let Some(path) = matches.value_of("tape-init") {
+        let mut text = String::new();
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+            .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, path)));
+        let image = text.parse()
+            .unwrap_or_else(|e| error_exit(1, &format!("bad tape image: {}: ‘{}’.", e, path)));
+        result.tape_init = Some(image);
+    }
+
+    // Unless it comes from a tape image, memory starts out zero.
+    result.config = result.config.fresh_memory(result.tape_init.is_none());
+
+    if let Some(pointer) = matches.value_of("pointer") {
+        let pointer = pointer.parse()
+            .unwrap_or_else(|e| error_exit(1, &format!("error: could not parse pointer: {}.", e)));
+        result.pointer = Some(pointer);
+    }
+
+    let pointer = result.pointer
+        .unwrap_or_else(|| result.tape_init.as_ref().map_or(0, |image| image.pointer));
+    let tape_len = result.tape_init.as_ref().map_or(0, |image| image.cells.len());
+    if pointer >= result.config.memory_size.max(tape_len) {
+        error_exit(1, "error: pointer is past the end of memory.");
+    }
+
+    if matches.is_present("dump-tape") {
+        result.dump_tape = match matches.value_of("dump-tape") {
+            Some("always") => DumpTape::Always,
+            _ => DumpTape::OnError,
+        };
+    }
+
+    result.input_file = matches.value_of("input").map(String::from);
+    result.output_file = matches.value_of("output").map(String::from);
+
+    result.out_file = matches.value_of("out-file").map(String::from);
+
+    if matches.is_present("compile") {
+        result.compile_to = result.out_file.clone();
+    }
+
+    result.cache_dir = matches.value_of("cache-dir").map(String::from);
+
+    match matches.value_of("emit") {
+        Some("c") => result.emit = Some(Emit::C),
+        Some("rust") => result.emit = Some(Emit::Rust),
+        Some("wasm") => result.emit = Some(Emit::Wasm),
+        Some("wat") => result.emit = Some(Emit::Wat),
+        Some("bytecode") => result.emit = Some(Emit::Bytecode),
+        #[cfg(feature = "llvm")]
+        Some(format) => result.emit = llvm_emit_format(format),
+        _ => (),
+    }
+
+    if matches.is_present("interactive") {
+        result.buffering = Buffering::interactive();
     }
 
     if let Some(exprs) = matches.values_of("expr") {
@@ -165,13 +609,44 @@ fn get_options() -> Options {
             file.read_to_end(&mut result.program_text)
                 .unwrap_or_else(|e| error_exit(1, &format!("{}: ‘{}’.", e, f)));
         }
-    } else {
+    } else if matches.is_present("repl")
+        || !PROGRAM_OPTIONS.iter().any(|&name| matches.is_present(name)) {
+        if result.config.cell_width != CellWidth::Eight {
+            error_exit(1, "error: the REPL does not support --cell-width.");
+        }
+        result.repl = true;
+    } else if !emits_runtime(&result) {
         error_exit(1, "error: no program given.");
     }
 
     result
 }
 
+#[cfg(feature = "llvm")]
+fn llvm_emit_format(format: &str) -> Option<Emit> {
+    use bf::llvm::OutputFormat;
+
+    match format {
+        "ll" => Some(Emit::Llvm(OutputFormat::Ir)),
+        "bc" => Some(Emit::Llvm(OutputFormat::Bitcode)),
+        "asm" => Some(Emit::Llvm(OutputFormat::Assembly)),
+        "obj" => Some(Emit::Llvm(OutputFormat::Object)),
+        "runtime" => Some(Emit::LlvmRuntime),
+        _ => None,
+    }
+}
+
+/// The LLVM runtime doesn’t depend on the program, so it doesn’t need one.
+#[cfg(feature = "llvm")]
+fn emits_runtime(options: &Options) -> bool {
+    matches!(options.emit, Some(Emit::LlvmRuntime))
+}
+
+#[cfg(not(feature = "llvm"))]
+fn emits_runtime(_options: &Options) -> bool {
+    false
+}
+
 fn build_clap_app() -> App<'static, 'static> {
     let app = App::new("bfi")
         .version(crate_version!())
@@ -196,14 +671,126 @@ fn build_clap_app() -> App<'static, 'static> {
             .value_name("SIZE")
             .help("Memory size in bytes (default 30,000)")
             .takes_value(true))
+        .arg(Arg::with_name("tape")
+            .long("tape")
+            .value_name("MODE")
+            .help("Whether memory grows to the right (default fixed)")
+            .takes_value(true)
+            .possible_values(&["fixed", "growable"]))
+        .arg(Arg::with_name("cell-width")
+            .long("cell-width")
+            .value_name("BITS")
+            .help("Bits per cell (default 8); wider cells need --ast or --rle")
+            .takes_value(true)
+            .possible_values(&["8", "16", "32"])
+            .conflicts_with_all(&["emit", "dump-tape", "tape-init", "repl"]))
+        .arg(Arg::with_name("eof")
+            .long("eof")
+            .value_name("MODE")
+            .help("What `,` stores at end of input (default zero)")
+            .takes_value(true)
+            .possible_values(&["zero", "minus-one", "unchanged"]))
+        .arg(Arg::with_name("step-limit")
+            .long("step-limit")
+            .value_name("N")
+            .help("Stop with an error after N loop iterations")
+            .takes_value(true))
+        .arg(Arg::with_name("opt-level")
+            .short("O")
+            .value_name("LEVEL")
+            .help("Optimization level (default 3)")
+            .takes_value(true)
+            .possible_values(&["0", "1", "2", "3"]))
+        .arg(Arg::with_name("disable-pass")
+            .long("disable-pass")
+            .value_name("NAME")
+            .help("Skip an optimization pass")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .possible_values(&pass_names()))
+        .arg(Arg::with_name("tape-init")
+            .long("tape-init")
+            .value_name("FILE")
+            .help("Start with memory loaded from a tape image, such as `0 0 [5] 12 0`")
+            .takes_value(true)
+            .conflicts_with("emit"))
+        .arg(Arg::with_name("pointer")
+            .long("pointer")
+            .value_name("N")
+            .help("Start with the pointer at cell N")
+            .takes_value(true)
+            .conflicts_with("emit"))
+        .arg(Arg::with_name("input")
+            .long("input")
+            .value_name("FILE")
+            .help("Read the program’s input from FILE instead of stdin")
+            .takes_value(true)
+            .conflicts_with("emit"))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .value_name("FILE")
+            .help("Write the program’s output to FILE instead of stdout")
+            .takes_value(true)
+            .conflicts_with("emit"))
+        .arg(Arg::with_name("dump-tape")
+            .long("dump-tape")
+            .value_name("WHEN")
+            .help("Print the used part of memory to stderr when the program stops \
+                   (default on-error)")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .possible_values(&["on-error", "always"])
+            .conflicts_with("emit"))
+        .arg(Arg::with_name("checkpoint-every")
+            .long("checkpoint-every")
+            .value_name("N")
+            .help("Save a snapshot every N loop iterations (uses the bytecode interpreter)")
+            .takes_value(true)
+            .requires("checkpoint")
+            .conflicts_with("emit"))
+        .arg(Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .value_name("FILE")
+            .help("Where to save snapshots for --checkpoint-every")
+            .takes_value(true)
+            .requires("checkpoint-every"))
+        .arg(Arg::with_name("resume")
+            .long("resume")
+            .value_name("FILE")
+            .help("Continue from a snapshot saved by --checkpoint")
+            .takes_value(true)
+            .conflicts_with("emit"))
+        .arg(Arg::with_name("repl")
+            .long("repl")
+            .help("Run code a line at a time, keeping memory between lines \
+                   (the default with no program)")
+            .conflicts_with_all(&["FILE", "expr"])
+            .conflicts_with_all(PROGRAM_OPTIONS))
+        .arg(Arg::with_name("emit")
+            .long("emit")
+            .value_name("FORMAT")
+            .help("Print compiled code instead of running it")
+            .takes_value(true)
+            .possible_values(EMIT_FORMATS))
+        .arg(Arg::with_name("out-file")
+            .short("o")
+            .value_name("FILE")
+            .help("Where to write compiled output")
+            .takes_value(true))
+        .arg(Arg::with_name("interactive")
+            .short("i")
+            .long("interactive")
+            .help("Don’t buffer input or output"))
         .arg(Arg::with_name("ast")
             .long("ast")
             .help("Interpret the unoptimized AST")
-            .conflicts_with_all(&["rle", "peep", "byte", "jit", "llvm"]))
+            .conflicts_with_all(&["rle", "peep", "byte", "threaded", "compact", "jit", "llvm"]))
         .arg(Arg::with_name("rle")
             .long("rle")
             .help("Interpret the run-length encoded the AST")
-            .conflicts_with_all(&["ast", "peep", "byte", "jit", "llvm"]))
+            .conflicts_with_all(&["ast", "peep", "byte", "threaded", "compact", "jit", "llvm"]))
         .arg(Arg::with_name("peep")
             .long("peep")
             .help(
@@ -212,37 +799,68 @@ fn build_clap_app() -> App<'static, 'static> {
                 } else {
                     "Interpret the peephole-optimized AST (default)"
                 })
-            .conflicts_with_all(&["ast", "rle", "byte", "jit", "llvm"]))
+            .conflicts_with_all(&["ast", "rle", "byte", "threaded", "compact", "jit", "llvm"]))
         .arg(Arg::with_name("byte")
             .long("byte")
             .help("Compile AST to bytecode")
-            .conflicts_with_all(&["ast", "rle", "peep", "jit", "llvm"]));
+            .conflicts_with_all(&["ast", "rle", "peep", "threaded", "compact", "jit", "llvm"]))
+        .arg(Arg::with_name("threaded")
+            .long("threaded")
+            .help("Interpret threaded code compiled from bytecode")
+            .conflicts_with_all(&["ast", "rle", "peep", "byte", "compact", "jit", "llvm"]))
+        .arg(Arg::with_name("compact")
+            .long("compact")
+            .help("Compile AST to compact bytecode")
+            .conflicts_with_all(&["ast", "rle", "peep", "byte", "threaded", "jit", "llvm"]));
 
     #[cfg(feature = "llvm")]
     let app = app
         .arg(Arg::with_name("llvm")
             .long("llvm")
             .help("JIT using LLVM")
-            .conflicts_with_all(&["ast", "rle", "peep", "byte", "jit"]));
+            .conflicts_with_all(&["ast", "rle", "peep", "byte", "threaded", "compact", "jit"]));
 
     #[cfg(feature = "jit")]
     let app = app
         .arg(Arg::with_name("jit")
             .long("jit")
             .help("JIT to native x64 (default)")
-            .conflicts_with_all(&["ast", "rle", "peep", "byte", "llvm"]));
+            .conflicts_with_all(&["ast", "rle", "peep", "byte", "threaded", "compact", "llvm"]));
 
-    #[cfg(any(feature = "jit"))]
+    #[cfg(any(feature = "jit", feature = "llvm"))]
     let app = app
         .arg(Arg::with_name("unchecked")
             .short("u")
             .long("unchecked")
             .help("Omit memory bounds checks in JIT")
-            .conflicts_with_all(&["ast", "rle", "peep", "byte", "llvm"]));
+            .conflicts_with_all(&["ast", "rle", "peep", "byte", "threaded", "compact"]));
+
+    #[cfg(feature = "jit")]
+    let app = app
+        .arg(Arg::with_name("compile")
+            .long("compile")
+            .help("Compile to a standalone x64 Linux executable")
+            .requires("out-file")
+            .conflicts_with_all(&["ast", "rle", "peep", "byte", "threaded", "compact", "llvm",
+                                  "emit"]));
+
+    #[cfg(feature = "jit")]
+    let app = app
+        .arg(Arg::with_name("cache-dir")
+            .long("cache-dir")
+            .value_name("DIR")
+            .help("Reuse JIT-compiled code saved in this directory")
+            .takes_value(true)
+            .conflicts_with_all(&["ast", "rle", "peep", "byte", "threaded", "compact", "llvm",
+                                  "compile", "emit"]));
 
     app
 }
 
+fn pass_names() -> Vec<&'static str> {
+    config::PASSES.iter().map(|pass| pass.name()).collect()
+}
+
 fn error_exit(code: i32, msg: &str) -> ! {
     eprintln!("bfi: {}", msg);
     exit(code)
@@ -0,0 +1,404 @@
+//! Running bytecode over asynchronous I/O.
+//!
+//! With the `async` feature, bytecode programs can do their I/O through the
+//! [`futures-io`](https://docs.rs/futures-io) `AsyncRead` and `AsyncWrite` traits, which are
+//! implemented by (or adaptable from) the streams of every common async runtime. The future
+//! returned by [`run_async`](trait.AsyncRunnable.html#tymethod.run_async) uses a
+//! [resumable machine](../resumable/index.html): whenever the program needs input that hasn’t
+//! arrived, the future returns `Pending` instead of blocking the thread, so many sessions can
+//! share a few threads.
+//!
+//! Output is buffered until the program needs input or finishes. So that a long computation
+//! neither starves the other tasks on its thread nor piles up output in memory, the future also
+//! yields after every [`STEPS_PER_POLL`](constant.STEPS_PER_POLL.html) loop iterations, and once
+//! the buffered output passes
+//! [`DEFAULT_BUFFER_SIZE`](../../channel/constant.DEFAULT_BUFFER_SIZE.html) bytes, which it
+//! checks every [`STEPS_PER_SLICE`](constant.STEPS_PER_SLICE.html) loop iterations.
+//!
+//! ```ignore
+//! use bf::traits::{AsyncRunnable, BytecodeCompilable};
+//!
+//! let program = bf::ast::parse_program(b",[.,]")?.bytecode_compile(&Config::new());
+//! program.run_async(State::new(), reader, writer).await?;
+//! ```
+
+use std::future::Future;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use channel::{Channel, InputResult, DEFAULT_BUFFER_SIZE};
+use common::BfResult;
+use state::State;
+use super::Program;
+use super::resumable::{Machine, Status, Suspension};
+
+/// How many loop iterations the program may run in one poll before the future yields.
+pub const STEPS_PER_POLL: u64 = 1 << 16;
+
+/// How many loop iterations the program runs between checks on how much output is buffered.
+pub const STEPS_PER_SLICE: u64 = 1 << 10;
+
+/// Program forms that can be run over asynchronous I/O.
+pub trait AsyncRunnable {
+    /// Runs the program against the given state, reading from `input` and writing to `output`.
+    ///
+    /// The returned future completes when the program finishes or fails, after its output has
+    /// been written and flushed. Errors writing the output are ignored, as for
+    /// [`BufferedChannel`](../../channel/struct.BufferedChannel.html).
+    fn run_async<R, W>(&self, state: State, input: R, output: W) -> Run<'_, R, W>
+        where R: AsyncRead + Unpin,
+              W: AsyncWrite + Unpin;
+}
+
+impl AsyncRunnable for Program {
+    fn run_async<R, W>(&self, state: State, input: R, output: W) -> Run<'_, R, W>
+        where R: AsyncRead + Unpin,
+              W: AsyncWrite + Unpin
+    {
+        Run {
+            program: self,
+            machine: Machine::new(state),
+            input,
+            output,
+            input_buffer: vec![0; DEFAULT_BUFFER_SIZE].into_boxed_slice(),
+            input_start: 0,
+            input_end: 0,
+            input_done: false,
+            needs_input: false,
+            output_buffer: Vec::new(),
+            output_written: 0,
+            result: None,
+        }
+    }
+}
+
+/// The future returned by [`run_async`](trait.AsyncRunnable.html#tymethod.run_async).
+pub struct Run<'a, R, W> {
+    program: &'a Program,
+    machine: Machine,
+    input: R,
+    output: W,
+    input_buffer: Box<[u8]>,
+    input_start: usize,
+    input_end: usize,
+    /// Whether the input has reached its end (or failed).
+    input_done: bool,
+    /// Whether the program is waiting for more input than is buffered.
+    needs_input: bool,
+    output_buffer: Vec<u8>,
+    /// How much of the output buffer has been written.
+    output_written: usize,
+    /// The program’s result, once it has stopped, to return after flushing.
+    result: Option<BfResult<()>>,
+}
+
+impl<'a, R, W> Run<'a, R, W>
+    where R: AsyncRead + Unpin,
+          W: AsyncWrite + Unpin
+{
+    /// Writes buffered output, returning `Pending` if the writer isn’t ready.
+    fn poll_write_output(&mut self, cx: &mut Context) -> Poll<()> {
+        while self.output_written < self.output_buffer.len() {
+            let bytes = &self.output_buffer[self.output_written ..];
+            match Pin::new(&mut self.output).poll_write(cx, bytes) {
+                Poll::Ready(Ok(0)) => break,
+                Poll::Ready(Ok(count)) => self.output_written += count,
+                Poll::Ready(Err(ref e)) if e.kind() == ErrorKind::Interrupted => (),
+                Poll::Ready(Err(_)) => break,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        self.output_buffer.clear();
+        self.output_written = 0;
+        Poll::Ready(())
+    }
+
+    /// Refills the input buffer, returning `Pending` if no input is ready.
+    fn poll_read_input(&mut self, cx: &mut Context) -> Poll<()> {
+        loop {
+            match Pin::new(&mut self.input).poll_read(cx, &mut self.input_buffer) {
+                Poll::Ready(Ok(0)) => self.input_done = true,
+                Poll::Ready(Ok(count)) => {
+                    self.input_start = 0;
+                    self.input_end = count;
+                }
+                Poll::Ready(Err(ref e)) if e.kind() == ErrorKind::Interrupted => continue,
+                Poll::Ready(Err(_)) => self.input_done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+
+            return Poll::Ready(());
+        }
+    }
+
+    /// Runs the program for up to `STEPS_PER_SLICE` loop iterations, until it stops, or until
+    /// it needs more input than is buffered. Returns whether it used up its steps.
+    fn run_machine(&mut self) -> bool {
+        let mut channel = Buffers {
+            input: &self.input_buffer[self.input_start .. self.input_end],
+            input_done: self.input_done,
+            output: &mut self.output_buffer,
+        };
+
+        let status = self.machine.run_steps(self.program, &mut channel, STEPS_PER_SLICE);
+        self.input_start = self.input_end - channel.input.len();
+
+        match status {
+            Status::Finished => self.result = Some(Ok(())),
+            Status::Error(error) => self.result = Some(Err(error)),
+            Status::Suspended(Suspension::NeedInput) => self.needs_input = true,
+            Status::Suspended(Suspension::Paused) => return true,
+        }
+
+        false
+    }
+}
+
+impl<'a, R, W> Future for Run<'a, R, W>
+    where R: AsyncRead + Unpin,
+          W: AsyncWrite + Unpin
+{
+    type Output = BfResult<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut steps = 0;
+
+        loop {
+            // As with buffered channels, output is written before every read.
+            if this.poll_write_output(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            if let Some(result) = this.result {
+                return match Pin::new(&mut this.output).poll_flush(cx) {
+                    Poll::Ready(_) => Poll::Ready(result),
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+
+            if this.needs_input {
+                if this.poll_read_input(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.needs_input = false;
+            }
+
+            if this.run_machine() {
+                steps += STEPS_PER_SLICE;
+                if steps >= STEPS_PER_POLL || this.output_buffer.len() >= DEFAULT_BUFFER_SIZE {
+                    // The output, if any, gets written first thing when polled again.
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+/// The channel that the machine runs against: buffered input, and output to be written.
+struct Buffers<'b> {
+    input: &'b [u8],
+    input_done: bool,
+    output: &'b mut Vec<u8>,
+}
+
+impl<'b> Channel for Buffers<'b> {
+    fn read_byte(&mut self) -> InputResult {
+        match self.input.split_first() {
+            Some((&byte, rest)) => {
+                self.input = rest;
+                InputResult::Byte(byte)
+            }
+            None if self.input_done => InputResult::Eof,
+            None => InputResult::WouldBlock,
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+    }
+
+    fn flush(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    use common::Error;
+    use config::Config;
+    use test_helpers::*;
+    use traits::BytecodeCompilable;
+
+    #[test]
+    fn echo() {
+        assert_eq!(run(b",[.,]", b"hello"), (Ok(()), b"hello".to_vec()));
+    }
+
+    #[test]
+    fn factoring() {
+        assert_eq!(run(FACTOR_SRC, b"100\n"), (Ok(()), b"100: 2 2 5 5\n".to_vec()));
+    }
+
+    #[test]
+    fn output_is_written_before_errors() {
+        assert_eq!(run(b"+.<", b""), (Err(Error::PointerUnderflow), b"\x01".to_vec()));
+    }
+
+    #[test]
+    fn long_computations_yield() {
+        let program = ::ast::parse_program(b"-[>-[-]<-]>>-[>-[-]<-]").unwrap()
+            .bytecode_compile(&Config::new().opt_level(0));
+        let mut output = MaxWrite::default();
+        let polls = count_polls(program.run_async(State::new(), &b""[..], &mut output));
+        assert!(polls > 1);
+    }
+
+    #[test]
+    fn output_is_written_as_it_accumulates() {
+        let program = ::ast::parse_program(b"-[>-[<.>-]<-]").unwrap()
+            .bytecode_compile(&Config::new().opt_level(0));
+        let mut output = MaxWrite::default();
+        count_polls(program.run_async(State::new(), &b""[..], &mut output));
+        assert_eq!(output.total, 255 * 255);
+        assert!(output.max < DEFAULT_BUFFER_SIZE + STEPS_PER_SLICE as usize);
+    }
+
+    /// Runs a program to completion over a reader and writer that are only ready every other
+    /// time they’re polled, and that transfer one byte at a time.
+    fn run(src: &[u8], input: &[u8]) -> (BfResult<()>, Vec<u8>) {
+        let program = ::ast::parse_program(src).unwrap().bytecode_compile(&Config::new());
+        let mut output = Trickle { bytes: Vec::new(), ready: false };
+        let result = {
+            let future = program.run_async(State::new(),
+                                           Trickle { bytes: input.to_vec(), ready: false },
+                                           &mut output);
+            block_on(future)
+        };
+        (result, output.bytes)
+    }
+
+    /// Polls a future until it’s ready, assuming that it will be ready eventually.
+    fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(result) = Pin::new(&mut future).poll(&mut cx) {
+                return result;
+            }
+        }
+    }
+
+    /// Polls a future that completes successfully, returning how many polls it took.
+    fn count_polls<F: Future<Output = BfResult<()>> + Unpin>(mut future: F) -> usize {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut polls = 1;
+        while Pin::new(&mut future).poll(&mut cx).is_pending() {
+            polls += 1;
+        }
+        polls
+    }
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(::std::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        unsafe { Waker::from_raw(clone(::std::ptr::null())) }
+    }
+
+    /// Always ready, keeping track of the most written at once.
+    #[derive(Default)]
+    struct MaxWrite {
+        total: usize,
+        max: usize,
+    }
+
+    impl AsyncWrite for MaxWrite {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context, buf: &[u8])
+                      -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            this.total += buf.len();
+            this.max = this.max.max(buf.len());
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A byte at a time, every other poll.
+    struct Trickle {
+        bytes: Vec<u8>,
+        ready: bool,
+    }
+
+    impl Trickle {
+        fn poll_ready(&mut self, cx: &mut Context) -> Poll<()> {
+            self.ready = !self.ready;
+            if self.ready {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    impl AsyncRead for Trickle {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+                     -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            if this.poll_ready(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            if this.bytes.is_empty() {
+                Poll::Ready(Ok(0))
+            } else {
+                buf[0] = this.bytes.remove(0);
+                Poll::Ready(Ok(1))
+            }
+        }
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+                      -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            if this.poll_ready(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            this.bytes.push(buf[0]);
+            Poll::Ready(Ok(1))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
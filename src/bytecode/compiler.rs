@@ -2,16 +2,20 @@ use super::*;
 use peephole;
 
 use common::{Count, Instruction};
+use config::{Config, Pass};
 
 /// Program forms that can be compiled to bytecode.
 pub trait BytecodeCompilable {
     /// Compile the given program into the peephole AST to prepare for bytecode compilation.
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R;
 
     /// Compile the given program to bytecode.
-    fn bytecode_compile(&self) -> Box<Program> {
-        self.with_peephole(compile)
+    fn bytecode_compile(&self, config: &Config) -> Box<Program> {
+        let mut program = self.with_peephole(config, compile);
+        // Only constant output fusion relies on memory starting out zero.
+        program.fresh_memory = config.fresh_memory && config.enabled(Pass::ConstOutput);
+        program
     }
 }
 
@@ -24,12 +28,14 @@ pub fn compile(src: &[peephole::Statement]) -> Box<Program> {
 
 pub struct Compiler {
     instructions: Vec<Instruction>,
+    strings: Vec<Box<[u8]>>,
 }
 
 impl Compiler {
     pub fn new() -> Self {
         Compiler {
             instructions: Vec::new(),
+            strings: Vec::new(),
         }
     }
 
@@ -40,6 +46,12 @@ impl Compiler {
         for instruction in src {
             match *instruction {
                 Src::Instr(instruction) => self.issue(instruction),
+                Src::OutConst(ref bytes) if bytes.len() == 1 => self.issue(Obj::OutByte(bytes[0])),
+                Src::OutConst(ref bytes) => {
+                    let index = usize_to_count(self.strings.len());
+                    self.strings.push(bytes.clone());
+                    self.issue(Obj::OutString(index));
+                }
                 Src::Loop(ref body) => {
                     let begin_pc = self.instructions.len();
                     self.issue(Obj::JumpZero(0));
@@ -53,7 +65,8 @@ impl Compiler {
     }
 
     pub fn into_program(self) -> Box<Program> {
-        self.instructions.into_boxed_slice()
+        Box::new(Program::new(self.instructions.into_boxed_slice(),
+                              self.strings.into_boxed_slice()))
     }
 
     fn issue(&mut self, instruction: Instruction) {
@@ -69,7 +82,7 @@ pub fn usize_to_count(count: usize) -> Count {
 }
 
 impl BytecodeCompilable for peephole::Program {
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, _config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R
     {
         k(self)
@@ -77,9 +90,9 @@ impl BytecodeCompilable for peephole::Program {
 }
 
 impl<T: peephole::PeepholeCompilable + ?Sized> BytecodeCompilable for T {
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R
     {
-        k(&self.peephole_compile())
+        k(&self.peephole_compile(config))
     }
 }
@@ -0,0 +1,386 @@
+//! A versioned binary file format for bytecode.
+//!
+//! All integers are little-endian. A file starts with a header:
+//!
+//!   - the magic bytes `BFBC`;
+//!   - the format version, one byte;
+//!   - the width in bytes of the [`Count`](../../common/type.Count.html) operands, one byte;
+//!   - the width in bytes of a memory cell, one byte (always 1);
+//!   - option flags, one byte, where bit 1 means that the program was compiled assuming that
+//!     memory starts out zero (bit 0 is reserved and must be clear);
+//!   - the number of instructions, as a `u64`.
+//!
+//! Each instruction is an opcode byte followed by its operand, if any: a byte for `Add` and
+//! `OutByte`, and a count of the width given in the header for the others. The instructions are
+//! followed by the string table that `OutString` indexes: the number of strings as a `u64`, and
+//! then each string as its length in a `u64` followed by its bytes.
+//!
+//! Files may be loaded by builds with a different `Count` type, as long as every count fits.
+//! Loading checks that every jump targets its matching partner and that every string index is
+//! in the table, so that the interpreters can trust the program.
+
+use std::io::{self, Read, Write};
+use std::mem;
+
+use common::{invalid_data, Count, Instruction};
+use traits::IntoUsize;
+use super::Program;
+
+/// The magic bytes at the start of a bytecode file.
+pub const MAGIC: &[u8] = b"BFBC";
+
+/// The current format version.
+const VERSION: u8 = 2;
+
+/// The header flag indicating that the program assumes that memory starts out zero.
+const FRESH_MEMORY: u8 = 2;
+
+/// Does this look like a bytecode file?
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Writes a bytecode program to `output`.
+pub fn save<W: Write>(program: &Program, mut output: W) -> io::Result<()> {
+    use common::Instruction::*;
+
+    let mut buffer = Vec::with_capacity(16 + program.len() * 3);
+    buffer.extend_from_slice(MAGIC);
+    buffer.push(VERSION);
+    buffer.push(mem::size_of::<Count>() as u8);
+    buffer.push(1);
+    buffer.push(if program.assumes_fresh_memory() { FRESH_MEMORY } else { 0 });
+    buffer.extend_from_slice(&(program.len() as u64).to_le_bytes());
+
+    for &instruction in program.iter() {
+        let (opcode, operand) = match instruction {
+            Left(count)           => (opcode::LEFT, Operand::Count(count)),
+            Right(count)          => (opcode::RIGHT, Operand::Count(count)),
+            Add(amount)           => (opcode::ADD, Operand::Byte(amount)),
+            In                    => (opcode::IN, Operand::None),
+            Out                   => (opcode::OUT, Operand::None),
+            OutByte(byte)         => (opcode::OUT_BYTE, Operand::Byte(byte)),
+            OutString(index)      => (opcode::OUT_STRING, Operand::Count(index)),
+            JumpZero(address)     => (opcode::JUMP_ZERO, Operand::Count(address)),
+            JumpNotZero(address)  => (opcode::JUMP_NOT_ZERO, Operand::Count(address)),
+            SetZero               => (opcode::SET_ZERO, Operand::None),
+            OffsetAddRight(count) => (opcode::OFFSET_ADD_RIGHT, Operand::Count(count)),
+            OffsetAddLeft(count)  => (opcode::OFFSET_ADD_LEFT, Operand::Count(count)),
+            FindZeroRight(count)  => (opcode::FIND_ZERO_RIGHT, Operand::Count(count)),
+            FindZeroLeft(count)   => (opcode::FIND_ZERO_LEFT, Operand::Count(count)),
+        };
+
+        buffer.push(opcode);
+        match operand {
+            Operand::None => (),
+            Operand::Byte(byte) => buffer.push(byte),
+            Operand::Count(count) => {
+                let bytes = (count as u64).to_le_bytes();
+                buffer.extend_from_slice(&bytes[.. mem::size_of::<Count>()]);
+            }
+        }
+    }
+
+    buffer.extend_from_slice(&(program.strings().len() as u64).to_le_bytes());
+    for string in program.strings() {
+        buffer.extend_from_slice(&(string.len() as u64).to_le_bytes());
+        buffer.extend_from_slice(string);
+    }
+
+    output.write_all(&buffer)
+}
+
+/// Reads a bytecode program from `input`.
+///
+/// # Errors
+///
+/// Fails with `InvalidData` if the input is not a valid bytecode file for this build.
+pub fn load<R: Read>(mut input: R) -> io::Result<Box<Program>> {
+    use common::Instruction::*;
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let mut reader = Reader(&bytes);
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(invalid_data("not a bytecode file"));
+    }
+
+    let header = reader.take(4)?;
+    let (version, count_width, cell_width, flags) = (header[0], header[1], header[2], header[3]);
+
+    if version != VERSION {
+        return Err(invalid_data("unsupported bytecode version"));
+    }
+    if !(count_width == 2 || count_width == 4 || count_width == 8) {
+        return Err(invalid_data("unsupported count width"));
+    }
+    if cell_width != 1 {
+        return Err(invalid_data("unsupported cell width"));
+    }
+    if flags & !FRESH_MEMORY != 0 {
+        return Err(invalid_data("unknown bytecode options"));
+    }
+
+    let len = reader.u64()?;
+    // Every instruction takes at least a byte, which bounds the allocation.
+    if len > reader.0.len() as u64 {
+        return Err(invalid_data("truncated bytecode file"));
+    }
+    let len = len as usize;
+
+    let mut program = Vec::with_capacity(len);
+    for _ in 0 .. len {
+        let opcode = reader.take(1)?[0];
+        let instruction = match opcode {
+            opcode::LEFT             => Left(reader.count(count_width)?),
+            opcode::RIGHT            => Right(reader.count(count_width)?),
+            opcode::ADD              => Add(reader.take(1)?[0]),
+            opcode::IN               => In,
+            opcode::OUT              => Out,
+            opcode::OUT_BYTE         => OutByte(reader.take(1)?[0]),
+            opcode::OUT_STRING       => OutString(reader.count(count_width)?),
+            opcode::JUMP_ZERO        => JumpZero(reader.count(count_width)?),
+            opcode::JUMP_NOT_ZERO    => JumpNotZero(reader.count(count_width)?),
+            opcode::SET_ZERO         => SetZero,
+            opcode::OFFSET_ADD_RIGHT => OffsetAddRight(reader.count(count_width)?),
+            opcode::OFFSET_ADD_LEFT  => OffsetAddLeft(reader.count(count_width)?),
+            opcode::FIND_ZERO_RIGHT  => FindZeroRight(reader.count(count_width)?),
+            opcode::FIND_ZERO_LEFT   => FindZeroLeft(reader.count(count_width)?),
+            _ => return Err(invalid_data("unknown opcode")),
+        };
+        program.push(instruction);
+    }
+
+    let string_count = reader.u64()?;
+    // Every string takes at least the eight bytes of its length.
+    if string_count > reader.0.len() as u64 / 8 {
+        return Err(invalid_data("truncated bytecode file"));
+    }
+    let mut strings = Vec::with_capacity(string_count as usize);
+    for _ in 0 .. string_count {
+        let string_len = reader.u64()?;
+        if string_len > reader.0.len() as u64 {
+            return Err(invalid_data("truncated bytecode file"));
+        }
+        strings.push(Box::from(reader.take(string_len as usize)?));
+    }
+
+    if !reader.0.is_empty() {
+        return Err(invalid_data("trailing data after bytecode"));
+    }
+
+    check_jumps(&program)?;
+    check_strings(&program, strings.len())?;
+
+    let mut program = Program::new(program.into_boxed_slice(), strings.into_boxed_slice());
+    program.fresh_memory = flags & FRESH_MEMORY != 0;
+
+    Ok(Box::new(program))
+}
+
+/// Checks that every jump targets a jump of the opposite kind that targets it back.
+fn check_jumps(program: &[Instruction]) -> io::Result<()> {
+    use common::Instruction::*;
+
+    for (pc, &instruction) in program.iter().enumerate() {
+        let ok = match instruction {
+            JumpZero(address) => {
+                let address = address.into_usize();
+                address > pc && match program.get(address) {
+                    Some(&JumpNotZero(back)) => back.into_usize() == pc,
+                    _ => false,
+                }
+            }
+            JumpNotZero(address) => {
+                let address = address.into_usize();
+                address < pc && match program.get(address) {
+                    Some(&JumpZero(back)) => back.into_usize() == pc,
+                    _ => false,
+                }
+            }
+            _ => true,
+        };
+
+        if !ok {
+            return Err(invalid_data(&format!("bad jump target at instruction {}", pc)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that every `OutString` refers to one of the `string_count` strings in the table.
+fn check_strings(program: &[Instruction], string_count: usize) -> io::Result<()> {
+    for (pc, &instruction) in program.iter().enumerate() {
+        if let Instruction::OutString(index) = instruction {
+            if index.into_usize() >= string_count {
+                return Err(invalid_data(&format!("bad string index at instruction {}", pc)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// An instruction’s operand.
+enum Operand {
+    None,
+    Byte(u8),
+    Count(Count),
+}
+
+/// The opcodes of the file format.
+mod opcode {
+    pub const LEFT: u8 = 0;
+    pub const RIGHT: u8 = 1;
+    pub const ADD: u8 = 2;
+    pub const IN: u8 = 3;
+    pub const OUT: u8 = 4;
+    pub const OUT_BYTE: u8 = 5;
+    pub const JUMP_ZERO: u8 = 6;
+    pub const JUMP_NOT_ZERO: u8 = 7;
+    pub const SET_ZERO: u8 = 8;
+    pub const OFFSET_ADD_RIGHT: u8 = 9;
+    pub const OFFSET_ADD_LEFT: u8 = 10;
+    pub const FIND_ZERO_RIGHT: u8 = 11;
+    pub const FIND_ZERO_LEFT: u8 = 12;
+    pub const OUT_STRING: u8 = 13;
+}
+
+/// Reads from the front of a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.0.len() {
+            return Err(invalid_data("truncated bytecode file"));
+        }
+
+        let (result, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(result)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads a count of the given width, checking that it fits in `Count`.
+    fn count(&mut self, width: u8) -> io::Result<Count> {
+        let mut bytes = [0; 8];
+        bytes[.. width as usize].copy_from_slice(self.take(width as usize)?);
+        let value = u64::from_le_bytes(bytes);
+
+        let count = value as Count;
+        if count as u64 != value {
+            return Err(invalid_data("count too large for this build"));
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_helpers::*;
+    use common::Instruction;
+    use traits::BytecodeCompilable;
+    use config::Config;
+
+    #[test]
+    fn round_trip() {
+        let program = ::ast::parse_program(FACTOR_SRC).unwrap()
+            .bytecode_compile(&Config::new());
+
+        let mut bytes = Vec::new();
+        save(&program, &mut bytes).unwrap();
+        assert!(is_bytecode(&bytes));
+
+        assert_eq!(load(&bytes[..]).unwrap(), program);
+    }
+
+    #[test]
+    fn reserved_flag_is_rejected() {
+        let program = ::ast::parse_program(b"+[>,.<-]").unwrap()
+            .bytecode_compile(&Config::new());
+        let mut bytes = Vec::new();
+        save(&program, &mut bytes).unwrap();
+
+        bytes[7] |= 1;
+        assert!(load(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn bad_jumps_are_rejected() {
+        use common::Instruction::*;
+
+        assert_load_fails(&[JumpZero(1), Out, JumpNotZero(0)]);
+        assert_load_fails(&[JumpZero(1), JumpNotZero(1)]);
+        assert_load_fails(&[JumpNotZero(0)]);
+        assert_load_fails(&[JumpZero(5)]);
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let program = ::ast::parse_program(HELLO_WORLD_SRC).unwrap()
+            .bytecode_compile(&Config::new());
+        let mut bytes = Vec::new();
+        save(&program, &mut bytes).unwrap();
+        assert_load_rejects_corruption(&bytes, |bytes| load(bytes));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = VERSION + 1;
+        assert!(load(&wrong_version[..]).is_err());
+
+        let mut wrong_opcode = bytes;
+        wrong_opcode[16] = 0xFF;
+        assert!(load(&wrong_opcode[..]).is_err());
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let program = ::ast::parse_program(b"+++.+.>,.").unwrap()
+            .bytecode_compile(&Config::new().fresh_memory(true));
+        assert_eq!(program.strings(), &[Box::from(&[3, 4][..])]);
+
+        let mut bytes = Vec::new();
+        save(&program, &mut bytes).unwrap();
+        assert_eq!(load(&bytes[..]).unwrap(), program);
+    }
+
+    #[test]
+    fn fresh_memory_round_trips() {
+        for &fresh_memory in &[false, true] {
+            let program = ::ast::parse_program(b"+.+.").unwrap()
+                .bytecode_compile(&Config::new().fresh_memory(fresh_memory));
+            assert_eq!(program.assumes_fresh_memory(), fresh_memory);
+
+            let mut bytes = Vec::new();
+            save(&program, &mut bytes).unwrap();
+            assert_eq!(load(&bytes[..]).unwrap().assumes_fresh_memory(), fresh_memory);
+        }
+    }
+
+    #[test]
+    fn bad_string_indices_are_rejected() {
+        use common::Instruction::*;
+
+        let program = Program::new(Box::new([OutString(0)]), Box::new([Box::from(&b"hi"[..])]));
+        let mut bytes = Vec::new();
+        save(&program, &mut bytes).unwrap();
+        assert!(load(&bytes[..]).is_ok());
+
+        bytes[17] = 1;
+        assert!(load(&bytes[..]).is_err());
+    }
+
+    fn assert_load_fails(program: &[Instruction]) {
+        let mut bytes = Vec::new();
+        save(&Program::from(program), &mut bytes).unwrap();
+        assert!(load(&bytes[..]).is_err());
+    }
+}
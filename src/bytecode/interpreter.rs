@@ -1,44 +1,60 @@
-use std::io::{Read, Write};
-
+use channel::Channel;
 use state::State;
-use common::BfResult;
-use traits::Interpretable;
+use common::{BfResult, Error};
+use config::CellWidth;
+use traits::{Interpretable, IntoUsize};
 use super::*;
 
 impl Interpretable for Program {
-    fn interpret_state<R: Read, W: Write>(
-        &self, mut state: State, mut input: R, mut output: W) -> BfResult<()>
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, channel: &mut C)
+        -> BfResult<()>
     {
-        interpret(self, &mut state, &mut input, &mut output)
+        interpret(self, &mut 0, state, channel)
     }
 }
 
-fn interpret<R, W>(instructions: &Program, state: &mut State,
-                   input: &mut R, output: &mut W)
-                       -> BfResult<()>
-    where R: Read, W: Write
+/// Interprets starting at `*pc`.
+///
+/// If it fails, `*pc` is left at the instruction that failed, which is where to resume after a
+/// `WouldBlock`. Fails with `Unsupported` if the state’s cells are wider than 8 bits.
+pub fn interpret<C>(instructions: &Program, pc: &mut usize, state: &mut State, channel: &mut C)
+                    -> BfResult<()>
+    where C: Channel + ?Sized
 {
     use common::Instruction::*;
 
-    let mut pc = 0;
+    if state.cell_width() != CellWidth::Eight {
+        return Err(Error::Unsupported);
+    }
 
-    while pc < instructions.len() {
-        match instructions[pc] {
+    while *pc < instructions.len() {
+        match instructions[*pc] {
             Left(count) => state.left(count)?,
             Right(count) => state.right(count)?,
             Add(count) => state.up(count),
-            In => state.read(input),
-            Out => state.write(output),
+            In => state.read(channel)?,
+            Out => state.write(channel),
+
+            OutByte(byte) => {
+                channel.write_byte(byte);
+            }
+
+            OutString(index) => {
+                channel.write_bytes(instructions.string(index));
+            }
 
             JumpZero(address) => {
                 if state.load() == 0 {
-                    pc = address as usize;
+                    *pc = address.into_usize();
+                } else {
+                    state.step()?;
                 }
             }
 
             JumpNotZero(address) => {
                 if state.load() != 0 {
-                    pc = address as usize;
+                    state.step()?;
+                    *pc = address.into_usize();
                 }
             }
 
@@ -60,20 +76,12 @@ fn interpret<R, W>(instructions: &Program, state: &mut State,
                 }
             }
 
-            FindZeroRight(offset) => {
-                while state.load() != 0 {
-                    state.right(offset)?;
-                }
-            }
+            FindZeroRight(offset) => state.find_zero_right(offset)?,
 
-            FindZeroLeft(offset) => {
-                while state.load() != 0 {
-                    state.left(offset)?;
-                }
-            }
+            FindZeroLeft(offset) => state.find_zero_left(offset)?,
         }
 
-        pc += 1;
+        *pc += 1;
     }
 
     Ok(())
@@ -82,6 +90,7 @@ fn interpret<R, W>(instructions: &Program, state: &mut State,
 #[cfg(test)]
 mod tests {
     use test_helpers::*;
+    use config::Config;
 
     #[test]
     fn hello_world() {
@@ -99,7 +108,7 @@ mod tests {
     fn assert_parse_interpret(program: &[u8], input: &str, output: &str) {
         let program = ::ast::parse_program(program).unwrap();
         let program = ::rle::compile(&program);
-        let program = ::peephole::compile(&program);
+        let program = ::peephole::compile(&program, &Config::new());
         let program = ::bytecode::compile(&program);
         assert_interpret(&*program, input.as_bytes(), output.as_bytes());
     }
@@ -9,19 +9,100 @@
 //! address to possibly jump to as a parameter. This representation includes
 //! run-length encoding for some instructions, with moving and arithmetic
 //! commands taking the count as a parameter. It also includes the
-//! instructions produced by the peephole optimizer.
+//! instructions produced by the peephole optimizer. Runs of output known at compile time go in
+//! the program’s string table, so that each is written with a single call.
 //!
 //! Flattening is not necessary for interpretation, but it might
 //! perform better because of the cache. So far, it appears
 //! to perform worse than the peephole-optimized AST.
+//!
+//! Bytecode can be [saved to and loaded from files](format/index.html), so that a program need
+//! not be parsed and optimized on every run. In `bfi`, `--emit bytecode -o FILE.bfc` writes
+//! such a file, and `bfi FILE.bfc` runs it.
+//!
+//! The [resumable interpreter](resumable/index.html) runs bytecode until it needs input that
+//! isn’t available yet, and then returns so the caller can resume it later, and a running program
+//! can be saved as a [snapshot](snapshot/index.html) and picked up in another process. With the
+//! `async` feature, bytecode can also be [run over asynchronous I/O](asynchronous/index.html).
+
+use std::ops::Deref;
 
 use common;
+use traits::IntoUsize;
 
 mod compiler;
 mod interpreter;
+pub mod format;
+pub mod resumable;
+pub mod snapshot;
+#[cfg(feature = "async")]
+pub mod asynchronous;
 
 pub use self::compiler::{compile, BytecodeCompilable};
+pub use self::format::{save, load};
+
+/// A program is a bytecode sequence of instructions, along with the table of strings that its
+/// `OutString` instructions write.
+///
+/// A program dereferences to its sequence of instructions.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Program {
+    instructions: Box<[common::Instruction]>,
+    strings: Box<[Box<[u8]>]>,
+    fresh_memory: bool,
+}
+
+impl Program {
+    /// Creates a program from its instructions and string table.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an `OutString` instruction refers to a string that is not in the table.
+    pub fn new(instructions: Box<[common::Instruction]>, strings: Box<[Box<[u8]>]>) -> Self {
+        assert!(instructions.iter().all(|&instruction| match instruction {
+            common::Instruction::OutString(index) => index.into_usize() < strings.len(),
+            _ => true,
+        }), "string index out of range");
+
+        Program { instructions, strings, fresh_memory: false }
+    }
+
+    /// The program’s instructions.
+    pub fn instructions(&self) -> &[common::Instruction] {
+        &self.instructions
+    }
+
+    /// The program’s string table.
+    pub fn strings(&self) -> &[Box<[u8]>] {
+        &self.strings
+    }
+
+    /// Was the program compiled assuming that memory starts out zero?
+    ///
+    /// If so, it may write the wrong output when run on memory that was prepared beforehand; see
+    /// [`Config::fresh_memory`](../config/struct.Config.html#structfield.fresh_memory).
+    pub fn assumes_fresh_memory(&self) -> bool {
+        self.fresh_memory
+    }
+
+    /// The string that `OutString(index)` writes.
+    pub fn string(&self, index: common::Count) -> &[u8] {
+        &self.strings[index.into_usize()]
+    }
+}
+
+impl Deref for Program {
+    type Target = [common::Instruction];
+
+    fn deref(&self) -> &Self::Target {
+        &self.instructions
+    }
+}
 
-/// A program is a bytecode sequence of instructions.
-pub type Program = [common::Instruction];
+impl<'a> From<&'a [common::Instruction]> for Program {
+    /// A program with an empty string table.
+    fn from(instructions: &'a [common::Instruction]) -> Self {
+        Program::new(instructions.into(), Box::new([]))
+    }
+}
 
@@ -0,0 +1,302 @@
+//! A bytecode interpreter that can stop when it needs input and pick up again later.
+//!
+//! A [`Machine`](struct.Machine.html) holds everything about a running program except the
+//! program itself: the program counter and the [`State`](../../state/struct.State.html). Running
+//! it returns [`Suspended(NeedInput)`](enum.Status.html#variant.Suspended) when the program asks
+//! for input that isn’t available yet, leaving the machine at the `,` so that it can be resumed
+//! once there is more. Since a machine is just data, a service can keep one per session instead
+//! of a blocked thread, and can [write it out](struct.Machine.html#method.write_to) so that
+//! sessions survive a restart.
+//!
+//! ```
+//! use bf::bytecode::resumable::{Machine, Status, Suspension};
+//! use bf::config::Config;
+//! use bf::state::State;
+//! use bf::traits::BytecodeCompilable;
+//!
+//! let config = Config::new();
+//! let program = bf::ast::parse_program(b",[+.,]").unwrap().bytecode_compile(&config);
+//! let mut machine = Machine::new(State::with_config(&config));
+//! let mut output = Vec::new();
+//!
+//! let status = machine.run_with_input(&program, b"HAL", &mut output);
+//! assert_eq!(status, Status::Suspended(Suspension::NeedInput));
+//! assert_eq!(output, b"IBM");
+//!
+//! output.clear();
+//! let status = machine.run_with_input(&program, b"\0", &mut output);
+//! assert_eq!(status, Status::Finished);
+//! ```
+
+use std::io::{self, Read, Write};
+
+use channel::{Channel, InputResult, Io, IoChannel};
+use common::{invalid_data, BfResult, Error};
+use state::State;
+use super::Program;
+use super::interpreter::interpret;
+
+/// The magic bytes at the start of a saved machine.
+const MAGIC: &[u8] = b"BFRM";
+
+/// The current format version for saved machines.
+const VERSION: u8 = 1;
+
+/// The result of running a machine.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// The program ran to the end.
+    Finished,
+    /// The program stopped, and can be resumed.
+    Suspended(Suspension),
+    /// The program failed.
+    Error(Error),
+}
+
+/// Why a program stopped before finishing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Suspension {
+    /// The program is at a `,`, and no input is available.
+    NeedInput,
+    /// The program ran for as many steps as [`run_steps`](struct.Machine.html#method.run_steps)
+    /// allowed.
+    Paused,
+}
+
+/// A bytecode program’s execution state, which can be run, suspended, and resumed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Machine {
+    pc: usize,
+    state: State,
+}
+
+impl Machine {
+    /// Creates a machine that will run a program from the beginning against the given state.
+    pub fn new(state: State) -> Self {
+        Machine { pc: 0, state }
+    }
+
+    /// The index of the next instruction to run.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The machine state.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Unwraps the machine state.
+    pub fn into_state(self) -> State {
+        self.state
+    }
+
+    /// Runs `program` from where the machine left off, doing I/O through the given channel.
+    ///
+    /// The machine must always be run with the same program. It suspends when the channel
+    /// reports `WouldBlock`. After it finishes or fails, running it again does nothing more.
+    pub fn run<C: Channel + ?Sized>(&mut self, program: &Program, channel: &mut C) -> Status {
+        let result = interpret(program, &mut self.pc, &mut self.state, channel);
+        self.status(program, result)
+    }
+
+    /// Like [`run`](#method.run), but pauses after at most `steps` loop iterations.
+    ///
+    /// The machine returns `Suspended(Paused)` when it pauses, and can be resumed like any
+    /// suspended machine. Steps count against the state’s own step limit too, and running out of
+    /// that is still an error.
+    pub fn run_steps<C: Channel + ?Sized>(&mut self, program: &Program, channel: &mut C,
+                                          steps: u64) -> Status {
+        let limit = self.state.steps_remaining();
+        let budget = limit.map_or(steps, |limit| limit.min(steps));
+
+        self.state.set_steps_remaining(Some(budget));
+        let result = interpret(program, &mut self.pc, &mut self.state, channel);
+        let used = budget - self.state.steps_remaining().unwrap_or(0);
+        self.state.set_steps_remaining(limit.map(|limit| limit - used));
+
+        match result {
+            Err(Error::StepLimitExceeded) if limit.map_or(true, |limit| used < limit) =>
+                Status::Suspended(Suspension::Paused),
+            _ => self.status(program, result),
+        }
+    }
+
+    /// Runs `program` with the given input, appending its output to `output`.
+    ///
+    /// The machine suspends only once it has used up all of `input`, so a session can feed its
+    /// input to the program as it arrives. The input never ends; to signal the end of the
+    /// input, use [`run`](#method.run) with a channel that returns `InputResult::Eof`.
+    pub fn run_with_input(&mut self, program: &Program, input: &[u8], output: &mut Vec<u8>)
+                          -> Status {
+        self.run(program, &mut IoChannel::new(Pending { input, output }))
+    }
+
+    fn status(&mut self, program: &Program, result: BfResult<()>) -> Status {
+        match result {
+            Ok(()) => Status::Finished,
+            Err(Error::WouldBlock) => Status::Suspended(Suspension::NeedInput),
+            Err(error) => {
+                // Don’t run the rest of the program if resumed.
+                self.pc = program.len();
+                Status::Error(error)
+            }
+        }
+    }
+
+    /// Writes the machine to `output`, so that [`read_from`](#method.read_from) can restore
+    /// it.
+    ///
+    /// The format is the magic bytes `BFRM`, a format version byte, the program counter as a
+    /// little-endian `u64`, and then the state as written by
+    /// [`State::write_to`](../../state/struct.State.html#method.write_to). The program is not
+    /// included.
+    pub fn write_to<W: Write>(&self, mut output: W) -> io::Result<()> {
+        output.write_all(MAGIC)?;
+        output.write_all(&[VERSION])?;
+        output.write_all(&(self.pc as u64).to_le_bytes())?;
+        self.state.write_to(output)
+    }
+
+    /// Reads a machine written by [`write_to`](#method.write_to).
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidData` if the input is not a saved machine for this version.
+    pub fn read_from<R: Read>(mut input: R) -> io::Result<Machine> {
+        let mut header = [0; 13];
+        input.read_exact(&mut header)?;
+
+        if &header[.. 4] != MAGIC || header[4] != VERSION {
+            return Err(invalid_data("not a saved machine for this version"));
+        }
+
+        let mut pc = [0; 8];
+        pc.copy_from_slice(&header[5 ..]);
+        let pc = u64::from_le_bytes(pc);
+        if pc > usize::MAX as u64 {
+            return Err(invalid_data("program counter too large"));
+        }
+
+        Ok(Machine {
+            pc: pc as usize,
+            state: State::read_from(input)?,
+        })
+    }
+}
+
+/// Input that would block when it runs out, and output collected in a vector.
+struct Pending<'a> {
+    input: &'a [u8],
+    output: &'a mut Vec<u8>,
+}
+
+impl<'a> Io for Pending<'a> {
+    fn input(&mut self) -> InputResult {
+        match self.input.split_first() {
+            Some((&byte, rest)) => {
+                self.input = rest;
+                InputResult::Byte(byte)
+            }
+            None => InputResult::WouldBlock,
+        }
+    }
+
+    fn output(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use traits::BytecodeCompilable;
+
+    #[test]
+    fn suspends_until_input_arrives() {
+        let program = compile(b",[.,]");
+        let mut machine = Machine::new(State::new());
+        let mut output = Vec::new();
+
+        assert_eq!(machine.run_with_input(&program, b"", &mut output),
+                   Status::Suspended(Suspension::NeedInput));
+        assert_eq!(machine.pc(), 0);
+
+        for chunk in &[&b"ab"[..], b"", b"c"] {
+            assert_eq!(machine.run_with_input(&program, chunk, &mut output),
+                       Status::Suspended(Suspension::NeedInput));
+        }
+        assert_eq!(output, b"abc");
+
+        assert_eq!(machine.run_with_input(&program, b"d\0e", &mut output), Status::Finished);
+        assert_eq!(output, b"abcd");
+    }
+
+    #[test]
+    fn errors_end_the_run() {
+        let program = compile(b",<,");
+        let mut machine = Machine::new(State::new());
+        let mut output = Vec::new();
+
+        assert_eq!(machine.run_with_input(&program, b"x", &mut output),
+                   Status::Error(Error::PointerUnderflow));
+        assert_eq!(machine.run_with_input(&program, b"x", &mut output), Status::Finished);
+    }
+
+    #[test]
+    fn resumes_after_round_trip() {
+        let program = compile(::test_helpers::FACTOR_SRC);
+        let mut machine = Machine::new(State::with_config(&Config::new().step_limit(1 << 20)));
+        let mut output = Vec::new();
+
+        assert_eq!(machine.run_with_input(&program, b"10", &mut output),
+                   Status::Suspended(Suspension::NeedInput));
+
+        let mut bytes = Vec::new();
+        machine.write_to(&mut bytes).unwrap();
+        let mut restored = Machine::read_from(&bytes[..]).unwrap();
+        assert_eq!(restored, machine);
+
+        assert_eq!(restored.run_with_input(&program, b"0\n", &mut output), Status::Finished);
+        assert_eq!(output, b"100: 2 2 5 5\n");
+    }
+
+    #[test]
+    fn pauses_and_resumes() {
+        let program = compile(b"+++++[-.]");
+        let mut machine = Machine::new(State::with_config(&Config::new().step_limit(5)));
+        let mut output = Vec::new();
+        let mut channel = IoChannel::new(Pending { input: b"", output: &mut output });
+
+        assert_eq!(machine.run_steps(&program, &mut channel, 3),
+                   Status::Suspended(Suspension::Paused));
+        assert_eq!(machine.state().steps_remaining(), Some(2));
+        assert_eq!(machine.run_steps(&program, &mut channel, 3), Status::Finished);
+        assert_eq!(machine.state().steps_remaining(), Some(0));
+        assert_eq!(output, b"\x04\x03\x02\x01\x00");
+    }
+
+    #[test]
+    fn pausing_respects_the_step_limit() {
+        let program = compile(b"+[]");
+        let mut machine = Machine::new(State::with_config(&Config::new().step_limit(4)));
+        let mut output = Vec::new();
+        let mut channel = IoChannel::new(Pending { input: b"", output: &mut output });
+
+        assert_eq!(machine.run_steps(&program, &mut channel, 3),
+                   Status::Suspended(Suspension::Paused));
+        assert_eq!(machine.run_steps(&program, &mut channel, 3),
+                   Status::Error(Error::StepLimitExceeded));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(Machine::read_from(&b"BFRM"[..]).is_err());
+        assert!(Machine::read_from(&b"not a saved machine"[..]).is_err());
+    }
+
+    fn compile(src: &[u8]) -> Box<Program> {
+        ::ast::parse_program(src).unwrap().bytecode_compile(&Config::new())
+    }
+}
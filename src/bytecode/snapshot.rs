@@ -0,0 +1,132 @@
+//! Snapshots of running programs, for checkpointing and resuming long runs.
+//!
+//! A snapshot is a [saved machine](../resumable/struct.Machine.html#method.write_to) together
+//! with a hash of the program it was running, so that it is not resumed against a different
+//! program by mistake. The program itself is not included; it has to be supplied again when the
+//! snapshot is loaded. Neither is any I/O, so input that the program consumed before the
+//! snapshot is not replayed.
+//!
+//! The file format, with integers in little-endian order, is the magic bytes `BFSS`, a format
+//! version byte, the program hash as a `u64`, and then the machine.
+//!
+//! ```
+//! use bf::bytecode::resumable::Machine;
+//! use bf::bytecode::snapshot;
+//! use bf::config::Config;
+//! use bf::state::State;
+//! use bf::traits::BytecodeCompilable;
+//!
+//! let program = bf::ast::parse_program(b",[.,]").unwrap().bytecode_compile(&Config::new());
+//! let mut bytes = Vec::new();
+//! snapshot::save(&Machine::new(State::new()), &program, &mut bytes).unwrap();
+//!
+//! let machine = snapshot::load(&program, &bytes[..]).unwrap();
+//! assert_eq!(machine.pc(), 0);
+//! ```
+
+use std::io::{self, Read, Write};
+
+use common::{fnv1a, invalid_data};
+use super::Program;
+use super::format;
+use super::resumable::Machine;
+
+/// The magic bytes at the start of a snapshot.
+const MAGIC: &[u8] = b"BFSS";
+
+/// The current snapshot format version.
+const VERSION: u8 = 1;
+
+/// Writes a snapshot of `machine`, which is running `program`, to `output`.
+pub fn save<W: Write>(machine: &Machine, program: &Program, mut output: W) -> io::Result<()> {
+    output.write_all(MAGIC)?;
+    output.write_all(&[VERSION])?;
+    output.write_all(&program_hash(program).to_le_bytes())?;
+    machine.write_to(output)
+}
+
+/// Reads a snapshot written by [`save`](fn.save.html), checking that it was taken from
+/// `program`.
+///
+/// # Errors
+///
+/// Fails with `InvalidData` if the input is not a snapshot for this version, or if it was taken
+/// from a different program.
+pub fn load<R: Read>(program: &Program, mut input: R) -> io::Result<Machine> {
+    let mut header = [0; 13];
+    input.read_exact(&mut header)?;
+
+    if &header[.. 4] != MAGIC || header[4] != VERSION {
+        return Err(invalid_data("not a snapshot for this version"));
+    }
+
+    let mut hash = [0; 8];
+    hash.copy_from_slice(&header[5 ..]);
+    if u64::from_le_bytes(hash) != program_hash(program) {
+        return Err(invalid_data("snapshot was taken from a different program"));
+    }
+
+    let machine = Machine::read_from(input)?;
+    if machine.pc() > program.len() {
+        return Err(invalid_data("program counter out of range"));
+    }
+
+    Ok(machine)
+}
+
+/// A hash of the program, as written in the [bytecode format](../format/index.html).
+pub fn program_hash(program: &Program) -> u64 {
+    let mut bytes = Vec::new();
+    format::save(program, &mut bytes).expect("writing to a vector cannot fail");
+    fnv1a(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use state::State;
+    use test_helpers::*;
+    use traits::BytecodeCompilable;
+
+    #[test]
+    fn rejects_other_programs() {
+        let mut bytes = Vec::new();
+        save(&Machine::new(State::new()), &compile(b"+[-]"), &mut bytes).unwrap();
+
+        assert!(load(&compile(b"+[-]"), &bytes[..]).is_ok());
+        let error = load(&compile(b"-[+]"), &bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut wrong_hash = bytes;
+        wrong_hash[5] ^= 1;
+        assert!(load(&compile(b"+[-]"), &wrong_hash[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_program_counters_past_the_end() {
+        let program = compile(b"+[-]");
+        let mut bytes = Vec::new();
+        save(&Machine::new(State::new()), &program, &mut bytes).unwrap();
+
+        // The machine’s program counter follows its own magic bytes and version.
+        let pc_offset = 13 + 5;
+        for &(pc, ok) in &[(program.len(), true), (program.len() + 1, false)] {
+            bytes[pc_offset .. pc_offset + 8].copy_from_slice(&(pc as u64).to_le_bytes());
+            assert_eq!(load(&program, &bytes[..]).is_ok(), ok, "pc {}", pc);
+        }
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let program = compile(b"+");
+        let mut bytes = Vec::new();
+        save(&Machine::new(State::new()), &program, &mut bytes).unwrap();
+
+        assert_load_rejects_corruption(&bytes, |bytes| load(&program, bytes));
+    }
+
+    fn compile(src: &[u8]) -> Box<Program> {
+        ::ast::parse_program(src).unwrap().bytecode_compile(&Config::new())
+    }
+}
@@ -0,0 +1,331 @@
+//! Compiles peephole-optimized AST to C source code.
+//!
+//! The generated program is portable C99: memory is a static array of `unsigned char`, the
+//! pointer is an index into it, and I/O goes through `getchar` and `putchar`. As with the
+//! interpreters, reading at the end of the input follows the configuration’s
+//! [EOF mode](../config/enum.EofMode.html). In checked mode, a move past either
+//! end of memory flushes the output and exits with status
+//! [`rts::UNDERFLOW`](../rts/constant.UNDERFLOW.html) or
+//! [`rts::OVERFLOW`](../rts/constant.OVERFLOW.html), so the exit codes match those of
+//! [standalone executables](../jit/fn.write_executable.html).
+//!
+//! In `bfi`, pass `--emit c` to print the C code, or add `-o FILE` to write it to a file.
+
+use std::fmt::Write;
+
+use common::Count;
+use config::{Config, EofMode};
+use peephole::{self, Statement};
+use rts;
+
+/// Program forms that can be compiled to C.
+pub trait CCompilable {
+    /// Compile the given program into the peephole AST to prepare for C code generation.
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R;
+
+    /// Compile the given program to C, with the configuration’s amount of memory.
+    fn c_compile(&self, config: &Config) -> String {
+        // The generated program allocates its own memory, which starts out zero.
+        let config = &config.fresh_memory(true);
+        self.with_peephole(config, |ast| compile(ast, config))
+    }
+}
+
+/// Compiles peephole-optimized AST to a C program with the configuration’s amount of memory.
+pub fn compile(program: &peephole::Program, config: &Config) -> String {
+    let mut compiler = Compiler::new(config);
+    compiler.emit_prologue(config.memory_size);
+    compiler.compile(program);
+    compiler.emit_epilogue();
+    compiler.code
+}
+
+/// The compiler state.
+struct Compiler {
+    /// The C code so far.
+    code: String,
+    /// Whether we are emitting bounds checks.
+    checked: bool,
+    /// What to store at the end of the input.
+    eof: EofMode,
+    /// The current indentation level.
+    depth: usize,
+}
+
+impl Compiler {
+    fn new(config: &Config) -> Self {
+        Compiler {
+            code: String::new(),
+            checked: config.checked,
+            eof: config.eof,
+            depth: 1,
+        }
+    }
+
+    fn emit_prologue(&mut self, memory_size: usize) {
+        self.code.push_str("#include <stdio.h>\n#include <stdlib.h>\n\n");
+        let _ = writeln!(self.code, "#define MEMORY_SIZE {}", memory_size);
+        self.code.push_str("\nstatic unsigned char memory[MEMORY_SIZE];\n\n");
+
+        if self.checked {
+            let _ = writeln!(self.code,
+                             "static void fail(int status)\n\
+                              {{\n    fflush(stdout);\n    exit(status);\n}}\n\n\
+                              #define UNDERFLOW() fail({})\n\
+                              #define OVERFLOW() fail({})\n",
+                             rts::UNDERFLOW, rts::OVERFLOW);
+        }
+
+        self.code.push_str("int main(void)\n{\n    size_t p = 0;\n    int c;\n\n");
+    }
+
+    fn emit_epilogue(&mut self) {
+        self.code.push_str("\n    (void) c;\n    fflush(stdout);\n    return 0;\n}\n");
+    }
+
+    fn compile(&mut self, program: &[Statement]) {
+        for statement in program {
+            self.compile_statement(statement);
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) {
+        use common::Instruction::*;
+
+        match *statement {
+            Statement::Instr(Right(count)) => {
+                self.check_right(count);
+                self.line(&format!("p += {};", count));
+            }
+
+            Statement::Instr(Left(count)) => {
+                self.check_left(count);
+                self.line(&format!("p -= {};", count));
+            }
+
+            Statement::Instr(Add(amount)) =>
+                self.line(&format!("memory[p] += {};", amount)),
+
+            Statement::Instr(In) => {
+                self.line("c = getchar();");
+                match self.eof {
+                    EofMode::Zero =>
+                        self.line("memory[p] = c == EOF ? 0 : (unsigned char) c;"),
+                    EofMode::MinusOne =>
+                        self.line("memory[p] = c == EOF ? 255 : (unsigned char) c;"),
+                    EofMode::Unchanged =>
+                        self.line("if (c != EOF) memory[p] = (unsigned char) c;"),
+                }
+            }
+
+            Statement::Instr(Out) =>
+                self.line("putchar(memory[p]);"),
+
+            Statement::Instr(OutByte(byte)) =>
+                self.line(&format!("putchar({});", byte)),
+
+            Statement::OutConst(ref bytes) =>
+                self.line(&format!("fwrite(\"{}\", 1, {}, stdout);",
+                                   escape(bytes), bytes.len())),
+
+            Statement::Instr(SetZero) =>
+                self.line("memory[p] = 0;"),
+
+            Statement::Instr(OffsetAddRight(offset)) => {
+                self.open("if (memory[p]) {");
+                self.check_right(offset);
+                self.line(&format!("memory[p + {}] += memory[p];", offset));
+                self.line("memory[p] = 0;");
+                self.close();
+            }
+
+            Statement::Instr(OffsetAddLeft(offset)) => {
+                self.open("if (memory[p]) {");
+                self.check_left(offset);
+                self.line(&format!("memory[p - {}] += memory[p];", offset));
+                self.line("memory[p] = 0;");
+                self.close();
+            }
+
+            Statement::Instr(FindZeroRight(skip)) => {
+                self.open("while (memory[p]) {");
+                self.check_right(skip);
+                self.line(&format!("p += {};", skip));
+                self.close();
+            }
+
+            Statement::Instr(FindZeroLeft(skip)) => {
+                self.open("while (memory[p]) {");
+                self.check_left(skip);
+                self.line(&format!("p -= {};", skip));
+                self.close();
+            }
+
+            Statement::Instr(JumpZero(_)) | Statement::Instr(JumpNotZero(_)) =>
+                panic!("unexpected jump instruction"),
+            Statement::Instr(OutString(_)) =>
+                panic!("unexpected string instruction"),
+
+            Statement::Loop(ref body) => {
+                self.open("while (memory[p]) {");
+                self.compile(body);
+                self.close();
+            }
+        }
+    }
+
+    fn check_right(&mut self, count: Count) {
+        if self.checked {
+            self.line(&format!("if (MEMORY_SIZE - p <= {}) OVERFLOW();", count));
+        }
+    }
+
+    fn check_left(&mut self, count: Count) {
+        if self.checked {
+            self.line(&format!("if (p < {}) UNDERFLOW();", count));
+        }
+    }
+
+    fn open(&mut self, line: &str) {
+        self.line(line);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("}");
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0 .. self.depth {
+            self.code.push_str("    ");
+        }
+        self.code.push_str(line);
+        self.code.push('\n');
+    }
+}
+
+/// Escapes bytes for a C string literal.
+fn escape(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                result.push('\\');
+                result.push(byte as char);
+            }
+            // Avoid trigraphs by escaping every question mark.
+            b'?' => result.push_str("\\?"),
+            b' ' ..= b'~' => result.push(byte as char),
+            // Octal escapes are at most three digits long, so they can't absorb what follows.
+            _ => {
+                let _ = write!(result, "\\{:03o}", byte);
+            }
+        }
+    }
+
+    result
+}
+
+impl CCompilable for peephole::Program {
+    fn with_peephole<F, R>(&self, _config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R
+    {
+        k(self)
+    }
+}
+
+impl<T: peephole::PeepholeCompilable + ?Sized> CCompilable for T {
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R
+    {
+        k(&self.peephole_compile(config))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use test_helpers::*;
+    use common::{BfResult, Error};
+    use traits::{BytecodeCompilable, Interpretable};
+
+    use std::fs;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape(b"a\"b\\c?\n\x7F1"), "a\\\"b\\\\c\\?\\012\\1771");
+    }
+
+    #[test]
+    fn hello_world() {
+        assert_compile_run("hello", HELLO_WORLD_SRC, "");
+    }
+
+    #[test]
+    fn factoring() {
+        assert_compile_run("factor", FACTOR_SRC, "100\n");
+    }
+
+    #[test]
+    fn eof_modes() {
+        for &eof in &[EofMode::Zero, EofMode::MinusOne, EofMode::Unchanged] {
+            assert_compile_run_config("eof", b"+,.", "", &Config::new().eof(eof));
+        }
+    }
+
+    #[test]
+    fn pointer_errors() {
+        assert_compile_run("underflow", b"<", "");
+        assert_compile_run("overflow", b"+[>+]", "");
+        assert_compile_run("offset", b"+[-<+>]", "");
+    }
+
+    /// Compiles the program to C with the system C compiler and checks that it behaves like the
+    /// bytecode interpreter.
+    fn assert_compile_run(name: &str, program: &[u8], input: &str) {
+        assert_compile_run_config(name, program, input, &Config::new());
+    }
+
+    fn assert_compile_run_config(name: &str, program: &[u8], input: &str, config: &Config) {
+        let program = ::ast::parse_program(program).unwrap();
+
+        let expected = program.bytecode_compile(config).interpret_memory(config, input.as_bytes());
+        let actual = compile_run(name, &program.c_compile(config), input);
+
+        assert_eq!(actual, expected);
+    }
+
+    fn compile_run(name: &str, code: &str, input: &str) -> BfResult<Vec<u8>> {
+        let dir = ::std::env::temp_dir()
+            .join(format!("bf-c-test-{}-{}", ::std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("program.c");
+        let executable = dir.join("program");
+
+        fs::write(&source, code).unwrap();
+        let status = Command::new("cc")
+            .arg("-std=c99").arg("-o").arg(&executable).arg(&source)
+            .status().unwrap();
+        assert!(status.success(), "cc failed on:\n{}", code);
+
+        let mut child = Command::new(&executable)
+            .stdin(Stdio::piped()).stdout(Stdio::piped())
+            .spawn().unwrap();
+        // The program may exit without reading its input.
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        let result = child.wait_with_output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result.status.code().unwrap() as u64 {
+            rts::OKAY      => Ok(result.stdout),
+            rts::UNDERFLOW => Err(Error::PointerUnderflow),
+            rts::OVERFLOW  => Err(Error::PointerOverflow),
+            code => panic!("Unknown exit code: {}", code),
+        }
+    }
+}
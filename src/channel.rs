@@ -0,0 +1,432 @@
+//! Buffered input and output for running programs.
+//!
+//! All the interpreters and the JIT’s [run-time system](../rts/index.html) do their I/O through
+//! the [`Channel`](trait.Channel.html) trait. The usual implementation,
+//! [`BufferedChannel`](struct.BufferedChannel.html), wraps a `Read` and a `Write` with buffers
+//! whose sizes are given by a [`Buffering`](struct.Buffering.html). Buffered output is flushed
+//! before every read, when the program finishes or fails, and optionally after every newline.
+//!
+//! To embed a program where input and output don’t come from streams, implement the
+//! callback-based [`Io`](trait.Io.html) trait and wrap it in an
+//! [`IoChannel`](struct.IoChannel.html):
+//!
+//! ```
+//! use bf::channel::{InputResult, Io};
+//! use bf::config::Config;
+//! use bf::state::State;
+//! use bf::traits::Interpretable;
+//!
+//! struct Shout(Vec<u8>);
+//!
+//! impl Io for Shout {
+//!     fn input(&mut self) -> InputResult {
+//!         InputResult::Byte(b'a')
+//!     }
+//!
+//!     fn output(&mut self, byte: u8) {
+//!         self.0.push(byte.to_ascii_uppercase());
+//!     }
+//! }
+//!
+//! let program = bf::ast::parse_program(b",.").unwrap();
+//! let mut io = Shout(Vec::new());
+//! program.interpret_io(State::with_config(&Config::new()), &mut io).unwrap();
+//! assert_eq!(io.0, b"A");
+//! ```
+
+use std::io::{self, ErrorKind, Read, Write};
+
+/// (`== 8192`) The default size of the input and output buffers.
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+
+/// The result of asking for a byte of input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputResult {
+    /// The next byte of input.
+    Byte(u8),
+    /// There is no more input.
+    Eof,
+    /// There is no input available now, but there may be later.
+    ///
+    /// Running programs stop at the `,` with
+    /// [`Error::WouldBlock`](../common/enum.Error.html#variant.WouldBlock).
+    WouldBlock,
+}
+
+/// The byte-at-a-time I/O interface used by running programs.
+pub trait Channel {
+    /// Reads a byte of input.
+    fn read_byte(&mut self) -> InputResult;
+
+    /// Writes a byte of output.
+    fn write_byte(&mut self, byte: u8);
+
+    /// Writes several bytes of output.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Writes out any buffered output.
+    fn flush(&mut self);
+}
+
+impl<C: Channel + ?Sized> Channel for &mut C {
+    fn read_byte(&mut self) -> InputResult {
+        (**self).read_byte()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        (**self).write_byte(byte)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        (**self).write_bytes(bytes)
+    }
+
+    fn flush(&mut self) {
+        (**self).flush()
+    }
+}
+
+/// How a [`BufferedChannel`](struct.BufferedChannel.html) buffers its input and output.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Buffering {
+    /// The size of the input buffer; `0` reads one byte at a time.
+    pub input_size: usize,
+    /// The size of the output buffer; `0` writes each byte as soon as it is produced.
+    pub output_size: usize,
+    /// Whether to flush the output buffer after writing a newline.
+    pub flush_on_newline: bool,
+}
+
+impl Buffering {
+    /// Line-buffered output and block-buffered input, each with a buffer of
+    /// [`DEFAULT_BUFFER_SIZE`](constant.DEFAULT_BUFFER_SIZE.html) bytes.
+    pub fn new() -> Self {
+        Buffering {
+            input_size: DEFAULT_BUFFER_SIZE,
+            output_size: DEFAULT_BUFFER_SIZE,
+            flush_on_newline: true,
+        }
+    }
+
+    /// No buffering at all, so that every byte is read or written as soon as the program asks.
+    pub fn interactive() -> Self {
+        Buffering {
+            input_size: 0,
+            output_size: 0,
+            flush_on_newline: true,
+        }
+    }
+}
+
+impl Default for Buffering {
+    fn default() -> Self {
+        Buffering::new()
+    }
+}
+
+/// A `Channel` that buffers a `Read` and a `Write`.
+///
+/// I/O errors are ignored, as is a short read: a byte that cannot be read counts as the end of
+/// the input. The exception is a read that fails with `ErrorKind::WouldBlock`, as from a
+/// non-blocking socket, which is reported as `InputResult::WouldBlock`.
+pub struct BufferedChannel<R: Read, W: Write> {
+    input: R,
+    output: W,
+    buffering: Buffering,
+    input_buffer: Box<[u8]>,
+    input_start: usize,
+    input_end: usize,
+    output_buffer: Vec<u8>,
+}
+
+impl<R: Read, W: Write> BufferedChannel<R, W> {
+    /// Creates a channel with the default buffering.
+    pub fn new(input: R, output: W) -> Self {
+        Self::with_buffering(input, output, Buffering::new())
+    }
+
+    /// Creates a channel with the given buffering.
+    pub fn with_buffering(input: R, output: W, buffering: Buffering) -> Self {
+        BufferedChannel {
+            input,
+            output,
+            buffering,
+            input_buffer: vec![0; buffering.input_size].into_boxed_slice(),
+            input_start: 0,
+            input_end: 0,
+            output_buffer: Vec::with_capacity(buffering.output_size),
+        }
+    }
+
+    /// Refills the input buffer, failing with `Eof` or `WouldBlock` if no input was read.
+    fn fill_input(&mut self) -> Result<(), InputResult> {
+        loop {
+            match self.input.read(&mut self.input_buffer) {
+                Ok(0) => return Err(InputResult::Eof),
+                Ok(count) => {
+                    self.input_start = 0;
+                    self.input_end = count;
+                    return Ok(());
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(failed_read(&e)),
+            }
+        }
+    }
+
+    /// Writes the output buffer to the underlying `Write` without flushing it.
+    fn drain_output(&mut self) {
+        if !self.output_buffer.is_empty() {
+            let _ = self.output.write_all(&self.output_buffer);
+            self.output_buffer.clear();
+        }
+    }
+}
+
+impl<R: Read, W: Write> Channel for BufferedChannel<R, W> {
+    fn read_byte(&mut self) -> InputResult {
+        self.flush();
+
+        if self.input_buffer.is_empty() {
+            let mut byte = [0];
+            return match self.input.read_exact(&mut byte) {
+                Ok(()) => InputResult::Byte(byte[0]),
+                Err(e) => failed_read(&e),
+            };
+        }
+
+        if self.input_start == self.input_end {
+            if let Err(result) = self.fill_input() {
+                return result;
+            }
+        }
+
+        let byte = self.input_buffer[self.input_start];
+        self.input_start += 1;
+        InputResult::Byte(byte)
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        if self.buffering.output_size == 0 {
+            let _ = self.output.write_all(&[byte]);
+            return;
+        }
+
+        self.output_buffer.push(byte);
+
+        if byte == b'\n' && self.buffering.flush_on_newline {
+            self.flush();
+        } else if self.output_buffer.len() >= self.buffering.output_size {
+            self.drain_output();
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.output_buffer.len() + bytes.len() > self.buffering.output_size {
+            self.drain_output();
+            let _ = self.output.write_all(bytes);
+        } else {
+            self.output_buffer.extend_from_slice(bytes);
+        }
+
+        if self.buffering.flush_on_newline && bytes.contains(&b'\n') {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.drain_output();
+        let _ = self.output.flush();
+    }
+}
+
+impl<R: Read, W: Write> Drop for BufferedChannel<R, W> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// What a failed read means for the program.
+fn failed_read(error: &io::Error) -> InputResult {
+    if error.kind() == ErrorKind::WouldBlock {
+        InputResult::WouldBlock
+    } else {
+        InputResult::Eof
+    }
+}
+
+/// Callback-based I/O, for embedding programs whose input and output don’t come from streams.
+///
+/// Wrap an implementation in an [`IoChannel`](struct.IoChannel.html) to use it wherever a
+/// `Channel` is expected, or pass it to
+/// [`Interpretable::interpret_io`](../traits/trait.Interpretable.html#method.interpret_io).
+pub trait Io {
+    /// Provides a byte of input, or reports that there is none now or ever.
+    fn input(&mut self) -> InputResult;
+
+    /// Receives a byte of output.
+    fn output(&mut self, byte: u8);
+}
+
+impl<H: Io + ?Sized> Io for &mut H {
+    fn input(&mut self) -> InputResult {
+        (**self).input()
+    }
+
+    fn output(&mut self, byte: u8) {
+        (**self).output(byte)
+    }
+}
+
+/// A `Channel` that does I/O by calling an [`Io`](trait.Io.html) implementation.
+///
+/// Nothing is buffered, so each `,` and `.` makes exactly one call.
+pub struct IoChannel<H: Io>(H);
+
+impl<H: Io> IoChannel<H> {
+    /// Creates a channel that calls `io`.
+    pub fn new(io: H) -> Self {
+        IoChannel(io)
+    }
+
+    /// Gets a reference to the underlying `Io`.
+    pub fn get_ref(&self) -> &H {
+        &self.0
+    }
+
+    /// Gets a mutable reference to the underlying `Io`.
+    pub fn get_mut(&mut self) -> &mut H {
+        &mut self.0
+    }
+
+    /// Unwraps the underlying `Io`.
+    pub fn into_inner(self) -> H {
+        self.0
+    }
+}
+
+impl<H: Io> Channel for IoChannel<H> {
+    fn read_byte(&mut self) -> InputResult {
+        self.0.input()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.0.output(byte);
+    }
+
+    fn flush(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_until_eof() {
+        for &buffering in &[Buffering::new(), Buffering::interactive()] {
+            let mut output = Vec::new();
+            let mut channel = BufferedChannel::with_buffering(&b"ab"[..], &mut output, buffering);
+            assert_eq!(channel.read_byte(), InputResult::Byte(b'a'));
+            assert_eq!(channel.read_byte(), InputResult::Byte(b'b'));
+            assert_eq!(channel.read_byte(), InputResult::Eof);
+        }
+    }
+
+    #[test]
+    fn output_is_held_until_flush() {
+        let mut output = Vec::new();
+        let mut channel = BufferedChannel::new(&b""[..], &mut output);
+        channel.write_byte(b'a');
+        channel.write_bytes(b"bc");
+        assert!(channel.output.is_empty());
+        channel.flush();
+        assert_eq!(&channel.output[..], b"abc");
+    }
+
+    #[test]
+    fn newline_flushes_output() {
+        let mut output = Vec::new();
+        let mut channel = BufferedChannel::new(&b""[..], &mut output);
+        channel.write_bytes(b"ab\ncd");
+        channel.write_byte(b'e');
+        assert_eq!(&channel.output[..], b"ab\ncd");
+        channel.write_byte(b'\n');
+        assert_eq!(&channel.output[..], b"ab\ncde\n");
+    }
+
+    #[test]
+    fn reading_flushes_output() {
+        let mut output = Vec::new();
+        let mut channel = BufferedChannel::new(&b"x"[..], &mut output);
+        channel.write_byte(b'?');
+        assert_eq!(channel.read_byte(), InputResult::Byte(b'x'));
+        assert_eq!(&channel.output[..], b"?");
+    }
+
+    #[test]
+    fn interactive_writes_immediately() {
+        let mut output = Vec::new();
+        let mut channel = BufferedChannel::with_buffering(&b""[..], &mut output,
+                                                          Buffering::interactive());
+        channel.write_byte(b'a');
+        channel.write_bytes(b"bc");
+        assert_eq!(&channel.output[..], b"abc");
+    }
+
+    #[test]
+    fn would_block_is_reported() {
+        for &buffering in &[Buffering::new(), Buffering::interactive()] {
+            let mut output = Vec::new();
+            let mut channel = BufferedChannel::with_buffering(Blocking(0), &mut output, buffering);
+            assert_eq!(channel.read_byte(), InputResult::WouldBlock);
+            assert_eq!(channel.read_byte(), InputResult::Byte(b'x'));
+            assert_eq!(channel.read_byte(), InputResult::Eof);
+        }
+    }
+
+    #[test]
+    fn io_channel_calls_back() {
+        let mut channel = IoChannel::new(Script(vec![InputResult::WouldBlock,
+                                                     InputResult::Byte(b'a')],
+                                                Vec::new()));
+        assert_eq!(channel.read_byte(), InputResult::Byte(b'a'));
+        assert_eq!(channel.read_byte(), InputResult::WouldBlock);
+        assert_eq!(channel.read_byte(), InputResult::Eof);
+        channel.write_bytes(b"hi");
+        assert_eq!(channel.into_inner().1, b"hi");
+    }
+
+    /// Would block once, then reads an `x`, then reaches the end of the input.
+    struct Blocking(usize);
+
+    impl Read for Blocking {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0 += 1;
+            match self.0 {
+                1 => Err(io::Error::new(ErrorKind::WouldBlock, "not yet")),
+                2 => {
+                    buf[0] = b'x';
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    /// Pops its input from the end of the vector and collects its output.
+    struct Script(Vec<InputResult>, Vec<u8>);
+
+    impl Io for Script {
+        fn input(&mut self) -> InputResult {
+            self.0.pop().unwrap_or(InputResult::Eof)
+        }
+
+        fn output(&mut self, byte: u8) {
+            self.1.push(byte);
+        }
+    }
+}
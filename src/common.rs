@@ -3,11 +3,11 @@
 //! This includes error handling and the basic definition of Brainfuck commands.
 
 use std::fmt;
+use std::io;
 
 /// The result type for Brainfuck operations that can fail.
 ///
-/// This is `Result` specialized to the four kinds of Brainfuck
-/// [`Error`](enum.Error.html)s
+/// This is `Result` specialized to the kinds of Brainfuck [`Error`](enum.Error.html)s
 pub type BfResult<T> = Result<T, Error>;
 
 /// The static and dynamic errors that can happen in Brainfuck.
@@ -22,6 +22,16 @@ pub enum Error {
     /// If execution continues, the pointer will go beyond the high end of the
     /// memory (run-time error)
     PointerOverflow,
+    /// The program ran for more loop iterations than its
+    /// [step limit](../config/struct.Config.html#structfield.step_limit) (run-time error)
+    StepLimitExceeded,
+    /// The backend does not support the requested [configuration](../config/index.html)
+    Unsupported,
+    /// The program asked for input that is not available yet (run-time error)
+    ///
+    /// Execution stopped at the `,`, without changing the cell. See
+    /// [`InputResult`](../channel/enum.InputResult.html).
+    WouldBlock,
 }
 
 impl fmt::Display for Error {
@@ -33,6 +43,9 @@ impl fmt::Display for Error {
             UnmatchedEnd => write!(f, "unmatched ‘]’"),
             PointerUnderflow => write!(f, "pointer underflow"),
             PointerOverflow => write!(f, "pointer overflow"),
+            StepLimitExceeded => write!(f, "step limit exceeded"),
+            Unsupported => write!(f, "configuration not supported by this backend"),
+            WouldBlock => write!(f, "input would block"),
         }
     }
 }
@@ -102,6 +115,16 @@ pub enum Instruction {
     In,
     /// Write a byte of output.
     Out,
+    /// Write the given byte of output.
+    ///
+    /// The bytecode flattener produces this from the peephole optimizer’s runs of statically
+    /// known output that are a single byte long.
+    OutByte(u8),
+    /// Write a string of output.
+    ///
+    /// The `Count` is the index of the string in the bytecode program’s string table. The
+    /// bytecode flattener produces this from longer runs of statically known output.
+    OutString(Count),
     /// Begin a loop, jumping to the end if the current byte value is 0.
     ///
     /// The `Count` is the address of the matching `JumpNotZero` instruction.
@@ -134,3 +157,20 @@ pub enum Instruction {
     FindZeroLeft(Count),
 }
 
+
+/// A 64-bit FNV-1a hash of the given bytes.
+///
+/// This is not a cryptographic hash; it only tells apart inputs that were not chosen to
+/// collide, such as cached code for different programs.
+pub fn fnv1a<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.into_iter()
+        .fold(OFFSET_BASIS, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+}
+
+/// An `InvalidData` error with the given message, for files that fail to load.
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
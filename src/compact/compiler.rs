@@ -0,0 +1,237 @@
+use super::*;
+use super::opcode::*;
+use bytecode;
+use common::Instruction;
+use config::Config;
+use traits::IntoUsize;
+
+/// Program forms that can be compiled to compact bytecode.
+pub trait CompactCompilable {
+    /// Compile the given program to bytecode to prepare for packing.
+    fn with_bytecode<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&bytecode::Program) -> R;
+
+    /// Compile the given program to compact bytecode.
+    fn compact_compile(&self, config: &Config) -> Program {
+        self.with_bytecode(config, compile)
+    }
+}
+
+/// Packs a bytecode program into the compact encoding.
+///
+/// # Panics
+///
+/// Panics if the encoded program does not fit in 4 GiB.
+pub fn compile(src: &bytecode::Program) -> Program {
+    let mut compiler = Compiler::new();
+    let mut pc = 0;
+
+    while pc < src.len() {
+        pc += compiler.compile_at(src, pc);
+    }
+
+    compiler.into_program()
+}
+
+struct Compiler {
+    /// The encoded program so far.
+    code: Vec<u8>,
+    /// The position in `code` of each bytecode instruction compiled so far.
+    positions: Vec<usize>,
+    /// Jump operands to fill in: their position in `code` and their bytecode target.
+    fixups: Vec<(usize, usize)>,
+}
+
+impl Compiler {
+    fn new() -> Self {
+        Compiler {
+            code: Vec::new(),
+            positions: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    /// Compiles the instruction at `pc`, possibly fusing it with the next one, and returns the
+    /// number of instructions consumed.
+    fn compile_at(&mut self, src: &bytecode::Program, pc: usize) -> usize {
+        use common::Instruction::*;
+
+        let position = self.code.len();
+        self.positions.push(position);
+
+        let fused = match (src[pc], src.get(pc + 1)) {
+            (Left(count), Some(&Add(amount))) => {
+                self.code.push(LEFT_ADD);
+                self.put_varint(count.into_usize());
+                self.code.push(amount);
+                true
+            }
+
+            (Right(count), Some(&Add(amount))) => {
+                self.code.push(RIGHT_ADD);
+                self.put_varint(count.into_usize());
+                self.code.push(amount);
+                true
+            }
+
+            (Add(amount), Some(&Left(count))) => {
+                self.code.push(ADD_LEFT);
+                self.code.push(amount);
+                self.put_varint(count.into_usize());
+                true
+            }
+
+            (Add(amount), Some(&Right(count))) => {
+                self.code.push(ADD_RIGHT);
+                self.code.push(amount);
+                self.put_varint(count.into_usize());
+                true
+            }
+
+            (Add(amount), Some(&Out)) => {
+                self.code.push(ADD_OUT);
+                self.code.push(amount);
+                true
+            }
+
+            (OutString(index), _) => {
+                let string = src.string(index);
+                self.put_count(OUT_STRING, string.len());
+                self.code.extend_from_slice(string);
+                false
+            }
+
+            (instruction, _) => {
+                self.compile_one(instruction);
+                false
+            }
+        };
+
+        if fused {
+            // Jumps only ever target jumps, so the second instruction of a pair is never a
+            // target; it shares the position of the first.
+            self.positions.push(position);
+            2
+        } else {
+            1
+        }
+    }
+
+    fn compile_one(&mut self, instruction: Instruction) {
+        use common::Instruction::*;
+
+        match instruction {
+            Left(count) => self.put_count(LEFT, count.into_usize()),
+            Right(count) => self.put_count(RIGHT, count.into_usize()),
+
+            Add(amount) => {
+                self.code.push(ADD);
+                self.code.push(amount);
+            }
+
+            In => self.code.push(IN),
+            Out => self.code.push(OUT),
+
+            OutByte(byte) => {
+                self.code.push(OUT_BYTE);
+                self.code.push(byte);
+            }
+
+            JumpZero(address) => self.put_jump(JUMP_ZERO, address.into_usize()),
+            JumpNotZero(address) => self.put_jump(JUMP_NOT_ZERO, address.into_usize()),
+            SetZero => self.code.push(SET_ZERO),
+            OffsetAddRight(offset) => self.put_count(OFFSET_ADD_RIGHT, offset.into_usize()),
+            OffsetAddLeft(offset) => self.put_count(OFFSET_ADD_LEFT, offset.into_usize()),
+            FindZeroRight(skip) => self.put_count(FIND_ZERO_RIGHT, skip.into_usize()),
+            FindZeroLeft(skip) => self.put_count(FIND_ZERO_LEFT, skip.into_usize()),
+            OutString(_) => panic!("string instruction without its program"),
+        }
+    }
+
+    fn put_count(&mut self, opcode: u8, count: usize) {
+        self.code.push(opcode);
+        self.put_varint(count);
+    }
+
+    fn put_varint(&mut self, mut value: usize) {
+        while value >= 0x80 {
+            self.code.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.code.push(value as u8);
+    }
+
+    fn put_jump(&mut self, opcode: u8, target: usize) {
+        self.code.push(opcode);
+        self.fixups.push((self.code.len(), target));
+        self.code.extend_from_slice(&[0; 4]);
+    }
+
+    fn into_program(mut self) -> Program {
+        assert!(self.code.len() <= u32::MAX as usize,
+                "compact program too large");
+
+        // A jump to a jump lands just after it, as in the bytecode interpreter.
+        for &(operand, target) in &self.fixups {
+            let address = (self.positions[target] + JUMP_SIZE) as u32;
+            self.code[operand .. operand + 4].copy_from_slice(&address.to_le_bytes());
+        }
+
+        Program {
+            code: self.code.into_boxed_slice(),
+        }
+    }
+}
+
+impl CompactCompilable for bytecode::Program {
+    fn with_bytecode<F, R>(&self, _config: &Config, k: F) -> R
+        where F: FnOnce(&bytecode::Program) -> R
+    {
+        k(self)
+    }
+}
+
+impl<T: bytecode::BytecodeCompilable + ?Sized> CompactCompilable for T {
+    fn with_bytecode<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&bytecode::Program) -> R
+    {
+        k(&self.bytecode_compile(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Instruction::*;
+
+    #[test]
+    fn pairs_are_fused() {
+        assert_compile(&[Right(2), Add(3), Add(4), Out, Add(5), Left(1)],
+                       &[RIGHT_ADD, 2, 3, ADD_OUT, 4, ADD_LEFT, 5, 1]);
+    }
+
+    #[test]
+    fn counts_are_varints() {
+        assert_compile(&[Left(300), Right(127)],
+                       &[LEFT, 0xAC, 0x02, RIGHT, 0x7F]);
+    }
+
+    #[test]
+    fn jumps_land_after_their_partners() {
+        assert_compile(&[JumpZero(2), Add(1), JumpNotZero(0)],
+                       &[JUMP_ZERO, 12, 0, 0, 0,
+                         ADD, 1,
+                         JUMP_NOT_ZERO, 5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn strings_are_inline() {
+        let src = bytecode::Program::new(Box::new([OutString(0), Out]),
+                                         Box::new([Box::from(&b"hi"[..])]));
+        assert_eq!(compile(&src).as_bytes(), &[OUT_STRING, 2, b'h', b'i', OUT]);
+    }
+
+    fn assert_compile(src: &[Instruction], expected: &[u8]) {
+        assert_eq!(compile(&bytecode::Program::from(src)).as_bytes(), expected);
+    }
+}
@@ -0,0 +1,172 @@
+use channel::Channel;
+use state::State;
+use common::{BfResult, Error};
+use config::CellWidth;
+use traits::Interpretable;
+use super::*;
+use super::opcode::*;
+
+/// Fails with `Unsupported` if the state’s cells are wider than 8 bits.
+impl Interpretable for Program {
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, channel: &mut C)
+        -> BfResult<()>
+    {
+        if state.cell_width() != CellWidth::Eight {
+            return Err(Error::Unsupported);
+        }
+
+        interpret(&self.code, state, channel)
+    }
+}
+
+fn interpret<C>(code: &[u8], state: &mut State, channel: &mut C) -> BfResult<()>
+    where C: Channel + ?Sized
+{
+    let mut pc = 0;
+
+    while let Some(&opcode) = code.get(pc) {
+        pc += 1;
+
+        match opcode {
+            LEFT => state.left(read_varint(code, &mut pc))?,
+            RIGHT => state.right(read_varint(code, &mut pc))?,
+            ADD => state.up(read_byte(code, &mut pc)),
+            IN => state.read(channel)?,
+            OUT => state.write(channel),
+            OUT_BYTE => channel.write_byte(read_byte(code, &mut pc)),
+
+            OUT_STRING => {
+                let len = read_varint(code, &mut pc);
+                channel.write_bytes(&code[pc .. pc + len]);
+                pc += len;
+            }
+
+            JUMP_ZERO => {
+                let address = read_address(code, &mut pc);
+                if state.load() == 0 {
+                    pc = address;
+                } else {
+                    state.step()?;
+                }
+            }
+
+            JUMP_NOT_ZERO => {
+                let address = read_address(code, &mut pc);
+                if state.load() != 0 {
+                    state.step()?;
+                    pc = address;
+                }
+            }
+
+            SET_ZERO => state.store(0),
+
+            OFFSET_ADD_RIGHT => {
+                let offset = read_varint(code, &mut pc);
+                let value = state.load();
+                if value != 0 {
+                    state.store(0);
+                    state.up_pos_offset(offset, value)?;
+                }
+            }
+
+            OFFSET_ADD_LEFT => {
+                let offset = read_varint(code, &mut pc);
+                let value = state.load();
+                if value != 0 {
+                    state.store(0);
+                    state.up_neg_offset(offset, value)?;
+                }
+            }
+
+            FIND_ZERO_RIGHT => state.find_zero_right(read_varint(code, &mut pc))?,
+            FIND_ZERO_LEFT => state.find_zero_left(read_varint(code, &mut pc))?,
+
+            LEFT_ADD => {
+                state.left(read_varint(code, &mut pc))?;
+                state.up(read_byte(code, &mut pc));
+            }
+
+            RIGHT_ADD => {
+                state.right(read_varint(code, &mut pc))?;
+                state.up(read_byte(code, &mut pc));
+            }
+
+            ADD_LEFT => {
+                state.up(read_byte(code, &mut pc));
+                state.left(read_varint(code, &mut pc))?;
+            }
+
+            ADD_RIGHT => {
+                state.up(read_byte(code, &mut pc));
+                state.right(read_varint(code, &mut pc))?;
+            }
+
+            ADD_OUT => {
+                state.up(read_byte(code, &mut pc));
+                state.write(channel);
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn read_byte(code: &[u8], pc: &mut usize) -> u8 {
+    let byte = code[*pc];
+    *pc += 1;
+    byte
+}
+
+#[inline]
+fn read_varint(code: &[u8], pc: &mut usize) -> usize {
+    let mut result = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = read_byte(code, pc);
+        result |= ((byte & 0x7F) as usize) << shift;
+        if byte < 0x80 {
+            return result;
+        }
+        shift += 7;
+    }
+}
+
+#[inline]
+fn read_address(code: &[u8], pc: &mut usize) -> usize {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&code[*pc .. *pc + 4]);
+    *pc += 4;
+    u32::from_le_bytes(bytes) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::*;
+    use config::Config;
+
+    #[test]
+    fn hello_world() {
+        assert_parse_interpret(HELLO_WORLD_SRC, "", "Hello, World!");
+    }
+
+    #[test]
+    fn factoring() {
+        assert_parse_interpret(FACTOR_SRC, "2\n", "2: 2\n");
+        assert_parse_interpret(FACTOR_SRC, "3\n", "3: 3\n");
+        assert_parse_interpret(FACTOR_SRC, "6\n", "6: 2 3\n");
+        assert_parse_interpret(FACTOR_SRC, "100\n", "100: 2 2 5 5\n");
+    }
+
+    fn assert_parse_interpret(program: &[u8], input: &str, output: &str) {
+        let program = ::ast::parse_program(program).unwrap();
+        let program = ::rle::compile(&program);
+        let program = ::peephole::compile(&program, &Config::new());
+        let program = ::bytecode::compile(&program);
+        let program = ::compact::compile(&program);
+        assert_interpret(&program, input.as_bytes(), output.as_bytes());
+    }
+}
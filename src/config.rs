@@ -0,0 +1,396 @@
+//! Options for compiling and running programs.
+//!
+//! A [`Config`](struct.Config.html) gathers everything that affects how a program is compiled or
+//! run: the size of memory, the width of its cells, and what happens at its end, what `,` stores
+//! at the end of the input, whether generated code checks bounds, how many loop iterations to
+//! allow, and which optimizations to perform. Every `*Compilable` trait and
+//! [`Interpretable`](../traits/trait.Interpretable.html) takes one, and options that don’t apply
+//! to a particular pass are ignored. Configurations are built up from the defaults:
+//!
+//! ```
+//! use bf::config::{Config, EofMode};
+//!
+//! let config = Config::new().memory_size(1024).eof(EofMode::Unchanged).step_limit(1_000_000);
+//! assert_eq!(config.memory_size, 1024);
+//! ```
+//!
+//! Not every backend supports every option. The interpreters support them all, as does Rust
+//! code generated by [`rust`](../rust/index.html), which runs against a `State`. The JIT
+//! compilers reject growable tapes and step limits with
+//! [`Error::Unsupported`](../common/enum.Error.html#variant.Unsupported). Generated C honors
+//! only the memory size, EOF mode, and bounds checking, and WebAssembly leaves the EOF mode to
+//! the host that provides input.
+//!
+//! Cells wider than 8 bits are the exception: the optimizer folds arithmetic modulo 256, so
+//! only the unoptimized [AST](../ast/index.html) and [RLE](../rle/index.html) interpreters
+//! support them. Every other backend that runs against a `State` rejects them with
+//! `Unsupported`, and generated code always has 8-bit cells.
+//!
+//! Optimizations are chosen by level, and then individual [`Pass`](enum.Pass.html)es can be
+//! turned on or off, which is handy for bisecting optimizer bugs or measuring what each pass is
+//! worth:
+//!
+//! ```
+//! use bf::config::{Config, Pass};
+//!
+//! let config = Config::new().opt_level(2).disable(Pass::FindZero);
+//! assert!(config.enabled(Pass::SetZero));
+//! assert!(!config.enabled(Pass::FindZero));
+//! assert!(!config.enabled(Pass::ConstOutput));
+//! ```
+
+use state::DEFAULT_CAPACITY;
+
+/// (`== 3`) The highest optimization level.
+pub const MAX_OPT_LEVEL: u8 = 3;
+
+/// Options for compiling and running programs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Config {
+    /// The number of memory cells to start with (default 30,000).
+    pub memory_size: usize,
+    /// How many bits each memory cell holds (default 8).
+    pub cell_width: CellWidth,
+    /// What happens when the pointer goes past the end of memory.
+    pub tape_mode: TapeMode,
+    /// What `,` stores at the end of the input.
+    pub eof: EofMode,
+    /// Whether compiled code checks memory bounds. The interpreters always do.
+    pub checked: bool,
+    /// The number of loop iterations to allow, or `None` for no limit.
+    ///
+    /// Every time a program enters the body of a loop counts as an iteration. Since the
+    /// optimizer replaces some loops with single instructions, the count depends on which
+    /// passes are enabled.
+    pub step_limit: Option<u64>,
+    /// The optimization passes to perform (default all).
+    pub passes: PassSet,
+    /// Whether compiled programs always start with memory all zero, as in a new
+    /// [`State`](../state/struct.State.html) (default false).
+    ///
+    /// This lets [`Pass::ConstOutput`](enum.Pass.html#variant.ConstOutput) know the output of
+    /// code that runs before the program reads input or enters a loop. Leave it off for programs
+    /// that may run against a prepared state. Generated C and WebAssembly, standalone
+    /// executables, and LLVM output files allocate their own memory, so they assume it
+    /// regardless.
+    pub fresh_memory: bool,
+}
+
+/// An optimization pass that can be enabled or disabled.
+///
+/// Each pass belongs to an optimization level, and
+/// [`Config::opt_level`](struct.Config.html#method.opt_level) enables the passes at or below
+/// the given level:
+///
+///  - 0 translates each command on its own;
+///  - 1 adds `Rle`;
+///  - 2 adds `SetZero`, `FindZero`, and `OffsetAdd`;
+///  - 3 adds `ConstOutput`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pass {
+    /// Combine runs of the same command into one instruction.
+    Rle,
+    /// Replace `[-]` and `[+]` with `SetZero`.
+    SetZero,
+    /// Replace `[>]` and `[<]`, with any count, with `FindZeroRight` and `FindZeroLeft`.
+    FindZero,
+    /// Replace `[->+<]` and `[-<+>]`, with any count, with `OffsetAddRight` and `OffsetAddLeft`.
+    OffsetAdd,
+    /// Gather output whose value is known at compile time into runs.
+    ///
+    /// Cells count as known only once the program sets them, unless
+    /// [`Config::fresh_memory`](struct.Config.html#structfield.fresh_memory) says that memory
+    /// starts out zero.
+    ConstOutput,
+}
+
+/// All the optimization passes, in the order that they run.
+pub const PASSES: &[Pass] = &[Pass::Rle, Pass::SetZero, Pass::FindZero, Pass::OffsetAdd,
+                              Pass::ConstOutput];
+
+/// A set of optimization passes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct PassSet(u32);
+
+/// How many bits a memory cell holds.
+///
+/// Arithmetic on a cell wraps around at its width. Input stores a byte in the cell, and output
+/// writes the cell’s low byte.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CellWidth {
+    /// 8-bit cells.
+    Eight,
+    /// 16-bit cells.
+    Sixteen,
+    /// 32-bit cells.
+    ThirtyTwo,
+}
+
+/// What happens when the pointer goes past the end of memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TapeMode {
+    /// Moving past either end is an error.
+    Fixed,
+    /// Memory grows to the right as needed; moving left of the start is an error.
+    Growable,
+}
+
+/// What `,` stores at the end of the input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EofMode {
+    /// Store 0.
+    Zero,
+    /// Store −1, which is 255 for 8-bit cells.
+    MinusOne,
+    /// Leave the cell unchanged.
+    Unchanged,
+}
+
+impl Config {
+    /// The default configuration: 30,000 fixed 8-bit cells, 0 at the end of input, bounds
+    /// checked, no step limit, full optimization, and no assumptions about the starting memory.
+    pub fn new() -> Self {
+        Config {
+            memory_size: DEFAULT_CAPACITY,
+            cell_width:  CellWidth::Eight,
+            tape_mode:   TapeMode::Fixed,
+            eof:         EofMode::Zero,
+            checked:     true,
+            step_limit:  None,
+            passes:      PassSet::for_level(MAX_OPT_LEVEL),
+            fresh_memory: false,
+        }
+    }
+
+    /// Sets the number of memory cells to start with.
+    pub fn memory_size(mut self, memory_size: usize) -> Self {
+        self.memory_size = memory_size;
+        self
+    }
+
+    /// Sets how many bits each memory cell holds.
+    pub fn cell_width(mut self, cell_width: CellWidth) -> Self {
+        self.cell_width = cell_width;
+        self
+    }
+
+    /// Sets what happens when the pointer goes past the end of memory.
+    pub fn tape_mode(mut self, tape_mode: TapeMode) -> Self {
+        self.tape_mode = tape_mode;
+        self
+    }
+
+    /// Sets what `,` stores at the end of the input.
+    pub fn eof(mut self, eof: EofMode) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    /// Sets whether compiled code checks memory bounds.
+    pub fn checked(mut self, checked: bool) -> Self {
+        self.checked = checked;
+        self
+    }
+
+    /// Limits the number of loop iterations.
+    pub fn step_limit(mut self, step_limit: u64) -> Self {
+        self.step_limit = Some(step_limit);
+        self
+    }
+
+    /// Sets whether compiled programs always start with memory all zero.
+    pub fn fresh_memory(mut self, fresh_memory: bool) -> Self {
+        self.fresh_memory = fresh_memory;
+        self
+    }
+
+    /// Enables exactly the passes at or below the given optimization level. Levels above
+    /// [`MAX_OPT_LEVEL`](constant.MAX_OPT_LEVEL.html) enable every pass.
+    pub fn opt_level(mut self, opt_level: u8) -> Self {
+        self.passes = PassSet::for_level(opt_level);
+        self
+    }
+
+    /// Enables a pass.
+    pub fn enable(mut self, pass: Pass) -> Self {
+        self.passes.insert(pass);
+        self
+    }
+
+    /// Disables a pass.
+    pub fn disable(mut self, pass: Pass) -> Self {
+        self.passes.remove(pass);
+        self
+    }
+
+    /// Whether a pass is enabled.
+    #[inline]
+    pub fn enabled(&self, pass: Pass) -> bool {
+        self.passes.contains(pass)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+impl Pass {
+    /// The pass’s name, as used by [`from_name`](#method.from_name).
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Rle         => "rle",
+            Pass::SetZero     => "set-zero",
+            Pass::FindZero    => "find-zero",
+            Pass::OffsetAdd   => "offset-add",
+            Pass::ConstOutput => "const-output",
+        }
+    }
+
+    /// Looks up a pass by name.
+    pub fn from_name(name: &str) -> Option<Pass> {
+        PASSES.iter().cloned().find(|pass| pass.name() == name)
+    }
+
+    /// The lowest optimization level that enables the pass.
+    pub fn level(self) -> u8 {
+        match self {
+            Pass::Rle => 1,
+            Pass::SetZero | Pass::FindZero | Pass::OffsetAdd => 2,
+            Pass::ConstOutput => 3,
+        }
+    }
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+impl PassSet {
+    /// The empty set.
+    pub fn empty() -> Self {
+        PassSet(0)
+    }
+
+    /// The passes at or below the given optimization level.
+    pub fn for_level(opt_level: u8) -> Self {
+        let mut result = PassSet::empty();
+        for &pass in PASSES {
+            if pass.level() <= opt_level {
+                result.insert(pass);
+            }
+        }
+        result
+    }
+
+    /// Whether the set contains the given pass.
+    #[inline]
+    pub fn contains(self, pass: Pass) -> bool {
+        self.0 & pass.bit() != 0
+    }
+
+    /// Adds a pass to the set.
+    pub fn insert(&mut self, pass: Pass) {
+        self.0 |= pass.bit();
+    }
+
+    /// Removes a pass from the set.
+    pub fn remove(&mut self, pass: Pass) {
+        self.0 &= !pass.bit();
+    }
+
+    /// The set as bits, with bit `n` set for the `n`th pass in [`PASSES`](constant.PASSES.html).
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl CellWidth {
+    /// The width in bits.
+    pub fn bits(self) -> u32 {
+        match self {
+            CellWidth::Eight => 8,
+            CellWidth::Sixteen => 16,
+            CellWidth::ThirtyTwo => 32,
+        }
+    }
+
+    /// Looks up a width by its number of bits.
+    pub fn from_bits(bits: u32) -> Option<CellWidth> {
+        match bits {
+            8 => Some(CellWidth::Eight),
+            16 => Some(CellWidth::Sixteen),
+            32 => Some(CellWidth::ThirtyTwo),
+            _ => None,
+        }
+    }
+
+    /// The largest value that a cell can hold, which is −1 modulo the width.
+    #[inline]
+    pub fn max_value(self) -> u32 {
+        u32::MAX >> (32 - self.bits())
+    }
+}
+
+impl EofMode {
+    /// The value that `,` stores at the end of the input, given the cell’s current value.
+    #[inline]
+    pub fn value(self, current: u8) -> u8 {
+        match self {
+            EofMode::Zero => 0,
+            EofMode::MinusOne => 255,
+            EofMode::Unchanged => current,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_are_cumulative() {
+        assert_eq!(PassSet::for_level(0), PassSet::empty());
+        assert_eq!(Config::new().passes, PassSet::for_level(MAX_OPT_LEVEL));
+
+        for level in 0 .. MAX_OPT_LEVEL + 1 {
+            let config = Config::new().opt_level(level);
+            for &pass in PASSES {
+                assert_eq!(config.enabled(pass), pass.level() <= level, "{:?} at {}", pass, level);
+            }
+        }
+    }
+
+    #[test]
+    fn enable_and_disable() {
+        let config = Config::new().disable(Pass::SetZero);
+        assert!(!config.enabled(Pass::SetZero));
+        assert!(config.enabled(Pass::FindZero));
+
+        let config = Config::new().opt_level(0).enable(Pass::OffsetAdd);
+        assert!(config.enabled(Pass::OffsetAdd));
+        assert!(!config.enabled(Pass::Rle));
+    }
+
+    #[test]
+    fn pass_names_round_trip() {
+        for &pass in PASSES {
+            assert_eq!(Pass::from_name(pass.name()), Some(pass));
+        }
+
+        assert_eq!(Pass::from_name("nonesuch"), None);
+    }
+
+    #[test]
+    fn cell_widths() {
+        for &width in &[CellWidth::Eight, CellWidth::Sixteen, CellWidth::ThirtyTwo] {
+            assert_eq!(CellWidth::from_bits(width.bits()), Some(width));
+        }
+
+        assert_eq!(CellWidth::Eight.max_value(), 0xFF);
+        assert_eq!(CellWidth::Sixteen.max_value(), 0xFFFF);
+        assert_eq!(CellWidth::ThirtyTwo.max_value(), 0xFFFF_FFFF);
+        assert_eq!(CellWidth::from_bits(12), None);
+    }
+}
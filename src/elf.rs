@@ -0,0 +1,148 @@
+//! Writes standalone x86-64 Linux executables.
+//!
+//! The [JIT](../jit/index.html) can generate code that does its own I/O with Linux system calls
+//! rather than calling into the [run-time system](../rts/index.html). This module wraps such code
+//! in a minimal ELF file: one read-only, executable segment holding the headers and the code, and
+//! one zero-initialized, writable segment at [`TAPE_ADDRESS`](constant.TAPE_ADDRESS.html) holding
+//! the Brainfuck memory. There are no section headers and no dynamic linking.
+
+use std::io::{self, Write};
+
+/// (`== 0x40_0000`) The address at which the code segment is loaded.
+pub const CODE_ADDRESS: u64 = 0x40_0000;
+
+/// (`== 0x1000_0000`) The address at which the zeroed memory segment is loaded.
+pub const TAPE_ADDRESS: u64 = 0x1000_0000;
+
+/// The size of the ELF file header.
+const FILE_HEADER_SIZE: u64 = 64;
+
+/// The size of a program header.
+const PROGRAM_HEADER_SIZE: u64 = 56;
+
+/// The number of program headers.
+const PROGRAM_HEADER_COUNT: u64 = 2;
+
+/// The offset of the code from the start of the file (and of the code segment).
+pub const CODE_OFFSET: u64 = FILE_HEADER_SIZE + PROGRAM_HEADER_SIZE * PROGRAM_HEADER_COUNT;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+// Segment types and permissions.
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+/// Writes an executable that runs `code`, starting at offset `entry`, with `tape_size` bytes of
+/// zeroed memory at [`TAPE_ADDRESS`](constant.TAPE_ADDRESS.html).
+///
+/// The code must be position independent apart from its use of `TAPE_ADDRESS`, and must exit
+/// with a system call rather than returning.
+///
+/// # Panics
+///
+/// Panics if the code would overlap the tape.
+pub fn write_executable<W: Write>(mut output: W, code: &[u8], entry: usize, tape_size: usize)
+                                  -> io::Result<()> {
+    let code_segment_size = CODE_OFFSET + code.len() as u64;
+    assert!(CODE_ADDRESS + code_segment_size <= TAPE_ADDRESS, "code too large");
+
+    let mut header = Vec::with_capacity(CODE_OFFSET as usize);
+
+    // e_ident: magic, 64-bit, little-endian, version 1, System V ABI, padding
+    header.extend_from_slice(b"\x7FELF\x02\x01\x01\x00");
+    header.extend_from_slice(&[0; 8]);
+    put_u16(&mut header, 2);                                // e_type: executable
+    put_u16(&mut header, 62);                               // e_machine: x86-64
+    put_u32(&mut header, 1);                                // e_version
+    put_u64(&mut header, CODE_ADDRESS + CODE_OFFSET + entry as u64); // e_entry
+    put_u64(&mut header, FILE_HEADER_SIZE);                 // e_phoff
+    put_u64(&mut header, 0);                                // e_shoff
+    put_u32(&mut header, 0);                                // e_flags
+    put_u16(&mut header, FILE_HEADER_SIZE as u16);          // e_ehsize
+    put_u16(&mut header, PROGRAM_HEADER_SIZE as u16);       // e_phentsize
+    put_u16(&mut header, PROGRAM_HEADER_COUNT as u16);      // e_phnum
+    put_u16(&mut header, 64);                               // e_shentsize
+    put_u16(&mut header, 0);                                // e_shnum
+    put_u16(&mut header, 0);                                // e_shstrndx
+
+    put_segment(&mut header, PF_R | PF_X, 0, CODE_ADDRESS,
+                code_segment_size, code_segment_size);
+    put_segment(&mut header, PF_R | PF_W, 0, TAPE_ADDRESS,
+                0, tape_size as u64);
+
+    output.write_all(&header)?;
+    output.write_all(code)?;
+    output.flush()
+}
+
+fn put_segment(buf: &mut Vec<u8>, flags: u32, offset: u64, address: u64,
+               file_size: u64, memory_size: u64) {
+    put_u32(buf, PT_LOAD);          // p_type
+    put_u32(buf, flags);            // p_flags
+    put_u64(buf, offset);           // p_offset
+    put_u64(buf, address);          // p_vaddr
+    put_u64(buf, address);          // p_paddr
+    put_u64(buf, file_size);        // p_filesz
+    put_u64(buf, memory_size);      // p_memsz
+    put_u64(buf, PAGE_SIZE);        // p_align
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_precede_code() {
+        let mut output = Vec::new();
+        write_executable(&mut output, &[0x90, 0xC3], 1, 100).unwrap();
+
+        assert_eq!(output.len() as u64, CODE_OFFSET + 2);
+        assert_eq!(&output[.. 4], b"\x7FELF");
+        assert_eq!(&output[24 .. 32], &(CODE_ADDRESS + CODE_OFFSET + 1).to_le_bytes());
+        assert_eq!(&output[CODE_OFFSET as usize ..], &[0x90, 0xC3]);
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn executable_runs() {
+        use std::fs::{self, File};
+        use std::os::unix::fs::PermissionsExt;
+        use std::process::Command;
+
+        // mov byte [TAPE_ADDRESS + 99], 42
+        // movzx edi, byte [TAPE_ADDRESS + 99]
+        // mov eax, 60
+        // syscall
+        let tape_end = (TAPE_ADDRESS as u32 + 99).to_le_bytes();
+        let mut code = vec![0xC6, 0x04, 0x25];
+        code.extend_from_slice(&tape_end);
+        code.push(42);
+        code.extend_from_slice(&[0x0F, 0xB6, 0x3C, 0x25]);
+        code.extend_from_slice(&tape_end);
+        code.extend_from_slice(&[0xB8, 60, 0, 0, 0, 0x0F, 0x05]);
+
+        let path = ::std::env::temp_dir()
+            .join(format!("bf-elf-test-{}", ::std::process::id()));
+        write_executable(File::create(&path).unwrap(), &code, 0, 100).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let status = Command::new(&path).status().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(status.code(), Some(42));
+    }
+}
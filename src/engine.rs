@@ -0,0 +1,273 @@
+//! Compiling to a program form chosen at run time.
+//!
+//! Each pass has its own program type, which is convenient when the pass is known statically.
+//! When it isn’t, a [`Backend`](enum.Backend.html) compiles a program to the requested form and
+//! returns it as a [`Runnable`](../traits/trait.Runnable.html) trait object:
+//!
+//! ```
+//! use bf::config::Config;
+//! use bf::engine::Backend;
+//!
+//! let config = Config::new();
+//! let program = Backend::Bytecode.compile(b"++++++[>++++++++<-]>.", &config).unwrap();
+//! assert_eq!(program.run_memory(&config, b"").unwrap(), b"0");
+//! ```
+
+use std::fmt;
+
+use ast;
+use common::BfResult;
+use config::Config;
+use traits::*;
+
+/// The program forms that can be run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Interpret the unoptimized AST.
+    Ast,
+    /// Interpret the run-length encoded AST.
+    Rle,
+    /// Interpret the peephole-optimized AST.
+    Peephole,
+    /// Interpret bytecode.
+    Bytecode,
+    /// Interpret threaded code.
+    Threaded,
+    /// Interpret compact bytecode.
+    Compact,
+    /// JIT compile to native x64.
+    #[cfg(feature = "jit")]
+    Jit,
+    /// JIT compile using LLVM.
+    #[cfg(feature = "llvm")]
+    Llvm,
+}
+
+/// All the backends enabled in this build, in order from least to most optimized.
+#[cfg(all(feature = "jit", feature = "llvm"))]
+pub const BACKENDS: &[Backend] = &[Backend::Ast, Backend::Rle, Backend::Peephole,
+                                   Backend::Bytecode, Backend::Threaded, Backend::Compact,
+                                   Backend::Jit, Backend::Llvm];
+
+/// All the backends enabled in this build, in order from least to most optimized.
+#[cfg(all(feature = "jit", not(feature = "llvm")))]
+pub const BACKENDS: &[Backend] = &[Backend::Ast, Backend::Rle, Backend::Peephole,
+                                   Backend::Bytecode, Backend::Threaded, Backend::Compact,
+                                   Backend::Jit];
+
+/// All the backends enabled in this build, in order from least to most optimized.
+#[cfg(all(not(feature = "jit"), feature = "llvm"))]
+pub const BACKENDS: &[Backend] = &[Backend::Ast, Backend::Rle, Backend::Peephole,
+                                   Backend::Bytecode, Backend::Threaded, Backend::Compact,
+                                   Backend::Llvm];
+
+/// All the backends enabled in this build, in order from least to most optimized.
+#[cfg(not(any(feature = "jit", feature = "llvm")))]
+pub const BACKENDS: &[Backend] = &[Backend::Ast, Backend::Rle, Backend::Peephole,
+                                   Backend::Bytecode, Backend::Threaded, Backend::Compact];
+
+impl Backend {
+    /// Parses and compiles a program.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the program does not parse.
+    pub fn compile(self, program_text: &[u8], config: &Config) -> BfResult<Box<dyn Runnable>> {
+        let program = ast::parse_program(program_text)?;
+        Ok(self.compile_ast(program, config))
+    }
+
+    /// Compiles a parsed program.
+    pub fn compile_ast(self, program: Box<ast::Program>, config: &Config) -> Box<dyn Runnable> {
+        match self {
+            Backend::Ast => Box::new(program),
+            Backend::Rle => Box::new(program.rle_compile()),
+            Backend::Peephole => Box::new(program.peephole_compile(config)),
+            Backend::Bytecode => Box::new(program.bytecode_compile(config)),
+            Backend::Threaded => Box::new(program.threaded_compile(config)),
+            Backend::Compact => Box::new(program.compact_compile(config)),
+            #[cfg(feature = "jit")]
+            Backend::Jit => Box::new(program.jit_compile(config)),
+            #[cfg(feature = "llvm")]
+            Backend::Llvm => Box::new(program.llvm_compile(config)),
+        }
+    }
+
+    /// The backend’s name, as used by [`from_name`](#method.from_name).
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Ast => "ast",
+            Backend::Rle => "rle",
+            Backend::Peephole => "peephole",
+            Backend::Bytecode => "bytecode",
+            Backend::Threaded => "threaded",
+            Backend::Compact => "compact",
+            #[cfg(feature = "jit")]
+            Backend::Jit => "jit",
+            #[cfg(feature = "llvm")]
+            Backend::Llvm => "llvm",
+        }
+    }
+
+    /// Looks up an enabled backend by name.
+    pub fn from_name(name: &str) -> Option<Backend> {
+        BACKENDS.iter().cloned().find(|backend| backend.name() == name)
+    }
+}
+
+/// The native JIT if it’s enabled, and the peephole interpreter otherwise.
+impl Default for Backend {
+    #[cfg(feature = "jit")]
+    fn default() -> Self {
+        Backend::Jit
+    }
+
+    #[cfg(not(feature = "jit"))]
+    fn default() -> Self {
+        Backend::Peephole
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use channel::{InputResult, Io, IoChannel};
+    use common::Error;
+    use config::CellWidth;
+    use state::State;
+    use test_helpers::*;
+
+    #[test]
+    fn every_backend_runs() {
+        let config = Config::new();
+        for &backend in BACKENDS {
+            let program = backend.compile(FACTOR_SRC, &config).unwrap();
+            assert_eq!(program.run_memory(&config, b"100\n").unwrap(), b"100: 2 2 5 5\n",
+                       "backend {}", backend);
+        }
+    }
+
+    #[test]
+    fn every_backend_writes_constant_output() {
+        let config = Config::new().fresh_memory(true);
+        for &backend in BACKENDS {
+            let program = backend.compile(HELLO_WORLD_SRC, &config).unwrap();
+            assert_eq!(program.run_memory(&config, b"").unwrap(), b"Hello, World!",
+                       "backend {}", backend);
+            let program = backend.compile(&[&[b'+'; 72][..], b".+.+."].concat(), &config)
+                .unwrap();
+            assert_eq!(program.run_memory(&config, b"").unwrap(), b"HIJ", "backend {}", backend);
+        }
+    }
+
+    #[test]
+    fn wide_cells_run_only_on_the_ast_and_rle() {
+        let config = Config::new().cell_width(CellWidth::Sixteen);
+        for &backend in BACKENDS {
+            let program = backend.compile(&[&[b'+'; 256][..], b"[.[-]]"].concat(), &config)
+                .unwrap();
+            let expected = match backend {
+                Backend::Ast | Backend::Rle => Ok(vec![0]),
+                _ => Err(Error::Unsupported),
+            };
+            assert_eq!(program.run_memory(&config, b""), expected, "backend {}", backend);
+        }
+    }
+
+    #[test]
+    fn every_backend_checks_bounds() {
+        let config = Config::new();
+        for &backend in BACKENDS {
+            let program = backend.compile(b"<", &config).unwrap();
+            assert_eq!(program.run_memory(&config, b""), Err(Error::PointerUnderflow),
+                       "backend {}", backend);
+        }
+    }
+
+    #[test]
+    fn every_backend_stops_when_input_would_block() {
+        let config = Config::new();
+        for &backend in BACKENDS {
+            let program = backend.compile(b"+.,.", &config).unwrap();
+            let mut io = NoInputYet(Vec::new());
+            assert_eq!(program.run_io(State::with_config(&config), &mut io),
+                       Err(Error::WouldBlock), "backend {}", backend);
+            assert_eq!(io.0, b"\x01", "backend {}", backend);
+        }
+    }
+
+    #[test]
+    fn every_backend_leaves_the_final_state() {
+        let config = Config::new().memory_size(3);
+        for &backend in BACKENDS {
+            let program = backend.compile(b"+>++>+++>", &config).unwrap();
+            let mut state = State::with_config(&config);
+            let mut io = NoInputYet(Vec::new());
+            assert_eq!(program.run_in_place(&mut state, &mut IoChannel::new(&mut io)),
+                       Err(Error::PointerOverflow), "backend {}", backend);
+            assert_eq!(state.as_bytes(), &[1, 2, 3], "backend {}", backend);
+            assert_eq!(state.pointer(), 2, "backend {}", backend);
+        }
+    }
+
+    #[test]
+    fn every_backend_reports_the_highest_address() {
+        let config = Config::new().memory_size(8);
+        for &backend in BACKENDS {
+            for &(source, max_pointer) in &[(&b">>>>>+[-<<<<<]"[..], 5), (b"+[->>>+<<<]", 3)] {
+                let program = backend.compile(source, &config).unwrap();
+                let mut state = State::with_config(&config);
+                let mut io = NoInputYet(Vec::new());
+                program.run_in_place(&mut state, &mut IoChannel::new(&mut io)).unwrap();
+                assert_eq!(state.pointer(), 0, "backend {}", backend);
+                assert_eq!(state.max_pointer(), max_pointer, "backend {}", backend);
+            }
+        }
+    }
+
+    #[test]
+    fn every_backend_reads_prepared_memory() {
+        let config = Config::new().memory_size(1);
+        for &backend in BACKENDS {
+            let program = backend.compile(b".+.", &config).unwrap();
+            let mut io = NoInputYet(Vec::new());
+            program.run_in_place(&mut State::from_bytes(&[65], 0), &mut IoChannel::new(&mut io))
+                .unwrap();
+            assert_eq!(io.0, b"AB", "backend {}", backend);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(Backend::default().compile(b"[", &Config::new()).err(),
+                   Some(Error::UnmatchedBegin));
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for &backend in BACKENDS {
+            assert_eq!(Backend::from_name(backend.name()), Some(backend));
+        }
+
+        assert_eq!(Backend::from_name("nonesuch"), None);
+    }
+
+    /// Collects output, but never has input available.
+    struct NoInputYet(Vec<u8>);
+
+    impl Io for NoInputYet {
+        fn input(&mut self) -> InputResult {
+            InputResult::WouldBlock
+        }
+
+        fn output(&mut self, byte: u8) {
+            self.0.push(byte);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Saving JIT-compiled code to disk and loading it again.
+//!
+//! The generated code is position independent, except for the addresses of the run-time system
+//! functions that it calls, which are 64-bit immediates. The compiler records where each of
+//! those is as a [`Relocation`](struct.Relocation.html). To save a program, we zero the
+//! immediates and write the code along with the relocations; to load it, we patch in the
+//! functions’ addresses in the current process.
+//!
+//! The file format, with integers in little-endian order, is:
+//!
+//!   - the magic bytes `BFJIT`, then a format version byte;
+//!   - the entry point offset and code length, as `u64`s;
+//!   - the code;
+//!   - the number of relocations, as a `u64`, then for each, its offset as a `u64` and the
+//!     function as a byte.
+//!
+//! Cache files are keyed by [`cache_key`](fn.cache_key.html), which hashes the program text
+//! together with everything else that affects the generated code.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use dynasmrt::x64::Assembler;
+use dynasmrt::{AssemblyOffset, DynasmApi};
+
+use ast;
+use common::{fnv1a, invalid_data};
+use config::Config;
+use rts;
+use super::{compile, Program};
+
+/// The magic bytes at the start of a cache file.
+const MAGIC: &[u8] = b"BFJIT";
+
+/// The version of the file format and code generator.
+///
+/// Bump this whenever the generated code changes, so that old cache entries are not reused.
+const VERSION: u8 = 6;
+
+/// A run-time system function that generated code calls by address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtsFunction {
+    /// [`RtsState::read`](../../rts/struct.RtsState.html#method.read)
+    Read,
+    /// [`RtsState::write`](../../rts/struct.RtsState.html#method.write)
+    Write,
+    /// [`RtsState::write_bytes`](../../rts/struct.RtsState.html#method.write_bytes)
+    WriteBytes,
+    /// [`rts::find_zero_right`](../../rts/fn.find_zero_right.html)
+    FindZeroRight,
+    /// [`rts::find_zero_left`](../../rts/fn.find_zero_left.html)
+    FindZeroLeft,
+    /// [`RtsState::set_pointer`](../../rts/struct.RtsState.html#method.set_pointer)
+    SetPointer,
+    /// [`RtsState::get_pointer`](../../rts/struct.RtsState.html#method.get_pointer)
+    GetPointer,
+    /// [`RtsState::set_max_pointer`](../../rts/struct.RtsState.html#method.set_max_pointer)
+    SetMaxPointer,
+}
+
+/// The location of a function address in generated code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// The offset of the 64-bit address in the code.
+    pub offset: usize,
+    /// The function whose address goes there.
+    pub function: RtsFunction,
+}
+
+impl RtsFunction {
+    /// The function’s address in this process.
+    pub fn address(self) -> usize {
+        match self {
+            RtsFunction::Read          => rts::RtsState::read as usize,
+            RtsFunction::Write         => rts::RtsState::write as usize,
+            RtsFunction::WriteBytes    => rts::RtsState::write_bytes as usize,
+            RtsFunction::FindZeroRight => rts::find_zero_right as usize,
+            RtsFunction::FindZeroLeft  => rts::find_zero_left as usize,
+            RtsFunction::SetPointer    => rts::RtsState::set_pointer as usize,
+            RtsFunction::GetPointer    => rts::RtsState::get_pointer as usize,
+            RtsFunction::SetMaxPointer => rts::RtsState::set_max_pointer as usize,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(RtsFunction::Read),
+            1 => Some(RtsFunction::Write),
+            2 => Some(RtsFunction::WriteBytes),
+            3 => Some(RtsFunction::FindZeroRight),
+            4 => Some(RtsFunction::FindZeroLeft),
+            5 => Some(RtsFunction::SetPointer),
+            6 => Some(RtsFunction::GetPointer),
+            7 => Some(RtsFunction::SetMaxPointer),
+            _ => None,
+        }
+    }
+}
+
+impl Program {
+    /// Serializes the program, with its function addresses replaced by relocations.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut code = self.code.to_vec();
+        for relocation in &*self.relocations {
+            write_address(&mut code, relocation.offset, 0);
+        }
+
+        let mut result = Vec::with_capacity(code.len() + 32);
+        result.extend_from_slice(MAGIC);
+        result.push(VERSION);
+        put_u64(&mut result, self.start.0 as u64);
+        put_u64(&mut result, code.len() as u64);
+        result.extend_from_slice(&code);
+        put_u64(&mut result, self.relocations.len() as u64);
+        for relocation in &*self.relocations {
+            put_u64(&mut result, relocation.offset as u64);
+            result.push(relocation.function.to_byte());
+        }
+
+        result
+    }
+
+    /// Loads a program serialized by [`to_bytes`](#method.to_bytes), patching in the addresses
+    /// of the run-time system functions.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidData` if the bytes are not a program in the current format.
+    ///
+    /// # Safety
+    ///
+    /// The code is not checked, so running a program loaded from untrusted bytes can do
+    /// anything.
+    pub unsafe fn from_bytes(bytes: &[u8]) -> io::Result<Program> {
+        let mut reader = Reader(bytes);
+
+        if reader.take(MAGIC.len())? != MAGIC || reader.take(1)? != [VERSION] {
+            return Err(invalid_data("not a JIT cache file for this version"));
+        }
+
+        let start = reader.u64()? as usize;
+        let code_len = reader.u64()? as usize;
+        let mut code = reader.take(code_len)?.to_vec();
+
+        let count = reader.u64()? as usize;
+        let mut relocations = Vec::with_capacity(count.min(code_len / 8));
+        for _ in 0 .. count {
+            let offset = reader.u64()? as usize;
+            let function = RtsFunction::from_byte(reader.take(1)?[0])
+                .ok_or_else(|| invalid_data("unknown relocation"))?;
+            if offset.checked_add(8).map_or(true, |end| end > code.len()) {
+                return Err(invalid_data("relocation out of range"));
+            }
+
+            write_address(&mut code, offset, function.address() as u64);
+            relocations.push(Relocation { offset, function });
+        }
+
+        if start >= code.len() || !reader.0.is_empty() {
+            return Err(invalid_data("malformed JIT cache file"));
+        }
+
+        let mut asm = Assembler::new()?;
+        asm.extend(code);
+        let code = asm.finalize()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "could not finalize code"))?;
+
+        Ok(Program {
+            code,
+            start: AssemblyOffset(start),
+            relocations: relocations.into_boxed_slice(),
+        })
+    }
+}
+
+/// The cache key for the given program text and configuration.
+///
+/// This is a 64-bit FNV-1a hash of the text, the options that affect code generation, and the
+/// crate and code generator versions.
+pub fn cache_key(program_text: &[u8], config: &Config) -> u64 {
+    let mut header = [VERSION, config.checked as u8, config.fresh_memory as u8, 0, 0, 0, 0];
+    header[3 ..].copy_from_slice(&config.passes.bits().to_le_bytes());
+
+    fnv1a(env!("CARGO_PKG_VERSION").as_bytes().iter().chain(&header).chain(program_text))
+}
+
+/// JIT compiles the given program text with the given configuration, reusing code cached in
+/// the directory `cache_dir` if possible.
+///
+/// Newly compiled code is saved to the cache. Failing to save it is not an error, since the
+/// program is usable anyway.
+///
+/// # Errors
+///
+/// Fails with `InvalidData` if the program does not parse.
+pub fn compile_cached<P: AsRef<Path>>(program_text: &[u8], config: &Config, cache_dir: P)
+                                      -> io::Result<Program> {
+    let path = cache_dir.as_ref()
+        .join(format!("{:016x}.bfjit", cache_key(program_text, config)));
+
+    let mut bytes = Vec::new();
+    if File::open(&path).and_then(|mut file| file.read_to_end(&mut bytes)).is_ok() {
+        // The cache directory is trusted to contain only files that we wrote.
+        if let Ok(program) = unsafe { Program::from_bytes(&bytes) } {
+            return Ok(program);
+        }
+    }
+
+    let program = ast::parse_program(program_text)
+        .map_err(|e| invalid_data(&e.to_string()))?;
+    let program = compile(&::peephole::compile(&::rle::compile(&program), config), config);
+
+    let _ = fs::create_dir_all(&cache_dir)
+        .and_then(|_| File::create(&path))
+        .and_then(|mut file| file.write_all(&program.to_bytes()));
+
+    Ok(program)
+}
+
+fn write_address(code: &mut [u8], offset: usize, address: u64) {
+    code[offset .. offset + 8].copy_from_slice(&address.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Reads from the front of a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.0.len() {
+            return Err(invalid_data("truncated JIT cache file"));
+        }
+
+        let (result, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(result)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Pass;
+    use test_helpers::*;
+
+    #[test]
+    fn round_trip() {
+        let program = compile_source(FACTOR_SRC, &Config::new());
+        let bytes = program.to_bytes();
+        let loaded = unsafe { Program::from_bytes(&bytes) }.unwrap();
+
+        assert_eq!(loaded.to_bytes(), bytes);
+        assert_interpret_result(&loaded, b"100\n", Ok(&b"100: 2 2 5 5\n"[..]));
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let bytes = compile_source(HELLO_WORLD_SRC, &Config::new()).to_bytes();
+        assert_load_rejects_corruption(&bytes, |bytes| unsafe { Program::from_bytes(bytes) });
+    }
+
+    #[test]
+    fn keys_depend_on_options() {
+        let config = Config::new();
+        assert_ne!(cache_key(b"+", &config), cache_key(b"+", &config.checked(false)));
+        assert_ne!(cache_key(b"+", &config), cache_key(b"+", &config.opt_level(0)));
+        assert_ne!(cache_key(b"+", &config), cache_key(b"+", &config.disable(Pass::SetZero)));
+        assert_ne!(cache_key(b"+", &config), cache_key(b"-", &config));
+        assert_eq!(cache_key(b"+", &config), cache_key(b"+", &config.step_limit(5)));
+    }
+
+    #[test]
+    fn compile_cached_reuses_code() {
+        let dir = ::std::env::temp_dir().join(format!("bf-jit-cache-{}", ::std::process::id()));
+
+        let first = compile_cached(HELLO_WORLD_SRC, &Config::new(), &dir).unwrap();
+        let second = compile_cached(HELLO_WORLD_SRC, &Config::new(), &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first.to_bytes(), second.to_bytes());
+        assert_interpret_result(&second, b"", Ok(&b"Hello, World!"[..]));
+    }
+
+    fn compile_source(program: &[u8], config: &Config) -> Program {
+        let program = ::ast::parse_program(program).unwrap();
+        compile(&::peephole::compile(&::rle::compile(&program), config), config)
+    }
+}
@@ -1,21 +1,36 @@
+use std::io::{self, Write};
+use std::mem;
+
 use dynasmrt::x64::Assembler;
-use dynasmrt::{DynasmApi, DynasmLabelApi};
+use dynasmrt::{DynasmApi, DynasmLabelApi, DynamicLabel, ExecutableBuffer, AssemblyOffset};
 
 use super::*;
-use super::analysis::{BoundsAnalysis, AbstractInterpreter, NoAnalysis};
+use super::cache::{Relocation, RtsFunction};
+use analysis::{BoundsAnalysis, AbstractInterpreter, NoAnalysis};
 use common::Count;
+use config::{Config, EofMode};
+use elf;
 use peephole;
 use rts;
+use scan;
 
 /// Program forms that can be JIT compiled.
 pub trait JitCompilable {
     /// Compile the given program into the peephole AST to prepare for JIT compilation.
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R;
 
     /// JIT compile the given program.
-    fn jit_compile(&self, checked: bool) -> Program {
-        self.with_peephole(|ast| compile(ast, checked))
+    fn jit_compile(&self, config: &Config) -> Program {
+        self.with_peephole(config, |ast| compile(ast, config))
+    }
+
+    /// Compile the given program to a standalone x86-64 Linux executable with the
+    /// configuration’s amount of memory.
+    fn aot_compile<W: Write>(&self, config: &Config, output: W) -> io::Result<()> {
+        // The executable’s tape is fresh zero pages.
+        let config = &config.fresh_memory(true);
+        self.with_peephole(config, |ast| write_executable(ast, config, output))
     }
 }
 
@@ -24,23 +39,82 @@ dynasm!(asm
     ; .alias mem_start, r13
     ; .alias mem_limit, r14
     ; .alias rts, r15
+    ; .alias max_pointer, rbx
+);
+
+// Argument registers for the platform’s C calling convention: Microsoft x64 on Windows, and
+// System V AMD64 everywhere else. The generated function takes its arguments in these, and
+// passes them to the run-time system in them.
+
+#[cfg(windows)]
+dynasm!(asm
+    ; .alias arg1, rcx
+    ; .alias arg2, rdx
+    ; .alias arg3, r8
 );
 
+#[cfg(not(windows))]
+dynasm!(asm
+    ; .alias arg1, rdi
+    ; .alias arg2, rsi
+    ; .alias arg3, rdx
+);
+
+/// How far to move the stack pointer before a call to keep it 16-byte aligned, including the
+/// 32 bytes of shadow space that the Microsoft x64 convention reserves for the callee.
+#[cfg(windows)]
+const CALL_STACK_ADJUSTMENT: i8 = 0x28;
+
+/// How far to move the stack pointer before a call to keep it 16-byte aligned.
+#[cfg(not(windows))]
+const CALL_STACK_ADJUSTMENT: i8 = 0x08;
+
 /// Compiles peephole-optimized AST to x64 machine code.
 ///
-/// Uses the `dynasmrt` assembler
-pub fn compile(program: &peephole::Program, checked: bool) -> Program {
-    if checked {
-        let mut compiler = Compiler::<AbstractInterpreter>::new(program, true);
+/// Uses the `dynasmrt` assembler. Of the configuration, only bounds checking matters here: the
+/// EOF mode comes from the `State` at run time.
+pub fn compile(program: &peephole::Program, config: &Config) -> Program {
+    let (code, start, relocations) = assemble(program, config, Target::Function);
+    Program { code, start, relocations: relocations.into_boxed_slice() }
+}
+
+/// Compiles peephole-optimized AST to a standalone x86-64 Linux executable.
+///
+/// The executable has the configuration’s amount of memory and does unbuffered I/O with system
+/// calls. Its exit code is [`rts::OKAY`](../rts/constant.OKAY.html),
+/// [`rts::UNDERFLOW`](../rts/constant.UNDERFLOW.html), or
+/// [`rts::OVERFLOW`](../rts/constant.OVERFLOW.html).
+pub fn write_executable<W: Write>(program: &peephole::Program, config: &Config, output: W)
+                                  -> io::Result<()> {
+    let memory_size = config.memory_size;
+    let (code, start, _) = assemble(program, config, Target::Executable(memory_size));
+    elf::write_executable(output, &code, start.0, memory_size)
+}
+
+fn assemble(program: &peephole::Program, config: &Config, target: Target)
+            -> (ExecutableBuffer, AssemblyOffset, Vec<Relocation>) {
+    if config.checked {
+        let mut compiler = Compiler::<AbstractInterpreter>::new(program, config, target);
         compiler.compile(program);
-        compiler.into_program()
+        compiler.finish()
     } else {
-        let mut compiler = Compiler::<NoAnalysis>::new(program, false);
+        let mut compiler = Compiler::<NoAnalysis>::new(program, config, target);
         compiler.compile(program);
-        compiler.into_program()
+        compiler.finish()
     }
 }
 
+/// What kind of code to generate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Target {
+    /// An [`EntryFunction`](type.EntryFunction.html), which does I/O through the run-time
+    /// system.
+    Function,
+    /// The entry point of a standalone Linux executable with the given amount of memory, which
+    /// does I/O with system calls and exits rather than returning.
+    Executable(usize),
+}
+
 /// The compiler state.
 struct Compiler<B: BoundsAnalysis> {
     /// The underlying assembler.
@@ -49,20 +123,32 @@ struct Compiler<B: BoundsAnalysis> {
     start: dynasmrt::AssemblyOffset,
     /// Whether we are emitting bounds checks.
     checked: bool,
+    /// What to store at the end of the input, for executables.
+    eof: EofMode,
+    /// What kind of code we are generating.
+    target: Target,
     /// Abstract interpreter for bounds checking analysis.
     interpreter: B,
+    /// Constant output runs, to be placed after the code.
+    constants: Vec<(DynamicLabel, Box<[u8]>)>,
+    /// Where the code refers to run-time system functions by address.
+    relocations: Vec<Relocation>,
 }
 
 impl<B: BoundsAnalysis> Compiler<B> {
-    fn new(program: &peephole::Program, checked: bool) -> Self {
+    fn new(program: &peephole::Program, config: &Config, target: Target) -> Self {
         let asm = Assembler::new().expect("Could not create assembler");
         let start = asm.offset();
 
         let mut result = Compiler {
             asm: asm,
             start: start,
-            checked: checked,
+            checked: config.checked,
+            eof: config.eof,
+            target: target,
             interpreter: B::new(program),
+            constants: Vec::new(),
+            relocations: Vec::new(),
         };
 
         result.emit_prologue();
@@ -70,26 +156,46 @@ impl<B: BoundsAnalysis> Compiler<B> {
         result
     }
 
-    fn into_program(mut self) -> Program {
+    fn finish(mut self) -> (ExecutableBuffer, AssemblyOffset, Vec<Relocation>) {
         self.emit_epilogue();
+        self.emit_constants();
 
-        Program {
-            code: self.asm.finalize().unwrap(),
-            start: self.start,
-        }
+        (self.asm.finalize().unwrap(), self.start, self.relocations)
     }
 
     fn emit_prologue(&mut self) {
+        if let Target::Executable(memory_size) = self.target {
+            dynasm!(self.asm
+                ; mov pointer, QWORD elf::TAPE_ADDRESS as i64
+                ; mov mem_start, pointer
+                ; mov mem_limit, pointer
+                ; mov rax, QWORD memory_size as i64
+                ; add mem_limit, rax
+            );
+            return;
+        }
+
+        // Pushing rbp as well as rbx keeps the stack alignment that the calls expect.
         dynasm!(self.asm
+            ; push rbx
+            ; push rbp
             ; push r12
             ; push r13
             ; push r14
             ; push r15
-            ; mov pointer, rcx      // first argument
-            ; mov mem_start, rcx
-            ; mov mem_limit, rcx
-            ; add mem_limit, rdx    // second argument
-            ; mov rts, r8           // third argument
+            ; mov pointer, arg1
+            ; mov mem_start, arg1
+            ; mov mem_limit, arg1
+            ; add mem_limit, arg2
+            ; mov rts, arg3
+
+            // Start the pointer where the RTS says, if there is one.
+            ; test rts, rts
+            ; jz >start
+            ;; self.rts_call(RtsFunction::GetPointer)
+            ; add pointer, rax
+            ; start:
+            ; mov max_pointer, pointer
         );
     }
 
@@ -104,16 +210,56 @@ impl<B: BoundsAnalysis> Compiler<B> {
 
             ; ->overflow:
             ; mov rax, rts::OVERFLOW as i32
+            ; jmp ->finish
+
+            ; ->would_block:
+            ; mov rax, rts::WOULD_BLOCK as i32
 
             ; ->finish:
+        );
+
+        if let Target::Executable(_) = self.target {
+            dynasm!(self.asm
+                ; mov rdi, rax
+                ; mov eax, 231          // exit_group
+                ; syscall
+            );
+            return;
+        }
+
+        // Report the pointers to the RTS, if there is one. The memory limit is no longer needed,
+        // so its callee-saved register keeps the result across the calls.
+        dynasm!(self.asm
+            ; test rts, rts
+            ; jz >done
+            ; mov mem_limit, rax
+            ; mov arg2, pointer
+            ; sub arg2, mem_start
+            ;; self.rts_call(RtsFunction::SetPointer)
+            ; mov arg2, max_pointer
+            ; sub arg2, mem_start
+            ;; self.rts_call(RtsFunction::SetMaxPointer)
+            ; mov rax, mem_limit
+            ; done:
             ; pop r15
             ; pop r14
             ; pop r13
             ; pop r12
+            ; pop rbp
+            ; pop rbx
             ; ret
         );
     }
 
+    fn emit_constants(&mut self) {
+        for (label, bytes) in mem::take(&mut self.constants) {
+            dynasm!(self.asm
+                ; =>label
+            );
+            self.asm.extend(bytes.iter().cloned());
+        }
+    }
+
     fn compile(&mut self, program: &[peephole::Statement]) {
         for stm in program {
             self.compile_statement(stm);
@@ -131,6 +277,7 @@ impl<B: BoundsAnalysis> Compiler<B> {
                 dynasm!(self.asm
                     ;; self.load_pos_offset(count, proved)
                     ; add pointer, rax
+                    ;; self.note_max_pointer()
                 );
             }
 
@@ -149,27 +296,130 @@ impl<B: BoundsAnalysis> Compiler<B> {
                 );
             }
 
+            Instr(In) if self.target != Target::Function => {
+                // A read interrupted by a signal is retried, as in `write_syscall`.
+                dynasm!(self.asm
+                    ; read_loop:
+                    ; xor eax, eax          // read
+                    ; xor edi, edi          // from stdin
+                    ; mov rsi, pointer
+                    ; mov edx, 1
+                    ; syscall
+                    ; cmp rax, -4           // -EINTR
+                    ; je <read_loop
+                );
+
+                // Any other short read leaves the cell unchanged.
+                match self.eof {
+                    EofMode::Zero => dynasm!(self.asm
+                        ; cmp rax, 1
+                        ; je >done
+                        ; mov BYTE [pointer], 0
+                        ; done:
+                    ),
+                    EofMode::MinusOne => dynasm!(self.asm
+                        ; cmp rax, 1
+                        ; je >done
+                        ; mov BYTE [pointer], -1
+                        ; done:
+                    ),
+                    EofMode::Unchanged => (),
+                }
+            }
+
             Instr(In) => {
                 dynasm!(self.asm
-                    ;; self.rts_call(rts::RtsState::read as _)
+                    ; movzx arg2, BYTE [pointer]
+                    ;; self.rts_call(RtsFunction::Read)
+                    ; cmp eax, rts::READ_WOULD_BLOCK as i32
+                    ; je ->would_block
                     ; mov [pointer], al
                 );
             }
 
+            Instr(Out) if self.target != Target::Function => {
+                dynasm!(self.asm
+                    ; mov rsi, pointer
+                    ; mov edx, 1
+                    ;; self.write_syscall()
+                );
+            }
+
             Instr(Out) => {
                 dynasm!(self.asm
-                    ; xor rdx, rdx
-                    ; mov dl, [pointer]
-                    ;; self.rts_call(rts::RtsState::write as _)
+                    ; movzx arg2, BYTE [pointer]
+                    ;; self.rts_call(RtsFunction::Write)
+                );
+            }
+
+            Instr(OutByte(byte)) if self.target != Target::Function => {
+                self.write_constant(vec![byte].into_boxed_slice());
+            }
+
+            Instr(OutByte(byte)) => {
+                dynasm!(self.asm
+                    ; mov arg2, DWORD byte as i32
+                    ;; self.rts_call(RtsFunction::Write)
                 );
             }
 
+            OutConst(ref bytes) if self.target != Target::Function => {
+                self.write_constant(bytes.clone());
+            }
+
+            OutConst(ref bytes) => {
+                let label = self.asm.new_dynamic_label();
+
+                dynasm!(self.asm
+                    ; lea arg2, [=>label]
+                    ; mov arg3, QWORD bytes.len() as i64
+                    ;; self.rts_call(RtsFunction::WriteBytes)
+                );
+
+                self.constants.push((label, bytes.clone()));
+            }
+
             Instr(SetZero) => {
                 dynasm!(self.asm
                     ; mov BYTE [pointer], 0
                 )
             }
 
+            Instr(FindZeroRight(skip)) if self.calls_scan(skip) => {
+                self.interpreter.reset_right();
+
+                dynasm!(self.asm
+                    ; cmp BYTE [pointer], 0
+                    ; jz >done
+                    ; mov arg1, pointer
+                    ; mov arg2, mem_limit
+                    ; mov arg3, DWORD skip as i32
+                    ;; self.native_call(RtsFunction::FindZeroRight)
+                    ; test rax, rax
+                    ; jz ->overflow
+                    ; mov pointer, rax
+                    ;; self.note_max_pointer()
+                    ; done:
+                )
+            }
+
+            Instr(FindZeroLeft(skip)) if self.calls_scan(skip) => {
+                self.interpreter.reset_left();
+
+                dynasm!(self.asm
+                    ; cmp BYTE [pointer], 0
+                    ; jz >done
+                    ; mov arg1, pointer
+                    ; mov arg2, mem_start
+                    ; mov arg3, DWORD skip as i32
+                    ;; self.native_call(RtsFunction::FindZeroLeft)
+                    ; test rax, rax
+                    ; jz ->underflow
+                    ; mov pointer, rax
+                    ; done:
+                )
+            }
+
             Instr(FindZeroRight(skip)) => {
                 self.interpreter.reset_right();
 
@@ -181,6 +431,7 @@ impl<B: BoundsAnalysis> Compiler<B> {
                     ; end_loop:
                     ; cmp BYTE [pointer], 0
                     ; jnz <begin_loop
+                    ;; self.note_max_pointer()
                 )
             }
 
@@ -208,6 +459,7 @@ impl<B: BoundsAnalysis> Compiler<B> {
                     ; mov cl, BYTE [pointer]
                     ; mov BYTE [pointer], 0
                     ; add BYTE [pointer + rax], cl
+                    ;; self.note_max_offset()
                     ; skip:
                 );
             }
@@ -229,6 +481,8 @@ impl<B: BoundsAnalysis> Compiler<B> {
 
             Instr(JumpZero(_)) | Instr(JumpNotZero(_)) =>
                 panic!("unexpected jump instruction"),
+            Instr(OutString(_)) =>
+                panic!("unexpected string instruction"),
 
             Loop(ref body) => {
                 let begin_label = self.asm.new_dynamic_label();
@@ -250,13 +504,96 @@ impl<B: BoundsAnalysis> Compiler<B> {
         }
     }
 
-    fn rts_call(&mut self, fun: i64) {
+    /// Whether to call a [`scan`](../scan/index.html) routine for a scan with the given skip.
+    ///
+    /// Standalone executables cannot call into the library, so they always scan inline.
+    fn calls_scan(&self, skip: Count) -> bool {
+        self.checked && self.target == Target::Function && scan::scans_by_word(skip as usize)
+    }
+
+    /// Raises the highest address used to the pointer, if it is higher.
+    ///
+    /// Standalone executables have no run-time system to report it to, so they skip this.
+    fn note_max_pointer(&mut self) {
+        if self.target == Target::Function {
+            dynasm!(self.asm
+                ; cmp pointer, max_pointer
+                ; cmova max_pointer, pointer
+            );
+        }
+    }
+
+    /// Raises the highest address used to the pointer plus `rax`, if it is higher.
+    fn note_max_offset(&mut self) {
+        if self.target == Target::Function {
+            dynasm!(self.asm
+                ; add rax, pointer
+                ; cmp rax, max_pointer
+                ; cmova max_pointer, rax
+            );
+        }
+    }
+
+    /// Writes the given constant bytes to stdout with system calls.
+    fn write_constant(&mut self, bytes: Box<[u8]>) {
+        let label = self.asm.new_dynamic_label();
+
+        dynasm!(self.asm
+            ; lea rsi, [=>label]
+            ; mov rdx, QWORD bytes.len() as i64
+            ;; self.write_syscall()
+        );
+
+        self.constants.push((label, bytes));
+    }
+
+    /// Writes `rdx` bytes starting at `rsi` to stdout with system calls, clobbering both.
+    ///
+    /// Short writes continue with the rest, and writes interrupted by a signal are retried. Any
+    /// other failure, or a write that makes no progress, abandons the output, since there is
+    /// nowhere to report it.
+    fn write_syscall(&mut self) {
+        dynasm!(self.asm
+            ; write_loop:
+            ; mov eax, 1            // write
+            ; mov edi, 1            // to stdout
+            ; syscall
+            ; cmp rax, -4           // -EINTR
+            ; je <write_loop
+            ; test rax, rax
+            ; jle >write_done
+            ; add rsi, rax
+            ; sub rdx, rax
+            ; jnz <write_loop
+            ; write_done:
+        );
+    }
+
+    /// Calls an RTS method, passing the RTS state as the first argument.
+    fn rts_call(&mut self, fun: RtsFunction) {
+        dynasm!(self.asm
+            ; mov arg1, rts
+            ;; self.native_call(fun)
+        );
+    }
+
+    /// Calls a function whose arguments are already in place.
+    ///
+    /// The function’s address is an immediate operand, so we record a relocation for it.
+    fn native_call(&mut self, fun: RtsFunction) {
+        dynasm!(self.asm
+            ; mov rax, QWORD fun.address() as i64
+        );
+
+        self.relocations.push(Relocation {
+            offset: self.asm.offset().0 - 8,
+            function: fun,
+        });
+
         dynasm!(self.asm
-            ; mov rax, QWORD fun
-            ; mov rcx, rts
-            ; sub rsp, BYTE 0x28
+            ; sub rsp, BYTE CALL_STACK_ADJUSTMENT
             ; call rax
-            ; add rsp, BYTE 0x28
+            ; add rsp, BYTE CALL_STACK_ADJUSTMENT
         );
     }
 
@@ -303,7 +640,7 @@ impl<B: BoundsAnalysis> Compiler<B> {
 }
 
 impl JitCompilable for peephole::Program {
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, _config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R
     {
         k(self)
@@ -311,9 +648,9 @@ impl JitCompilable for peephole::Program {
 }
 
 impl<T: peephole::PeepholeCompilable + ?Sized> JitCompilable for T {
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R
     {
-        k(&self.peephole_compile())
+        k(&self.peephole_compile(config))
     }
 }
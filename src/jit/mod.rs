@@ -9,19 +9,34 @@
 //! memory bounds checking in the generated code. Note that this runs Brainfuck in
 //! unsafe mode, which means that programs that move the pointer outside the allocated
 //! memory will access and possibly overwrite arbitrary memory locations.
+//!
+//! The same code generator can also produce a standalone x86-64 Linux executable, which does
+//! its own I/O with system calls; see [`write_executable`](fn.write_executable.html), or pass
+//! `--compile -o FILE` to `bfi`.
+//!
+//! In checked mode, scans such as `[>]` call into the [`scan`](../scan/index.html) routines,
+//! which examine memory a word at a time, rather than looping over single bytes.
+//!
+//! Compiled programs can be saved and loaded again, which `bfi --cache-dir DIR` uses to skip
+//! compilation for programs it has seen before; see the [`cache`](cache/index.html) module.
+//!
+//! The generated function follows the platform’s C calling convention (System V AMD64, or
+//! Microsoft x64 on Windows), so it can be called from foreign code as well; see
+//! [`EntryFunction`](type.EntryFunction.html).
 
-mod loop_balance;
-mod analysis;
 mod compiler;
+pub mod cache;
 
-pub use self::compiler::{compile, JitCompilable};
+pub use self::compiler::{compile, write_executable, JitCompilable};
+pub use self::cache::compile_cached;
 
-use std::io::{Read, Write};
 use std::mem;
 
 use dynasmrt;
 
+use channel::Channel;
 use common::{BfResult, Error};
+use config::{CellWidth, TapeMode};
 use rts::{self, RtsState};
 use state::State;
 use traits::Interpretable;
@@ -36,38 +51,80 @@ use traits::Interpretable;
 pub struct Program {
     code: dynasmrt::ExecutableBuffer,
     start: dynasmrt::AssemblyOffset,
+    relocations: Box<[cache::Relocation]>,
 }
 
 /// The type of function that we will assemble and then call.
 ///
+/// In C, this is
+///
+/// ```c
+/// uint64_t entry(uint8_t *memory, uint64_t memory_size, void *rts_state);
+/// ```
+///
+/// It returns [`rts::OKAY`](../rts/constant.OKAY.html),
+/// [`rts::UNDERFLOW`](../rts/constant.UNDERFLOW.html),
+/// [`rts::OVERFLOW`](../rts/constant.OVERFLOW.html), or
+/// [`rts::WOULD_BLOCK`](../rts/constant.WOULD_BLOCK.html). `memory` must point to `memory_size`
+/// bytes (or, for unchecked code, as many as the program uses).
+///
 /// # Parameters
 ///
 /// `<'a>` – the lifetime of the channel references in the run-time system state.
 ///
-/// `memory` – the address of the beginning of memory (also where the pointer starts).
+/// `memory` – the address of the beginning of memory.
 ///
 /// `memory_size` – the amount of memory allocated, defaults to 30,000 bytes.
 ///
-/// `rts_state` – the state that the run-time system needs to do I/O.
-type EntryFunction<'a> = extern "win64" fn(memory: *mut u8,
-                                           memory_size: u64,
-                                           rts_state: *mut RtsState<'a>) -> u64;
+/// `rts_state` – the state that the run-time system needs to do I/O. Foreign code must get this
+/// from Rust, as with [`RtsState::new`](../rts/struct.RtsState.html#method.new), since the
+/// generated code passes it to the run-time system’s I/O functions. The program starts the
+/// pointer at the offset from
+/// [`RtsState::get_pointer`](../rts/struct.RtsState.html#method.get_pointer), and reports the
+/// final pointer with
+/// [`RtsState::set_pointer`](../rts/struct.RtsState.html#method.set_pointer) and the highest
+/// address it used with
+/// [`RtsState::set_max_pointer`](../rts/struct.RtsState.html#method.set_max_pointer). It may be
+/// null for a program that does no I/O, in which case the pointer starts at `memory`.
+pub type EntryFunction<'a> = unsafe extern "C" fn(memory: *mut u8,
+                                                  memory_size: u64,
+                                                  rts_state: *mut RtsState<'a>) -> u64;
 
+impl Program {
+    /// The compiled code as a function, which can be passed to foreign code.
+    ///
+    /// The function is valid only as long as `self` is alive.
+    pub fn entry_function<'a>(&self) -> EntryFunction<'a> {
+        unsafe { mem::transmute(self.code.ptr(self.start)) }
+    }
+}
+
+/// JIT-compiled programs honor the state’s EOF mode, memory contents, and pointer, but fail with
+/// `Unsupported` if it has a growable tape, a step limit, or cells wider than 8 bits.
 impl Interpretable for Program {
-    fn interpret_state<R: Read, W: Write>(&self, mut state: State,
-                                          mut input: R, mut output: W)
-                                          -> BfResult<()>
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, mut channel: &mut C)
+        -> BfResult<()>
     {
-        let mut rts = RtsState::new(&mut input, &mut output);
+        if state.tape_mode() != TapeMode::Fixed || state.steps_remaining().is_some()
+            || state.cell_width() != CellWidth::Eight
+        {
+            return Err(Error::Unsupported);
+        }
+
+        let mut rts = RtsState::with_eof(&mut channel, state.eof_mode());
+        rts.start_at(state.pointer());
 
-        let f: EntryFunction = unsafe { mem::transmute(self.code.ptr(self.start)) };
+        let f = self.entry_function();
 
-        let result = f(state.as_mut_ptr(), state.capacity() as u64, &mut rts);;
+        let result = unsafe { f(state.as_mut_ptr(), state.capacity() as u64, &mut rts) };
+        state.set_pointer(rts.pointer());
+        state.set_max_pointer(rts.max_pointer());
 
         match result {
             rts::OKAY      => Ok(()),
             rts::UNDERFLOW => Err(Error::PointerUnderflow),
             rts::OVERFLOW  => Err(Error::PointerOverflow),
+            rts::WOULD_BLOCK => Err(Error::WouldBlock),
             _ => panic!(format!("Unknown result code: {}", result)),
         }
     }
@@ -76,7 +133,11 @@ impl Interpretable for Program {
 #[cfg(test)]
 mod tests {
     use test_helpers::*;
+    use channel::{BufferedChannel, Channel};
     use common::{BfResult, Error};
+    use config::{Config, EofMode, TapeMode};
+    use state::State;
+    use traits::Interpretable;
 
     #[test]
     fn move_right_once() {
@@ -116,11 +177,136 @@ mod tests {
         assert_parse_interpret(FACTOR_SRC, "100\n", Ok("100: 2 2 5 5\n"));
     }
 
+    #[test]
+    fn eof_modes() {
+        let program = ::ast::parse_program(b"+,+.").unwrap();
+        let program = ::jit::compile(&::peephole::compile(&::rle::compile(&program),
+                                                          &Config::new()),
+                                     &Config::new());
+
+        for &(eof, expected) in &[(EofMode::Zero, 1), (EofMode::MinusOne, 0),
+                                  (EofMode::Unchanged, 2)] {
+            assert_eq!(program.interpret_memory(&Config::new().eof(eof), b""), Ok(vec![expected]));
+        }
+    }
+
+    #[test]
+    fn unsupported_configurations() {
+        let program = ::ast::parse_program(b"+").unwrap();
+        let program = ::jit::compile(&::peephole::compile(&::rle::compile(&program),
+                                                          &Config::new()),
+                                     &Config::new());
+
+        assert_eq!(program.interpret_memory(&Config::new().step_limit(10), b""),
+                   Err(Error::Unsupported));
+        assert_eq!(program.interpret_memory(&Config::new().tape_mode(TapeMode::Growable), b""),
+                   Err(Error::Unsupported));
+    }
+
+    #[test]
+    fn starts_at_the_state_pointer() {
+        let program = ::ast::parse_program(b".<+").unwrap();
+        let program = ::jit::compile(&::peephole::compile(&::rle::compile(&program),
+                                                          &Config::new()),
+                                     &Config::new());
+        let mut state = State::from_bytes(&[0, 7], 1);
+        let mut output = Vec::new();
+
+        {
+            let mut channel = BufferedChannel::new(&b""[..], &mut output);
+            assert_eq!(program.interpret_in_place(&mut state, &mut channel), Ok(()));
+            channel.flush();
+        }
+
+        assert_eq!(output, [7]);
+        assert_eq!(state.pointer(), 0);
+        assert_eq!(&state.as_bytes()[.. 2], &[1, 7]);
+    }
+
+    #[test]
+    fn entry_function_takes_c_arguments() {
+        use std::ptr;
+        use rts;
+
+        let program = ::ast::parse_program(b"+++>++++<[->+<]>").unwrap();
+        let program = ::peephole::compile(&::rle::compile(&program), &Config::new());
+        let program = ::jit::compile(&program, &Config::new());
+        let f = program.entry_function();
+
+        let mut memory = [0u8; 2];
+        assert_eq!(unsafe { f(memory.as_mut_ptr(), 2, ptr::null_mut()) }, rts::OKAY);
+        assert_eq!(memory, [0, 7]);
+
+        let mut memory = [0u8; 1];
+        assert_eq!(unsafe { f(memory.as_mut_ptr(), 1, ptr::null_mut()) }, rts::OVERFLOW);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn standalone_executable() {
+        assert_parse_execute(b"<", "", Err(Error::PointerUnderflow));
+        assert_parse_execute(b"+[>+]", "", Err(Error::PointerOverflow));
+        assert_parse_execute(b",+.", "A", Ok("B"));
+        assert_parse_execute(HELLO_WORLD_SRC, "", Ok("Hello, World!"));
+        assert_parse_execute(FACTOR_SRC, "100\n", Ok("100: 2 2 5 5\n"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn standalone_executable_eof_modes() {
+        assert_parse_execute_config(b"+,+.", "", Ok("\x01"), &Config::new());
+        assert_parse_execute_config(b"+,+.", "", Ok("\x00"),
+                                    &Config::new().eof(EofMode::MinusOne));
+        assert_parse_execute_config(b"+,+.", "", Ok("\x02"),
+                                    &Config::new().eof(EofMode::Unchanged));
+    }
+
+    #[cfg(target_os = "linux")]
+    fn assert_parse_execute(program: &[u8], input: &str, output: BfResult<&str>) {
+        assert_parse_execute_config(program, input, output, &Config::new());
+    }
+
+    #[cfg(target_os = "linux")]
+    fn assert_parse_execute_config(program: &[u8], input: &str, output: BfResult<&str>,
+                                   config: &Config) {
+        use std::fs::{self, File};
+        use std::io::Write;
+        use std::os::unix::fs::PermissionsExt;
+        use std::process::{Command, Stdio};
+        use rts;
+
+        let program = ::ast::parse_program(program).unwrap();
+        let program = ::rle::compile(&program);
+        let program = ::peephole::compile(&program, config);
+
+        let path = ::std::env::temp_dir()
+            .join(format!("bf-jit-test-{}", ::std::process::id()));
+        ::jit::write_executable(&program, config, File::create(&path).unwrap()).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut child = Command::new(&path)
+            .stdin(Stdio::piped()).stdout(Stdio::piped())
+            .spawn().unwrap();
+        // The program may exit without reading its input.
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        let result = child.wait_with_output().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let actual = match result.status.code().unwrap() as u64 {
+            rts::OKAY      => Ok(result.stdout),
+            rts::UNDERFLOW => Err(Error::PointerUnderflow),
+            rts::OVERFLOW  => Err(Error::PointerOverflow),
+            code => panic!("Unknown exit code: {}", code),
+        };
+
+        assert_eq!(actual, output.map(|s| s.as_bytes().to_vec()));
+    }
+
     fn assert_parse_interpret(program: &[u8], input: &str, output: BfResult<&str>) {
         let program = ::ast::parse_program(program).unwrap();
         let program = ::rle::compile(&program);
-        let program = ::peephole::compile(&program);
-        let program = ::jit::compile(&program, true);
+        let program = ::peephole::compile(&program, &Config::new());
+        let program = ::jit::compile(&program, &Config::new());
         assert_interpret_result(&program, input.as_bytes(), output.map(|s| s.as_bytes()));
     }
 }
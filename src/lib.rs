@@ -6,7 +6,7 @@
 //! and an executable `bfi` that provides a command-line interface for executing
 //! Brainfuck programs.
 //!
-//! This crate supports Rust version 1.20 and later. However,
+//! This crate supports Rust version 1.73 and later. However,
 //! by default, installing `bf` does not enable the JIT compiler because
 //! that requires nightly Rust. To build and install from crates.io with the native x86-64
 //! JIT enabled:
@@ -20,27 +20,37 @@
 //! This library implements a number of compilation passes:
 //!
 //!  - First, Brainfuck concrete syntax is parsed into
-//! [an abstract syntax tree](ast/index.html).
+//!    [an abstract syntax tree](ast/index.html).
 //!
 //!  - Then, repeated sequences of the same command are
-//! [run-length encoded](rle/index.html).
+//!    [run-length encoded](rle/index.html).
 //!
 //!  - Then, common loop forms are converted to new (non-Brainfuck)
-//! instructions by the [peephole optimizer](peephole/index.html).
+//!    instructions by the [peephole optimizer](peephole/index.html).
 //!
 //!  - The peephole output can be [flattened to bytecode](bytecode/index.html),
-//! which is then interpreted.
+//!    which is then interpreted.
+//!
+//!  - The bytecode can be [decoded into threaded code](threaded/index.html) for an
+//!    interpreter that does no decoding at run time, or
+//!    [packed into a byte stream](compact/index.html) with superinstructions.
 //!
 //!  - Or, if the `jit` feature is enabled (nightly only), the peephole output
-//! can be [just-in-time compiled to x64 machine code](jit/index.html).
+//!    can be [just-in-time compiled to x64 machine code](jit/index.html).
 //!
 //!  - Or, if the `llvm` feature is enabled (LLVM ≥ 3.8 must be in the PATH to build),
-//! the peephole output can be [JIT compiled using LLVM](llvm/index.html).
-//! (This is quite slow right now.)
+//!    the peephole output can be [JIT compiled using LLVM](llvm/index.html).
+//!    (This is quite slow right now.)
+//!
+//!  - Finally, the peephole output can be translated [to C](c/index.html),
+//!    [to Rust](rust/index.html), or [to WebAssembly](wasm/index.html), to be compiled
+//!    ahead of time.
 //!
 //! Interpreters are provided for the intermediate forms as well. In particular,
 //! all representations of Brainfuck programs implement the
-//! [`Interpretable`](traits/trait.Interpretable.html) trait.
+//! [`Interpretable`](traits/trait.Interpretable.html) trait. To choose a form at run time,
+//! [`engine::Backend`](engine/enum.Backend.html) compiles to any of them, returning a
+//! [`Runnable`](traits/trait.Runnable.html) trait object.
 
 #![cfg_attr(feature = "jit", feature(plugin))]
 #![cfg_attr(feature = "jit", plugin(dynasm))]
@@ -51,15 +61,31 @@ extern crate dynasmrt;
 #[cfg(feature = "llvm")]
 extern crate llvm_sys;
 
+#[cfg(feature = "async")]
+extern crate futures_io;
+
 pub mod common;
+pub mod config;
+pub mod channel;
 pub mod state;
+pub mod tape;
+pub mod repl;
 pub mod traits;
 pub mod rts;
+pub mod scan;
+pub mod elf;
+pub mod engine;
 
 pub mod ast;
 pub mod rle;
 pub mod bytecode;
 pub mod peephole;
+pub mod analysis;
+pub mod threaded;
+pub mod compact;
+pub mod c;
+pub mod rust;
+pub mod wasm;
 
 #[cfg(feature = "jit")]
 pub mod jit;
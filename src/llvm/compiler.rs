@@ -1,92 +1,162 @@
-use std::io;
+use std::cell::Cell;
 
-use common::{BfResult, Error, Count};
-use rts::{self, RtsState};
-use state::DEFAULT_CAPACITY;
+use analysis::{BoundsAnalysis, AbstractInterpreter, NoAnalysis};
+use common::Count;
+use config::{Config, EofMode};
+use rts;
 use peephole;
 
+use super::Program;
 use super::wrapper::*;
 
-/// Program forms that can be compiled and run via LLVM.
+/// Program forms that can be compiled via LLVM.
 pub trait LlvmCompilable {
     /// Compile the given program into the peephole AST to prepare for LLVM compilation.
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R;
 
-    /// JIT compile and run the given program via LLVM.
-    fn llvm_run(&self, memory_size: Option<usize>) -> BfResult<()> {
-        let mut stdin = io::stdin();
-        let mut stdout = io::stdout();
-        let rts_state = RtsState::new(&mut stdin, &mut stdout);
-        self.with_peephole(|ast| compile_and_run(ast, memory_size, false, rts_state))
+    /// JIT compile the given program via LLVM.
+    fn llvm_compile(&self, config: &Config) -> Program {
+        self.with_peephole(config, |ast| compile(ast, config))
+    }
+
+    /// Compile the given program via LLVM to a file of the given format.
+    fn llvm_emit(&self, config: &Config, format: OutputFormat) -> Result<Vec<u8>, String> {
+        // The runtime allocates memory with `calloc`, so it starts out zero.
+        let config = &config.fresh_memory(true);
+        self.with_peephole(config, |ast| compile_to(ast, config, format))
+    }
+}
+
+/// The kinds of file that LLVM can write.
+///
+/// Each defines the C-ABI function
+///
+/// ```c
+/// uint64_t bfi_main(uint8_t *memory,
+///                   uint64_t memory_size,
+///                   void *rts_state,
+///                   uint32_t (*read)(void *rts_state, uint8_t current),
+///                   void (*write)(void *rts_state, uint8_t byte),
+///                   void (*write_bytes)(void *rts_state, const uint8_t *bytes, uint64_t len),
+///                   void (*set_pointer)(void *rts_state, uint64_t pointer),
+///                   void (*set_max_pointer)(void *rts_state, uint64_t max_pointer));
+/// ```
+///
+/// which returns one of the [`rts`](../rts/index.html) status codes, where `read` is given the
+/// current value of the cell it reads into and returns either a byte or
+/// [`rts::READ_WOULD_BLOCK`](../rts/constant.READ_WOULD_BLOCK.html), `set_pointer` is told
+/// the final pointer, and `set_max_pointer` is told the highest address used. It also defines
+/// the constants `uint64_t bfi_memory_size`, which is the requested amount of memory, and
+/// `uint64_t bfi_eof`, which is 0, 1, or 2 for storing 0, storing 255, or leaving the cell
+/// unchanged at the end of the input. To get an executable, link an object file with
+/// [`RUNTIME_SOURCE`](constant.RUNTIME_SOURCE.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Textual LLVM IR (`.ll`).
+    Ir,
+    /// LLVM bitcode (`.bc`).
+    Bitcode,
+    /// Native assembly (`.s`).
+    Assembly,
+    /// A native object file (`.o`).
+    Object,
+}
+
+/// JIT compiles peephole-optimized AST via LLVM.
+///
+/// Of the configuration, only bounds checking matters here: the EOF mode comes from the `State`
+/// at run time.
+///
+/// # Panics
+///
+/// Panics if LLVM cannot create an execution engine for the host.
+pub fn compile(program: &peephole::Program, config: &Config) -> Program {
+    let context = Context::new();
+    let engine = build_module(&context, program, config.checked).into_engine()
+        .expect("Could not create LLVM execution engine");
+    let entry = engine.function_address("bfi_main");
+
+    Program::new(engine, context, entry)
+}
+
+/// Compiles peephole-optimized AST via LLVM to a file of the given format.
+pub fn compile_to(program: &peephole::Program, config: &Config, format: OutputFormat)
+                  -> Result<Vec<u8>, String> {
+    let eof = match config.eof {
+        EofMode::Zero => 0,
+        EofMode::MinusOne => 1,
+        EofMode::Unchanged => 2,
+    };
+
+    let context = Context::new();
+    let module = build_module(&context, program, config.checked);
+    module.add_u64_constant("bfi_memory_size", config.memory_size as u64);
+    module.add_u64_constant("bfi_eof", eof);
+    module.verify()?;
+
+    match format {
+        OutputFormat::Ir       => Ok(module.print_to_string().into_bytes()),
+        OutputFormat::Bitcode  => Ok(module.write_bitcode()),
+        OutputFormat::Assembly => module.emit(LLVMCodeGenFileType::LLVMAssemblyFile),
+        OutputFormat::Object   => module.emit(LLVMCodeGenFileType::LLVMObjectFile),
+    }
+}
+
+/// Compiles and optimizes peephole-optimized AST into an LLVM module.
+fn build_module<'a>(context: &'a Context, program: &peephole::Program, checked: bool)
+                    -> Module<'a> {
+    if checked {
+        Compiler::<AbstractInterpreter>::new(context, program, true).finish(program)
+    } else {
+        Compiler::<NoAnalysis>::new(context, program, false).finish(program)
     }
 }
 
 /// State required for the LLVM compiler.
-struct Compiler<'a> {
+struct Compiler<'a, B: BoundsAnalysis> {
     /// The LLVM context
     context:        &'a Context,
     /// The main module
     module:         Module<'a>,
     /// A builder positioned at the current end of the program
     builder:        Builder<'a>,
-    /// Label to jump to for pointer underflow
-    underflow:      BasicBlock<'a>,
-    /// Label to jump to for pointer overflow
-    overflow:       BasicBlock<'a>,
+    /// Label to jump to to stop, which reports the pointer and returns the status
+    exit:           BasicBlock<'a>,
+    /// The phi node for the status that `exit` returns
+    exit_status:    Value<'a>,
+    /// The phi node for the pointer that `exit` reports
+    exit_pointer:   Value<'a>,
+    /// The stack slot for the highest address used, which `exit` also reports
+    max_pointer:    Value<'a>,
+    /// Whether we are emitting bounds checks
+    checked:        bool,
+    /// Abstract interpreter for bounds checking analysis
+    interpreter:    B,
     /// The size of memory, for bounds checks
     memory_size:    Value<'a>,
     /// The main function
     main_function:  Value<'a>,
     /// &RtsState<'a>
     rts_state:      Value<'a>,
-    /// RtsState::read_c
+    /// RtsState::read
     read_function:  Value<'a>,
-    /// RtsSate::write_c
+    /// RtsState::write
     write_function: Value<'a>,
+    /// RtsState::write_bytes
+    write_bytes:    Value<'a>,
+    /// `memchr` from the C library, for scans right
+    memchr:         Value<'a>,
+    /// `memrchr` from the C library, for scans left
+    memrchr:        Value<'a>,
     /// The program’s memory (“tape”)
     memory:         Value<'a>,
-    /// The current offset into memory
-    pointer:        Value<'a>,
-}
-
-/// JIT compile and run the given program via LLVM.
-pub fn compile_and_run<'a>(program: &peephole::Program, memory_size: Option<usize>, debug: bool,
-                           mut rts_state: RtsState<'a>) -> BfResult<()> {
-    let context = Context::new();
-
-    let compiler = Compiler::prologue(&context, memory_size.unwrap_or(DEFAULT_CAPACITY) as u64);
-    compiler.compile_block(program);
-    compiler.epilogue();
-
-    compiler.module.optimize(3, 0);
-
-    if debug {
-        compiler.module.dump();
-        compiler.module.verify().unwrap();
-    }
-
-    // This panics if LLVM fails.
-    let result = unsafe {
-        compiler.module.with_function("bfi_main",
-                                      |f: extern fn(rts_state: &mut RtsState<'a>,
-                                                    read: extern fn(&mut RtsState<'a>) -> u8,
-                                                    write: extern fn(&mut RtsState<'a>, u8) -> ())
-                                                        -> u64| {
-                                          f(&mut rts_state, RtsState::read_c, RtsState::write_c)
-                                      }).unwrap()
-    };
-
-    match result {
-        rts::OKAY       => Ok(()),
-        rts::UNDERFLOW  => Err(Error::PointerUnderflow),
-        rts::OVERFLOW   => Err(Error::PointerOverflow),
-        _ => panic!("unrecognized error code"),
-    }
+    /// The SSA value holding the current offset into memory
+    pointer:        Cell<Value<'a>>,
 }
 
-impl<'a> Compiler<'a> {
-    fn compile_block(&self, body: &[peephole::Statement]) {
+impl<'a, B: BoundsAnalysis> Compiler<'a, B> {
+    fn compile_block(&mut self, body: &[peephole::Statement]) {
         use peephole::Statement::*;
         use common::Instruction::*;
 
@@ -95,13 +165,16 @@ impl<'a> Compiler<'a> {
         for statement in body {
             match *statement {
                 Instr(Right(count)) => {
-                    let new_pointer = self.load_pos_offset(count, "new_pointer");
-                    builder.store(new_pointer, self.pointer);
+                    let proved = self.interpreter.move_right(count);
+                    let new_pointer = self.pos_offset(count, proved, "pointer");
+                    self.pointer.set(new_pointer);
+                    self.note_max_pointer(new_pointer);
                 }
 
                 Instr(Left(count)) => {
-                    let new_pointer = self.load_neg_offset(count, "new_pointer");
-                    builder.store(new_pointer, self.pointer);
+                    let proved = self.interpreter.move_left(count);
+                    let new_pointer = self.neg_offset(count, proved, "pointer");
+                    self.pointer.set(new_pointer);
                 }
 
                 Instr(Add(count)) => {
@@ -112,8 +185,16 @@ impl<'a> Compiler<'a> {
                 }
 
                 Instr(In) => {
-                    let result = builder.call(self.read_function, &[self.rts_state], "");
-                    self.store_data(result);
+                    let current = self.load_data("current");
+                    let result = builder.call(self.read_function, &[self.rts_state, current],
+                                              "result");
+                    let blocked = builder.cmp(LLVMIntPredicate::LLVMIntEQ, result,
+                                              Value::get_u32(self.context, rts::READ_WOULD_BLOCK),
+                                              "blocked");
+                    self.exit_if(blocked, rts::WOULD_BLOCK, "read_success");
+
+                    let byte = builder.trunc(result, Type::get_i8(self.context), "byte");
+                    self.store_data(byte);
                 }
 
                 Instr(Out) => {
@@ -121,151 +202,226 @@ impl<'a> Compiler<'a> {
                     builder.call(self.write_function, &[self.rts_state, argument], "");
                 }
 
+                Instr(OutByte(byte)) => {
+                    let argument = Value::get_u8(self.context, byte);
+                    builder.call(self.write_function, &[self.rts_state, argument], "");
+                }
+
+                OutConst(ref bytes) => {
+                    let string = self.module.add_bytes_constant("string", bytes);
+                    let len = Value::get_u64(self.context, bytes.len() as u64);
+                    builder.call(self.write_bytes, &[self.rts_state, string, len], "");
+                }
+
                 Instr(SetZero) => {
                     self.store_data(Value::get_u8(self.context, 0));
                 }
 
+                Instr(FindZeroRight(1)) => {
+                    self.interpreter.reset_right();
+                    self.memchr_right();
+                    self.note_max_pointer(self.pointer.get());
+                }
+
                 Instr(FindZeroRight(count)) => {
-                    let instr = Loop(vec![Instr(Right(count))].into_boxed_slice());
-                    self.compile_block(&[instr]);
+                    self.interpreter.reset_right();
+                    self.find_zero(|this| this.pos_offset(count, false, "pointer"));
+                    self.note_max_pointer(self.pointer.get());
+                }
+
+                Instr(FindZeroLeft(1)) => {
+                    self.interpreter.reset_left();
+                    self.memrchr_left();
                 }
 
                 Instr(FindZeroLeft(count)) => {
-                    let instr = Loop(vec![Instr(Left(count))].into_boxed_slice());
-                    self.compile_block(&[instr]);
+                    self.interpreter.reset_left();
+                    self.find_zero(|this| this.neg_offset(count, false, "pointer"));
                 }
 
                 Instr(OffsetAddRight(count)) => {
-                    let do_it = self.main_function.append("do_it");
-                    let after = self.main_function.append("after");
-
-                    self.if_not0(do_it, after);
-
-                    builder.position_at_end(do_it);
-                    let pointer = self.load_pos_offset(count, "offset_ptr");
-                    let to_add = self.load_data("to_add");
-                    self.store_data(Value::get_u8(self.context, 0));
-                    let add_to = self.load_data_at(pointer, "add_to");
-                    let sum = builder.add(to_add, add_to, "sum");
-                    self.store_data_at(pointer, sum);
-                    builder.br(after);
-
-                    builder.position_at_end(after);
+                    let proved = self.interpreter.check_right(count);
+                    self.offset_add(count, proved, true);
                 }
 
                 Instr(OffsetAddLeft(count)) => {
-                    let do_it = self.main_function.append("do_it");
-                    let after = self.main_function.append("after");
-
-                    self.if_not0(do_it, after);
-
-                    builder.position_at_end(do_it);
-                    let pointer = self.load_neg_offset(count, "offset_ptr");
-                    let to_add = self.load_data("to_add");
-                    self.store_data(Value::get_u8(self.context, 0));
-                    let add_to = self.load_data_at(pointer, "add_to");
-                    let sum = builder.add(to_add, add_to, "sum");
-                    self.store_data_at(pointer, sum);
-                    builder.br(after);
-
-                    builder.position_at_end(after);
+                    let proved = self.interpreter.check_left(count);
+                    self.offset_add(count, proved, false);
                 }
 
                 Instr(JumpZero(_)) | Instr(JumpNotZero(_)) =>
                     panic!("unexpected instruction"),
+                Instr(OutString(_)) =>
+                    panic!("unexpected string instruction"),
 
                 Loop(ref body) => {
                     let header = self.main_function.append("loop_header");
                     let true_  = self.main_function.append("loop_body");
                     let false_ = self.main_function.append("after_loop");
 
-                    builder.br(header);
-
-                    builder.position_at_end(header);
+                    let phi = self.enter_ssa_loop(header);
                     self.if_not0(true_, false_);
 
+                    self.interpreter.enter_loop(body);
+
                     builder.position_at_end(true_);
                     self.compile_block(body);
-                    builder.br(header);
+                    self.leave_ssa_loop(header, phi);
+
+                    self.interpreter.leave_loop();
 
                     builder.position_at_end(false_);
+                    self.pointer.set(phi);
                 }
             }
         }
     }
 
     /// Set up compilation.
-    fn prologue(context: &'a Context, memory_size: u64) -> Self {
+    fn new(context: &'a Context, program: &peephole::Program, checked: bool) -> Self {
         let module = Module::new(context, "bfi_module");
 
         // Some useful types
         let i64_type        = Type::get_i64(context);
         let i32_type        = Type::get_i32(context);
         let i8_type         = Type::get_i8(context);
-        let bool_type       = Type::get_bool(context);
         let void_type       = Type::get_void(context);
         let char_ptr_type   = Type::get_pointer(i8_type);
 
-        // The size of memory as an LLVM Value
-        let memory_size = Value::get_u64(context, memory_size);
-
         let rts_state_type = Type::get_pointer(Type::get_void(context));
         let write_function_type = Type::get_function(&[rts_state_type, i8_type], void_type);
-        let read_function_type = Type::get_function(&[rts_state_type], i8_type);
+        let write_bytes_function_type = Type::get_function(&[rts_state_type, char_ptr_type,
+                                                             i64_type], void_type);
+        let read_function_type = Type::get_function(&[rts_state_type, i8_type], i32_type);
+        let set_pointer_function_type = Type::get_function(&[rts_state_type, i64_type],
+                                                           void_type);
+        let set_max_pointer_function_type = Type::get_function(&[rts_state_type, i64_type],
+                                                               void_type);
+
+        // void *memchr(const void *s, int c, size_t n), and likewise memrchr.
+        let memchr_type = Type::get_function(&[char_ptr_type, i32_type, i64_type],
+                                             char_ptr_type);
 
         // Create the main function, create an entry basic block, and position a builder at entry.
         let main_function_type = Type::get_function(&[
+            char_ptr_type,
+            i64_type,
             rts_state_type,
             Type::get_pointer(read_function_type),
-            Type::get_pointer(write_function_type)], i64_type);
+            Type::get_pointer(write_function_type),
+            Type::get_pointer(write_bytes_function_type),
+            Type::get_pointer(set_pointer_function_type),
+            Type::get_pointer(set_max_pointer_function_type)], i64_type);
         let main_function  = module.add_function("bfi_main", main_function_type);
         let entry_bb = main_function.append("entry");
         let builder = Builder::new(context);
+
+        // The highest address used lives on the stack, which optimization turns into SSA.
+        builder.position_at_end(entry_bb);
+        let max_pointer = builder.alloca(i64_type, "max_pointer");
+        builder.store(Value::get_u64(context, 0), max_pointer);
+
+        // Every way out goes through the exit block, which reports the pointers to the run-time
+        // system and returns the status. Each branch there adds its values to the phi nodes.
+        let exit = main_function.append("exit");
+        builder.position_at_end(exit);
+        let exit_status = builder.phi(i64_type, "status");
+        let exit_pointer = builder.phi(i64_type, "pointer");
+        builder.call(main_function.get_fun_param(6),
+                     &[main_function.get_fun_param(2), exit_pointer], "");
+        let exit_max_pointer = builder.load(max_pointer, "max_pointer");
+        builder.call(main_function.get_fun_param(7),
+                     &[main_function.get_fun_param(2), exit_max_pointer], "");
+        builder.ret(exit_status);
+
         builder.position_at_end(entry_bb);
 
-        // All state for the compiler.
-        let compiler = Compiler {
+        // All state for the compiler. The data pointer starts at 0.
+        Compiler {
             context:        context,
             module:         module,
             builder:        builder,
-            underflow:      main_function.append("underflow"),
-            overflow:       main_function.append("overflow"),
-            memory_size:    memory_size,
+            exit:           exit,
+            exit_status:    exit_status,
+            exit_pointer:   exit_pointer,
+            max_pointer:    max_pointer,
+            checked:        checked,
+            interpreter:    B::new(program),
             main_function:  main_function,
-            pointer:        builder.alloca(i64_type, "pointer"),
-            memory:         builder.array_alloca(i8_type, memory_size, "memory"),
-            rts_state:      main_function.get_fun_param(0),
-            read_function:  main_function.get_fun_param(1),
-            write_function: main_function.get_fun_param(2),
-        };
+            memchr:         module.add_function("memchr", memchr_type),
+            memrchr:        module.add_function("memrchr", memchr_type),
+            memory:         main_function.get_fun_param(0),
+            memory_size:    main_function.get_fun_param(1),
+            rts_state:      main_function.get_fun_param(2),
+            read_function:  main_function.get_fun_param(3),
+            write_function: main_function.get_fun_param(4),
+            write_bytes:    main_function.get_fun_param(5),
+            pointer:        Cell::new(Value::get_u64(context, 0)),
+        }
+    }
 
-        // Zero-initialize the memory
-        let memset_type = Type::get_function(&[char_ptr_type, i8_type, i64_type, i32_type, bool_type],
-                                             void_type);
-        let memset = compiler.module.add_function("llvm.memset.p0i8.i64", memset_type);
-        builder.call(memset,
-                     &[compiler.memory,
-                         Value::get_u8(context, 0),
-                         compiler.memory_size,
-                         Value::get_u32(context, 0),
-                         Value::get_bool(context, false)],
-                     "");
-
-        // Start the data pointer at 0.
-        builder.store(Value::get_u64(context, 0), compiler.pointer);
-
-        compiler
+    /// Compile the program, finish up, and optimize.
+    fn finish(mut self, program: &peephole::Program) -> Module<'a> {
+        self.compile_block(program);
+        self.epilogue();
+        self.module.optimize(3, 0);
+        self.module
     }
 
-    /// Emit the returns for the successful path and both error paths.
+    /// Exit successfully at the end of the program.
     fn epilogue(&self) {
-        self.builder.ret(Value::get_u64(self.context, rts::OKAY));
+        self.add_exit_edge(rts::OKAY);
+        self.builder.br(self.exit);
+    }
+
+    /// Record that the current block branches to the exit block, returning the given status and
+    /// reporting the current pointer.
+    fn add_exit_edge(&self, status: u64) {
+        let block = self.builder.insert_block();
+        self.exit_status.add_incoming(Value::get_u64(self.context, status), block);
+        self.exit_pointer.add_incoming(self.pointer.get(), block);
+    }
+
+    /// Exit with the given status if `test` is true, and otherwise continue in a new block.
+    fn exit_if(&self, test: Value<'a>, status: u64, name: &str) {
+        let success = self.main_function.append(name);
+        self.add_exit_edge(status);
+        self.builder.cond_br(test, self.exit, success);
+        self.builder.position_at_end(success);
+    }
+
+    /// Continue in a new block if `test` is true, and otherwise exit with the given status.
+    fn exit_unless(&self, test: Value<'a>, status: u64, name: &str) {
+        let success = self.main_function.append(name);
+        self.add_exit_edge(status);
+        self.builder.cond_br(test, success, self.exit);
+        self.builder.position_at_end(success);
+    }
+
+    /// Branch to a loop header and start it with a phi node for the data pointer.
+    ///
+    /// The phi node becomes the current pointer, and gets its value on entry from the current
+    /// block. Returns the phi node for `leave_ssa_loop`.
+    fn enter_ssa_loop(&self, header: BasicBlock<'a>) -> Value<'a> {
+        let entry_block = self.builder.insert_block();
+        let entry_pointer = self.pointer.get();
+
+        self.builder.br(header);
+        self.builder.position_at_end(header);
 
-        self.builder.position_at_end(self.underflow);
-        self.builder.ret(Value::get_u64(self.context, rts::UNDERFLOW));
+        let phi = self.builder.phi(Type::get_i64(self.context), "pointer");
+        phi.add_incoming(entry_pointer, entry_block);
+        self.pointer.set(phi);
 
-        self.builder.position_at_end(self.overflow);
-        self.builder.ret(Value::get_u64(self.context, rts::OVERFLOW));
+        phi
+    }
+
+    /// Branch from the end of a loop body back to its header, feeding the current pointer into
+    /// the header’s phi node.
+    fn leave_ssa_loop(&self, header: BasicBlock<'a>, phi: Value<'a>) {
+        let end_block = self.builder.insert_block();
+        phi.add_incoming(self.pointer.get(), end_block);
+        self.builder.br(header);
     }
 
     /// Branch based on whether the byte at the data pointer is 0.
@@ -276,57 +432,174 @@ impl<'a> Compiler<'a> {
         self.builder.cond_br(comparison, true_, false_);
     }
 
+    /// The address of the given index into memory.
+    fn address_of(&self, index: Value<'a>) -> Value<'a> {
+        self.builder.gep(self.memory, &[index], "data_ptr")
+    }
+
     /// Load the byte from the given index into memory.
     fn load_data_at(&self, index: Value<'a>, name: &str) -> Value<'a> {
-        let address = self.builder.gep(self.memory, &[index], "data_ptr");
+        let address = self.address_of(index);
         self.builder.load(address, name)
     }
 
     /// Store the given value at the given index into memory.
     fn store_data_at(&self, index: Value<'a>, value: Value<'a>) {
-        let address = self.builder.gep(self.memory, &[index], "data_ptr");
+        let address = self.address_of(index);
         self.builder.store(value, address);
     }
 
     /// Load the byte from the data pointer.
     fn load_data(&self, name: &str) -> Value<'a> {
-        let pointer = self.builder.load(self.pointer, "");
-        self.load_data_at(pointer, name)
+        self.load_data_at(self.pointer.get(), name)
     }
 
     /// Store the given value at the data pointer.
     fn store_data(&self, value: Value<'a>) {
-        let pointer = self.builder.load(self.pointer, "");
-        self.store_data_at(pointer, value);
+        self.store_data_at(self.pointer.get(), value);
     }
 
-    /// Add the given offset to the data pointer, checking for overflow.
-    fn load_pos_offset(&self, offset: Count, name: &str) -> Value<'a> {
-        let success = self.main_function.append("right_success");
-        let old_pointer = self.builder.load(self.pointer, "old_pointer");
-        let allowed = self.builder.sub(self.memory_size, old_pointer, "room");
+    /// Raise the highest address used to `address`, if it is higher.
+    fn note_max_pointer(&self, address: Value<'a>) {
+        let old_max = self.builder.load(self.max_pointer, "old_max");
+        let higher = self.builder.cmp(LLVMIntPredicate::LLVMIntUGT, address, old_max, "higher");
+        let new_max = self.builder.select(higher, address, old_max, "new_max");
+        self.builder.store(new_max, self.max_pointer);
+    }
+
+    /// Loop, using `step` to compute the next pointer, until the byte at the pointer is 0.
+    fn find_zero<F>(&self, step: F)
+        where F: FnOnce(&Self) -> Value<'a>
+    {
+        let header = self.main_function.append("scan_header");
+        let body   = self.main_function.append("scan_body");
+        let after  = self.main_function.append("after_scan");
+
+        let phi = self.enter_ssa_loop(header);
+        self.if_not0(body, after);
+
+        self.builder.position_at_end(body);
+        let new_pointer = step(self);
+        self.pointer.set(new_pointer);
+        self.leave_ssa_loop(header, phi);
+
+        self.builder.position_at_end(after);
+        self.pointer.set(phi);
+    }
+
+    /// Scan right one byte at a time by calling `memchr` on the rest of memory.
+    ///
+    /// If there is no zero byte, this fails with overflow even in unchecked mode, since `memchr`
+    /// needs a limit anyway.
+    fn memchr_right(&self) {
+        let old_pointer = self.pointer.get();
+
+        let start = self.address_of(old_pointer);
+        let room = self.builder.sub(self.memory_size, old_pointer, "room");
+        let found = self.builder.call(self.memchr,
+                                      &[start, Value::get_u32(self.context, 0), room],
+                                      "found");
+
+        let missing = self.builder.is_null(found, "missing");
+        self.exit_if(missing, rts::OVERFLOW, "scan_success");
+
+        let new_pointer = self.builder.ptr_diff(found, self.memory, "pointer");
+        self.pointer.set(new_pointer);
+    }
+
+    /// Scan left one byte at a time by calling `memrchr` on memory up to the data pointer.
+    ///
+    /// As with `memchr_right`, a missing zero byte fails with underflow even in unchecked mode.
+    fn memrchr_left(&self) {
+        let old_pointer = self.pointer.get();
+
+        let len = self.builder.add(old_pointer, Value::get_u64(self.context, 1), "len");
+        let found = self.builder.call(self.memrchr,
+                                      &[self.memory, Value::get_u32(self.context, 0), len],
+                                      "found");
+
+        let missing = self.builder.is_null(found, "missing");
+        self.exit_if(missing, rts::UNDERFLOW, "scan_success");
+
+        let new_pointer = self.builder.ptr_diff(found, self.memory, "pointer");
+        self.pointer.set(new_pointer);
+    }
+
+    /// Add the byte at the data pointer to the byte at the given offset, and zero it.
+    ///
+    /// Rather than branching on whether the byte is zero, this selects the data pointer itself
+    /// as the target when it is, which makes the addition a no-op. The bounds check, if any, only
+    /// fails when the byte is non-zero.
+    fn offset_add(&self, offset: Count, proved: bool, right: bool) {
+        let builder = self.builder;
+        let pointer = self.pointer.get();
+
+        let to_add = self.load_data("to_add");
+        let zero = Value::get_u8(self.context, 0);
+        let is_zero = builder.cmp(LLVMIntPredicate::LLVMIntEQ, to_add, zero, "is_zero");
+
         let offset = Value::get_u64(self.context, offset as u64);
+
+        if self.checked && !proved {
+            let (fits, failure) = if right {
+                let room = builder.sub(self.memory_size, pointer, "room");
+                (builder.cmp(LLVMIntPredicate::LLVMIntULT, offset, room, "fits"), rts::OVERFLOW)
+            } else {
+                (builder.cmp(LLVMIntPredicate::LLVMIntULE, offset, pointer, "fits"),
+                 rts::UNDERFLOW)
+            };
+
+            let allowed = builder.or(is_zero, fits, "allowed");
+            self.exit_unless(allowed, failure, "offset_success");
+        }
+
+        let target = if right {
+            builder.add(pointer, offset, "target")
+        } else {
+            builder.sub(pointer, offset, "target")
+        };
+        let target = builder.select(is_zero, pointer, target, "target");
+        self.note_max_pointer(target);
+
+        let add_to = self.load_data_at(target, "add_to");
+        let sum = builder.add(to_add, add_to, "sum");
+        self.store_data_at(target, sum);
+        self.store_data(zero);
+    }
+
+    /// Add the given offset to the data pointer, checking for overflow unless `proved`.
+    fn pos_offset(&self, offset: Count, proved: bool, name: &str) -> Value<'a> {
+        let old_pointer = self.pointer.get();
+        let offset = Value::get_u64(self.context, offset as u64);
+
+        if !self.checked || proved {
+            return self.builder.add(old_pointer, offset, name);
+        }
+
+        let allowed = self.builder.sub(self.memory_size, old_pointer, "room");
         let comparison = self.builder.cmp(LLVMIntPredicate::LLVMIntULT, offset, allowed, "allowed");
-        self.builder.cond_br(comparison, success, self.overflow);
-        self.builder.position_at_end(success);
+        self.exit_unless(comparison, rts::OVERFLOW, "right_success");
         self.builder.add(old_pointer, offset, name)
     }
 
-    /// Subtract the given offset from the data pointer, checking for underflow.
-    fn load_neg_offset(&self, offset: Count, name: &str) -> Value<'a> {
-        let success = self.main_function.append("left_success");
-        let old_pointer = self.builder.load(self.pointer, "old_pointer");
+    /// Subtract the given offset from the data pointer, checking for underflow unless `proved`.
+    fn neg_offset(&self, offset: Count, proved: bool, name: &str) -> Value<'a> {
+        let old_pointer = self.pointer.get();
         let offset = Value::get_u64(self.context, offset as u64);
+
+        if !self.checked || proved {
+            return self.builder.sub(old_pointer, offset, name);
+        }
+
         let comparison = self.builder.cmp(LLVMIntPredicate::LLVMIntULE, offset, old_pointer,
                                      "allowed");
-        self.builder.cond_br(comparison, success, self.underflow);
-        self.builder.position_at_end(success);
+        self.exit_unless(comparison, rts::UNDERFLOW, "left_success");
         self.builder.sub(old_pointer, offset, name)
     }
 }
 
 impl LlvmCompilable for peephole::Program {
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, _config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R
     {
         k(self)
@@ -334,10 +607,10 @@ impl LlvmCompilable for peephole::Program {
 }
 
 impl<T: peephole::PeepholeCompilable + ?Sized> LlvmCompilable for T {
-    fn with_peephole<F, R>(&self, k: F) -> R
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
         where F: FnOnce(&peephole::Program) -> R
     {
-        k(&self.peephole_compile())
+        k(&self.peephole_compile(config))
     }
 }
 
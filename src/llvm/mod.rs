@@ -2,8 +2,200 @@
 //!
 //! Enabled with `--features=llvm`. This is actually quite slow, because LLVM takes a long time
 //! optimizing. However, the actual running of the optimized code appears to be quite fast.
+//!
+//! As with the [dynasm JIT](../jit/index.html), the generated code is checked by default, and
+//! uses the [bounds analysis](../analysis/index.html) to omit checks it can prove unnecessary.
+//! Pass `--unchecked` to `bfi` to omit them all.
+//!
+//! Besides running programs in-process, the compiler can write them out as LLVM IR, bitcode,
+//! native assembly, or a native object file; see [`compile_to`](fn.compile_to.html). An object
+//! file needs a `main` function, which [`RUNTIME_SOURCE`](constant.RUNTIME_SOURCE.html)
+//! provides:
+//!
+//! ```text
+//! bfi --llvm --emit obj -o prog.o prog.bf
+//! bfi --emit runtime -o runtime.c
+//! cc -o prog prog.o runtime.c
+//! ```
 
 mod wrapper;
 mod compiler;
 
-pub use self::compiler::{LlvmCompilable, compile_and_run};
+pub use self::compiler::{LlvmCompilable, OutputFormat, compile, compile_to};
+
+use std::mem;
+
+use channel::Channel;
+use common::{BfResult, Error};
+use config::{CellWidth, TapeMode};
+use rts::{self, RtsState};
+use state::State;
+use traits::Interpretable;
+
+use self::wrapper::{Context, ExecutionEngine};
+
+/// C source for a `main` function that runs an object file’s `bfi_main` on stdin and stdout.
+///
+/// The exit status is an [`rts`](../rts/index.html) status code.
+pub const RUNTIME_SOURCE: &str = include_str!("runtime.c");
+
+/// The representation of a program JIT-compiled by LLVM.
+///
+/// Compile once, and then run as many times as you like.
+pub struct Program {
+    // The engine owns LLVM objects that belong to the context, so it must be dropped first.
+    _engine: ExecutionEngine,
+    _context: Context,
+    entry: u64,
+}
+
+/// The type of the compiled `bfi_main` function.
+///
+/// # Parameters
+///
+/// `<'a>` – the lifetime of the channel references in the run-time system state.
+///
+/// `memory` – the address of the beginning of memory (also where the pointer starts).
+///
+/// `memory_size` – the amount of memory allocated.
+///
+/// `rts_state` – the state that the run-time system needs to do I/O.
+///
+/// `read`, `write`, `write_bytes` – the run-time system’s I/O functions.
+///
+/// `set_pointer` – the run-time system function that the program reports its final pointer to.
+///
+/// `set_max_pointer` – the run-time system function that the program reports the highest
+/// address it used to.
+type EntryFunction<'a> = extern "C" fn(memory: *mut u8,
+                                       memory_size: u64,
+                                       rts_state: *mut RtsState<'a>,
+                                       read: extern "C" fn(&mut RtsState<'a>, u8) -> u32,
+                                       write: extern "C" fn(&mut RtsState<'a>, u8),
+                                       write_bytes: unsafe extern "C" fn(&mut RtsState<'a>,
+                                                                         *const u8, u64),
+                                       set_pointer: extern "C" fn(&mut RtsState<'a>, u64),
+                                       set_max_pointer: extern "C" fn(&mut RtsState<'a>, u64))
+                                       -> u64;
+
+impl Program {
+    fn new(engine: ExecutionEngine, context: Context, entry: u64) -> Self {
+        Program {
+            _engine: engine,
+            _context: context,
+            entry: entry,
+        }
+    }
+}
+
+/// As with the [dynasm JIT](../jit/struct.Program.html), the state’s EOF mode and memory are
+/// honored, but a growable tape, a step limit, wide cells, or a pointer not at the start is
+/// `Unsupported`.
+impl Interpretable for Program {
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, mut channel: &mut C)
+        -> BfResult<()>
+    {
+        if state.tape_mode() != TapeMode::Fixed || state.steps_remaining().is_some()
+            || state.pointer() != 0 || state.cell_width() != CellWidth::Eight
+        {
+            return Err(Error::Unsupported);
+        }
+
+        let mut rts = RtsState::with_eof(&mut channel, state.eof_mode());
+
+        let f: EntryFunction = unsafe { mem::transmute(self.entry as usize) };
+
+        let result = f(state.as_mut_ptr(), state.capacity() as u64, &mut rts,
+                       RtsState::read, RtsState::write, RtsState::write_bytes,
+                       RtsState::set_pointer, RtsState::set_max_pointer);
+        state.set_pointer(rts.pointer());
+        state.set_max_pointer(rts.max_pointer());
+
+        match result {
+            rts::OKAY      => Ok(()),
+            rts::UNDERFLOW => Err(Error::PointerUnderflow),
+            rts::OVERFLOW  => Err(Error::PointerOverflow),
+            rts::WOULD_BLOCK => Err(Error::WouldBlock),
+            _ => panic!("Unknown result code: {}", result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::*;
+    use common::{BfResult, Error};
+    use config::Config;
+    use traits::Interpretable;
+
+    #[test]
+    fn move_right_once() {
+        assert_parse_interpret(b">", "", Ok(""));
+    }
+
+    #[test]
+    fn move_left_once() {
+        assert_parse_interpret(b"<", "", Err(Error::PointerUnderflow));
+    }
+
+    #[test]
+    fn move_right_forever() {
+        assert_parse_interpret(b"+[>+]", "", Err(Error::PointerOverflow));
+    }
+
+    #[test]
+    fn scan_past_edges() {
+        assert_parse_interpret(b"+[<]", "", Err(Error::PointerUnderflow));
+
+        // With no zero cells, the scans run off the ends of the tape.
+        let config = Config::new().memory_size(3);
+        for &(source, error) in &[(&b"+>+>+<<[>]"[..], Error::PointerOverflow),
+                                  (b"+>+>+[<]", Error::PointerUnderflow)] {
+            let program = ::ast::parse_program(source).unwrap();
+            let program = ::peephole::compile(&::rle::compile(&program), &config);
+            let program = ::llvm::compile(&program, &config);
+            assert_eq!(program.interpret_memory(&config, b""), Err(error));
+        }
+    }
+
+    #[test]
+    fn echo_one_byte() {
+        assert_parse_interpret(b",.", "A", Ok("A"));
+    }
+
+    #[test]
+    fn hello_world() {
+        assert_parse_interpret(HELLO_WORLD_SRC, "", Ok("Hello, World!"));
+    }
+
+    #[test]
+    fn factoring() {
+        assert_parse_interpret(FACTOR_SRC, "2\n", Ok("2: 2\n"));
+        assert_parse_interpret(FACTOR_SRC, "100\n", Ok("100: 2 2 5 5\n"));
+    }
+
+    #[test]
+    fn unchecked_hello_world() {
+        let program = ::ast::parse_program(HELLO_WORLD_SRC).unwrap();
+        let program = ::peephole::compile(&::rle::compile(&program), &Config::new());
+        let program = ::llvm::compile(&program, &Config::new().checked(false));
+        assert_interpret_result(&program, b"", Ok(&b"Hello, World!"[..]));
+    }
+
+    #[test]
+    fn reusable() {
+        let program = ::ast::parse_program(b",[.,]").unwrap();
+        let program = ::peephole::compile(&::rle::compile(&program), &Config::new());
+        let program = ::llvm::compile(&program, &Config::new());
+        assert_interpret_result(&program, b"one", Ok(&b"one"[..]));
+        assert_interpret_result(&program, b"two", Ok(&b"two"[..]));
+    }
+
+    fn assert_parse_interpret(program: &[u8], input: &str, output: BfResult<&str>) {
+        let program = ::ast::parse_program(program).unwrap();
+        let program = ::rle::compile(&program);
+        let program = ::peephole::compile(&program, &Config::new());
+        let program = ::llvm::compile(&program, &Config::new());
+        assert_interpret_result(&program, input.as_bytes(), output.map(|s| s.as_bytes()));
+    }
+}
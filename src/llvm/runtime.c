@@ -0,0 +1,82 @@
+/*
+ * Runtime for object files produced by `bfi --emit obj`.
+ *
+ * Link it with the object file to get an executable:
+ *
+ *     bfi --llvm --emit obj -o prog.o prog.bf
+ *     bfi --emit runtime -o runtime.c
+ *     cc -o prog prog.o runtime.c
+ *
+ * The exit status is 0 on success, 1 on pointer underflow, and 2 on pointer overflow.
+ */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+extern const uint64_t bfi_memory_size;
+extern const uint64_t bfi_eof;
+
+uint64_t bfi_main(uint8_t *memory,
+                  uint64_t memory_size,
+                  void *rts_state,
+                  uint32_t (*read)(void *, uint8_t),
+                  void (*write)(void *, uint8_t),
+                  void (*write_bytes)(void *, const uint8_t *, uint64_t),
+                  void (*set_pointer)(void *, uint64_t),
+                  void (*set_max_pointer)(void *, uint64_t));
+
+static uint32_t bfi_read(void *rts_state, uint8_t current)
+{
+    int c = getchar();
+    (void) rts_state;
+
+    if (c != EOF)
+        return (uint8_t) c;
+
+    switch (bfi_eof) {
+    case 1:  return 255;
+    case 2:  return current;
+    default: return 0;
+    }
+}
+
+static void bfi_write(void *rts_state, uint8_t byte)
+{
+    (void) rts_state;
+    putchar(byte);
+}
+
+static void bfi_write_bytes(void *rts_state, const uint8_t *bytes, uint64_t len)
+{
+    (void) rts_state;
+    fwrite(bytes, 1, len, stdout);
+}
+
+static void bfi_set_pointer(void *rts_state, uint64_t pointer)
+{
+    (void) rts_state;
+    (void) pointer;
+}
+
+static void bfi_set_max_pointer(void *rts_state, uint64_t max_pointer)
+{
+    (void) rts_state;
+    (void) max_pointer;
+}
+
+int main(void)
+{
+    uint8_t *memory = calloc(bfi_memory_size, 1);
+    uint64_t result;
+
+    if (memory == NULL) {
+        perror("bfi");
+        abort();
+    }
+
+    result = bfi_main(memory, bfi_memory_size, NULL, bfi_read, bfi_write, bfi_write_bytes,
+                      bfi_set_pointer, bfi_set_max_pointer);
+    fflush(stdout);
+    return (int) result;
+}
@@ -1,6 +1,6 @@
 use std::ffi::{CString, CStr};
 use std::os::raw::{c_char, c_uint};
-use std::{mem, ptr};
+use std::{mem, ptr, slice};
 use std::cell::RefCell;
 
 use llvm_sys;
@@ -8,11 +8,12 @@ use llvm_sys::prelude::*;
 use llvm_sys::core::*;
 use llvm_sys::target;
 use llvm_sys::analysis::{LLVMVerifyModule, LLVMVerifierFailureAction};
+use llvm_sys::bit_writer::LLVMWriteBitcodeToMemoryBuffer;
+use llvm_sys::target_machine as machine;
 use llvm_sys::transforms::pass_manager_builder as builder;
 use llvm_sys::execution_engine as engine;
 pub use llvm_sys::LLVMIntPredicate;
-
-use rts::RtsState;
+pub use llvm_sys::target_machine::LLVMCodeGenFileType;
 
 pub struct Context {
     context_ref: LLVMContextRef,
@@ -95,12 +96,6 @@ impl<'a> Module<'a> {
         }
     }
 
-    pub fn dump(&self) {
-        unsafe {
-            LLVMDumpModule(self.module_ref);
-        }
-    }
-
     pub fn verify(&self) -> Result<(), String> {
         let mut out_message: *mut c_char = ptr::null_mut();
 
@@ -117,49 +112,171 @@ impl<'a> Module<'a> {
         }
     }
 
-    pub unsafe fn with_function<'b, F>(&self, name: &str, with: F) -> Result<u64, String>
-        where F: FnOnce(extern fn (&mut RtsState<'b>,
-                                   extern fn(&mut RtsState<'b>) -> u8,
-                                   extern fn(&mut RtsState<'b>, u8) -> ()) -> u64) -> u64
-    {
+    /// The module as textual IR.
+    pub fn print_to_string(&self) -> String {
+        unsafe {
+            take_message(LLVMPrintModuleToString(self.module_ref))
+        }
+    }
+
+    /// The module as bitcode.
+    pub fn write_bitcode(&self) -> Vec<u8> {
+        unsafe {
+            take_buffer(LLVMWriteBitcodeToMemoryBuffer(self.module_ref))
+        }
+    }
+
+    /// Compiles the module to assembly or an object file for the host.
+    ///
+    /// The code is position independent, so that it can be linked into a PIE.
+    pub fn emit(&self, file_type: LLVMCodeGenFileType) -> Result<Vec<u8>, String> {
         let mut out_message: *mut c_char = ptr::null_mut();
-        let mut exec: engine::LLVMExecutionEngineRef = ptr::null_mut();
+        let mut target: machine::LLVMTargetRef = ptr::null_mut();
+        let mut buffer: LLVMMemoryBufferRef = ptr::null_mut();
 
-        engine::LLVMLinkInMCJIT();
+        unsafe {
+            initialize_native_target()?;
 
-        if target::LLVM_InitializeNativeAsmPrinter() == 1 {
-            return Err("Could not initialize native asm printer for LLVM.".to_owned());
+            let triple = machine::LLVMGetDefaultTargetTriple();
+            if machine::LLVMGetTargetFromTriple(triple, &mut target, &mut out_message) != 0 {
+                LLVMDisposeMessage(triple);
+                return Err(take_message(out_message));
+            }
+
+            let cpu = CString::new("generic").unwrap();
+            let features = CString::new("").unwrap();
+            let target_machine = machine::LLVMCreateTargetMachine(
+                target, triple, cpu.as_ptr(), features.as_ptr(),
+                machine::LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+                machine::LLVMRelocMode::LLVMRelocPIC,
+                machine::LLVMCodeModel::LLVMCodeModelDefault);
+
+            LLVMSetTarget(self.module_ref, triple);
+            LLVMDisposeMessage(triple);
+
+            let failed = machine::LLVMTargetMachineEmitToMemoryBuffer(
+                target_machine, self.module_ref, file_type, &mut out_message, &mut buffer);
+            machine::LLVMDisposeTargetMachine(target_machine);
+
+            if failed != 0 {
+                Err(take_message(out_message))
+            } else {
+                Ok(take_buffer(buffer))
+            }
         }
+    }
 
-        if target::LLVM_InitializeNativeTarget() == 1 {
-            return Err("Could not initialize native target for LLVM.".to_owned());
+    /// Adds a constant global `u64` with external linkage.
+    pub fn add_u64_constant(&self, name: &str, value: u64) {
+        let name = self.context.new_name(name);
+        unsafe {
+            let global = LLVMAddGlobal(self.module_ref, Type::get_i64(self.context).type_ref, name);
+            LLVMSetInitializer(global, Value::get_u64(self.context, value).value_ref);
+            LLVMSetGlobalConstant(global, 1);
         }
+    }
 
-        let mut options = engine::LLVMMCJITCompilerOptions {
-            OptLevel: 3,
-            CodeModel: llvm_sys::target_machine::LLVMCodeModel::LLVMCodeModelDefault,
-            NoFramePointerElim: 0,
-            EnableFastISel: 0,
-            MCJMM: ptr::null_mut(),
-        };
+    /// Adds a private constant array of bytes, returning a pointer to its first byte.
+    pub fn add_bytes_constant(&self, name: &str, bytes: &[u8]) -> Value<'a> {
+        let name = self.context.new_name(name);
+        let zero = Value::get_u64(self.context, 0).value_ref;
+        let mut indices = [zero, zero];
+        self.context.wrap_value(unsafe {
+            let array = LLVMConstStringInContext(self.context.context_ref,
+                                                 bytes.as_ptr() as *const c_char,
+                                                 bytes.len() as c_uint,
+                                                 true as _);
+            let global = LLVMAddGlobal(self.module_ref, LLVMTypeOf(array), name);
+            LLVMSetInitializer(global, array);
+            LLVMSetGlobalConstant(global, 1);
+            LLVMSetLinkage(global, llvm_sys::LLVMLinkage::LLVMPrivateLinkage);
+            LLVMConstInBoundsGEP(global, indices.as_mut_ptr(), 2)
+        })
+    }
+
+    /// Hands the module over to a new MCJIT execution engine, which compiles it to native code.
+    pub fn into_engine(self) -> Result<ExecutionEngine, String> {
+        let mut out_message: *mut c_char = ptr::null_mut();
+        let mut exec: engine::LLVMExecutionEngineRef = ptr::null_mut();
+
+        unsafe {
+            engine::LLVMLinkInMCJIT();
+            initialize_native_target()?;
+
+            let mut options = engine::LLVMMCJITCompilerOptions {
+                OptLevel: 3,
+                CodeModel: llvm_sys::target_machine::LLVMCodeModel::LLVMCodeModelDefault,
+                NoFramePointerElim: 0,
+                EnableFastISel: 0,
+                MCJMM: ptr::null_mut(),
+            };
+
+            if engine::LLVMCreateMCJITCompilerForModule(
+                &mut exec, self.module_ref,
+                &mut options,
+                mem::size_of::<c_uint>() as _,
+                &mut out_message
+            ) != 0 {
+                return Err(take_message(out_message));
+            }
+        }
+
+        Ok(ExecutionEngine {
+            engine_ref: exec,
+        })
+    }
+}
+
+/// An MCJIT execution engine, which owns its module and the machine code compiled from it.
+///
+/// This does not borrow its `Context`, but it must be dropped before the context is.
+pub struct ExecutionEngine {
+    engine_ref: engine::LLVMExecutionEngineRef,
+}
 
-        if engine::LLVMCreateMCJITCompilerForModule(
-            &mut exec, self.module_ref,
-            &mut options,
-            mem::size_of::<c_uint>() as _,
-            &mut out_message
-        ) != 0 {
-            let result = CStr::from_ptr(out_message).to_string_lossy().into_owned();
-            LLVMDisposeMessage(out_message);
-            return Err(result);
+impl ExecutionEngine {
+    /// The address of the compiled function with the given name.
+    pub fn function_address(&self, name: &str) -> u64 {
+        let cname = CString::new(name).unwrap();
+        unsafe {
+            engine::LLVMGetFunctionAddress(self.engine_ref, cname.as_ptr())
+        }
+    }
+}
+
+impl Drop for ExecutionEngine {
+    fn drop(&mut self) {
+        unsafe {
+            engine::LLVMDisposeExecutionEngine(self.engine_ref);
         }
+    }
+}
 
-        let cname    = CString::new(name).unwrap();
-        let fun_addr = engine::LLVMGetFunctionAddress(exec, cname.as_ptr());
-        let fun = mem::transmute(fun_addr);
+unsafe fn initialize_native_target() -> Result<(), String> {
+    if target::LLVM_InitializeNativeAsmPrinter() == 1 {
+        return Err("Could not initialize native asm printer for LLVM.".to_owned());
+    }
 
-        Ok(with(fun))
+    if target::LLVM_InitializeNativeTarget() == 1 {
+        return Err("Could not initialize native target for LLVM.".to_owned());
     }
+
+    Ok(())
+}
+
+/// Copies and disposes of a message allocated by LLVM.
+unsafe fn take_message(message: *mut c_char) -> String {
+    let result = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeMessage(message);
+    result
+}
+
+/// Copies and disposes of a memory buffer allocated by LLVM.
+unsafe fn take_buffer(buffer: LLVMMemoryBufferRef) -> Vec<u8> {
+    let start = LLVMGetBufferStart(buffer) as *const u8;
+    let result = slice::from_raw_parts(start, LLVMGetBufferSize(buffer) as usize).to_vec();
+    LLVMDisposeMemoryBuffer(buffer);
+    result
 }
 
 #[derive(Copy, Clone)]
@@ -187,12 +304,6 @@ impl<'a> Type<'a> {
         })
     }
 
-    pub fn get_bool(context: &'a Context) -> Self {
-        context.wrap_type(unsafe {
-            LLVMInt1TypeInContext(context.context_ref)
-        })
-    }
-
     pub fn get_void(context: &'a Context) -> Self {
         context.wrap_type(unsafe {
             LLVMVoidTypeInContext(context.context_ref)
@@ -242,6 +353,15 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Adds an incoming value to a phi node.
+    pub fn add_incoming(&self, value: Value<'a>, block: BasicBlock<'a>) {
+        let mut values = [value.value_ref];
+        let mut blocks = [block.bb_ref];
+        unsafe {
+            LLVMAddIncoming(self.value_ref, values.as_mut_ptr(), blocks.as_mut_ptr(), 1);
+        }
+    }
+
     pub fn get_u64(context: &'a Context, value: u64) -> Self {
         context.wrap_value(unsafe {
             LLVMConstInt(Type::get_i64(context).type_ref,
@@ -265,15 +385,6 @@ impl<'a> Value<'a> {
                          false as _)
         })
     }
-
-
-    pub fn get_bool(context: &'a Context, value: bool) -> Self {
-        context.wrap_value(unsafe {
-            LLVMConstInt(Type::get_bool(context).type_ref,
-                         value as _,
-                         false as _)
-        })
-    }
 }
 
 #[derive(Copy, Clone)]
@@ -316,14 +427,12 @@ impl<'a> Builder<'a> {
         })
     }
 
-    pub fn array_alloca(&self, ty: Type<'a>, size: Value<'a>, name: &str) -> Value<'a> {
-        let name = self.context.new_name(name);
-        self.context.wrap_value(unsafe {
-            LLVMBuildArrayAlloca(self.builder_ref,
-                                 ty.type_ref,
-                                 size.value_ref,
-                                 name)
-        })
+    /// The block that the builder is positioned in.
+    pub fn insert_block(&self) -> BasicBlock<'a> {
+        BasicBlock {
+            bb_ref: unsafe { LLVMGetInsertBlock(self.builder_ref) },
+            _context: self.context,
+        }
     }
 
     pub fn br(&self, dst: BasicBlock<'a>) {
@@ -371,6 +480,13 @@ impl<'a> Builder<'a> {
         })
     }
 
+    pub fn is_null(&self, value: Value<'a>, name: &str) -> Value<'a> {
+        let name = self.context.new_name(name);
+        self.context.wrap_value(unsafe {
+            LLVMBuildIsNull(self.builder_ref, value.value_ref, name)
+        })
+    }
+
     pub fn load(&self, ptr: Value<'a>, name: &str) -> Value<'a> {
         let name = self.context.new_name(name);
         self.context.wrap_value(unsafe {
@@ -378,12 +494,44 @@ impl<'a> Builder<'a> {
         })
     }
 
+    pub fn or(&self, v1: Value<'a>, v2: Value<'a>, name: &str) -> Value<'a> {
+        let name = self.context.new_name(name);
+        self.context.wrap_value(unsafe {
+            LLVMBuildOr(self.builder_ref, v1.value_ref, v2.value_ref, name)
+        })
+    }
+
+    /// Creates a phi node, whose incoming values are added with `Value::add_incoming`.
+    pub fn phi(&self, ty: Type<'a>, name: &str) -> Value<'a> {
+        let name = self.context.new_name(name);
+        self.context.wrap_value(unsafe {
+            LLVMBuildPhi(self.builder_ref, ty.type_ref, name)
+        })
+    }
+
+    /// The distance in elements between two pointers, as an `i64`.
+    pub fn ptr_diff(&self, lhs: Value<'a>, rhs: Value<'a>, name: &str) -> Value<'a> {
+        let name = self.context.new_name(name);
+        self.context.wrap_value(unsafe {
+            LLVMBuildPtrDiff(self.builder_ref, lhs.value_ref, rhs.value_ref, name)
+        })
+    }
+
     pub fn ret(&self, value: Value<'a>) {
         unsafe {
             LLVMBuildRet(self.builder_ref, value.value_ref);
         }
     }
 
+    pub fn select(&self, test: Value<'a>, then: Value<'a>, else_: Value<'a>,
+                  name: &str) -> Value<'a> {
+        let name = self.context.new_name(name);
+        self.context.wrap_value(unsafe {
+            LLVMBuildSelect(self.builder_ref, test.value_ref, then.value_ref, else_.value_ref,
+                            name)
+        })
+    }
+
     pub fn store(&self, src: Value<'a>, dst: Value<'a>) {
         unsafe {
             LLVMBuildStore(self.builder_ref, src.value_ref, dst.value_ref);
@@ -397,13 +545,13 @@ impl<'a> Builder<'a> {
         })
     }
 
-//    pub fn trunc(&self, value: Value<'a>, ty: Type<'a>, name: &str) -> Value<'a> {
-//        let name = self.context.new_name(name);
-//        self.context.wrap_value(unsafe {
-//            LLVMBuildTrunc(self.builder_ref, value.value_ref, ty.type_ref, name)
-//        })
-//    }
-//
+    pub fn trunc(&self, value: Value<'a>, ty: Type<'a>, name: &str) -> Value<'a> {
+        let name = self.context.new_name(name);
+        self.context.wrap_value(unsafe {
+            LLVMBuildTrunc(self.builder_ref, value.value_ref, ty.type_ref, name)
+        })
+    }
+
 //    pub fn zext(&self, value: Value<'a>, ty: Type<'a>, name: &str) -> Value<'a> {
 //        let name = self.context.new_name(name);
 //        self.context.wrap_value(unsafe {
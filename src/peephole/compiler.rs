@@ -1,4 +1,5 @@
 use super::*;
+use config::{Config, Pass, PassSet};
 use rle;
 
 /// Program forms that can be compiled to the peephole AST.
@@ -7,23 +8,38 @@ pub trait PeepholeCompilable {
     fn with_rle<F, R>(&self, k: F) -> R
         where F: FnOnce(&rle::Program) -> R;
 
-    /// Peephole optimize the given program.
-    fn peephole_compile(&self) -> Box<Program> {
-        self.with_rle(compile)
+    /// Peephole optimize the given program with the passes that the configuration enables.
+    fn peephole_compile(&self, config: &Config) -> Box<Program> {
+        self.with_rle(|ast| compile(ast, config))
     }
 }
 
 /// Peephole-optimizes run-length encoded AST.
 ///
-/// See [`Instruction`](struct.Instruction.html) for descriptions of the peepholes.
-pub fn compile(src: &[rle::Statement]) -> Box<Program> {
-    let mut compiler = Compiler::new();
+/// See [`Instruction`](struct.Instruction.html) for descriptions of the peepholes. The result
+/// is then passed through [`fuse_const_output`](fn.fuse_const_output.html). Each of these steps
+/// is a [`Pass`](../config/enum.Pass.html) that runs only if the configuration enables it; with
+/// `Pass::Rle` disabled, runs are split back into single commands.
+pub fn compile(src: &[rle::Statement], config: &Config) -> Box<Program> {
+    let program = compile_block(src, config.passes);
+
+    if config.enabled(Pass::ConstOutput) {
+        fuse_const_output(&program, config.fresh_memory)
+    } else {
+        program
+    }
+}
+
+/// Peephole-optimizes a block without fusing its output.
+fn compile_block(src: &[rle::Statement], passes: PassSet) -> Box<Program> {
+    let mut compiler = Compiler::new(passes);
     compiler.compile(src);
     compiler.into_program()
 }
 
 pub struct Compiler {
     instructions: Vec<Statement>,
+    passes: PassSet,
 }
 
 macro_rules! or_else {
@@ -32,9 +48,10 @@ macro_rules! or_else {
 }
 
 impl Compiler {
-    pub fn new() -> Self {
+    pub fn new(passes: PassSet) -> Self {
         Compiler {
             instructions: Vec::new(),
+            passes,
         }
     }
 
@@ -45,6 +62,11 @@ impl Compiler {
 
         for instruction in src {
             match *instruction {
+                Cmd(command, count) if !self.passes.contains(Pass::Rle) && count > 1 => {
+                    for _ in 0 .. count {
+                        self.compile(&[Cmd(command, 1)]);
+                    }
+                }
                 Cmd(Right, count) =>
                     self.push(Obj::Right(count)),
                 Cmd(Left, count) =>
@@ -71,12 +93,12 @@ impl Compiler {
                     panic!("bad opcode"),
 
                 Loop(ref body) => {
-                    let body = compile(&*body);
+                    let body = compile_block(body, self.passes);
 
                     let peephole = or_else!(
-                        set_zero_peephole(&body),
-                        find_zero_peephole(&body),
-                        offset_add_peephole(&body)
+                        self.peephole(Pass::SetZero, set_zero_peephole, &body),
+                        self.peephole(Pass::FindZero, find_zero_peephole, &body),
+                        self.peephole(Pass::OffsetAdd, offset_add_peephole, &body)
                     );
 
                     if let Some(instr) = peephole {
@@ -96,8 +118,21 @@ impl Compiler {
     fn push(&mut self, instr: common::Instruction) {
         self.instructions.push(Statement::Instr(instr));
     }
+
+    /// Applies `peephole` to a loop body if `pass` is enabled.
+    fn peephole(&self, pass: Pass, peephole: Peephole, body: &[Statement])
+                -> Option<common::Instruction> {
+        if self.passes.contains(pass) {
+            peephole(body)
+        } else {
+            None
+        }
+    }
 }
 
+/// A peephole optimization, which recognizes a loop body that it can replace.
+type Peephole = fn(&[Statement]) -> Option<common::Instruction>;
+
 pub fn set_zero_peephole(body: &[Statement]) -> Option<common::Instruction> {
     use self::Statement::*;
     use common::Instruction::*;
@@ -164,3 +199,47 @@ impl<T: rle::RleCompilable + ?Sized> PeepholeCompilable for T {
         k(&self.rle_compile())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Instruction::*;
+    use config::PASSES;
+
+    #[test]
+    fn passes_can_be_disabled() {
+        let src = b"++[-]>[>]>[->+<].";
+        assert_eq!(compile_src(src, &Config::new()).len(), 7);
+
+        let program = compile_src(src, &Config::new().disable(Pass::SetZero));
+        assert_eq!(program[1], Statement::Loop(vec![Statement::Instr(Add(255))].into()));
+        assert_eq!(program[3], Statement::Instr(FindZeroRight(1)));
+
+        let program = compile_src(src, &Config::new().disable(Pass::OffsetAdd));
+        assert_eq!(program[1], Statement::Instr(SetZero));
+        assert!(matches!(program[5], Statement::Loop(_)));
+    }
+
+    #[test]
+    fn level_zero_splits_runs() {
+        let program = compile_src(b"+++>>", &Config::new().opt_level(0));
+        assert_eq!(&*program, &[Statement::Instr(Add(1)), Statement::Instr(Add(1)),
+                                Statement::Instr(Add(1)), Statement::Instr(Right(1)),
+                                Statement::Instr(Right(1))]);
+    }
+
+    #[test]
+    fn every_pass_preserves_meaning() {
+        for &pass in PASSES {
+            for config in &[Config::new().disable(pass), Config::new().opt_level(0).enable(pass)] {
+                let program = compile_src(::test_helpers::FACTOR_SRC, config);
+                ::test_helpers::assert_interpret(&*program, b"100\n", b"100: 2 2 5 5\n");
+            }
+        }
+    }
+
+    fn compile_src(src: &[u8], config: &Config) -> Box<Program> {
+        let program = ::ast::parse_program(src).unwrap();
+        compile(&::rle::compile(&program), config)
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::mem;
+
+use super::*;
+use common::Instruction;
+
+/// Replaces output whose value is known at compile time with `OutConst` runs.
+///
+/// The pass tracks the values of memory cells relative to the pointer. At the start of the
+/// program every cell is known to be zero if `fresh_memory` says so, and unknown otherwise;
+/// after a loop or a scan only the current cell is known (to be zero). Whenever an `Out` would
+/// write a known value, the byte is added to a pending run rather than issued. Since additions
+/// and `SetZero` can neither fail nor do I/O, the run continues past them; any other instruction
+/// first flushes the pending run as a single `OutConst` statement, so the order of output
+/// relative to input and errors is unchanged.
+pub fn fuse_const_output(program: &Program, fresh_memory: bool) -> Box<Program> {
+    let mut fuser = Fuser::new(if fresh_memory { Some(0) } else { None });
+    fuser.fuse(program);
+    fuser.into_program()
+}
+
+/// The state of the output fuser for a single block.
+struct Fuser {
+    /// The output statements.
+    statements: Vec<Statement>,
+    /// Known output that has not been issued yet.
+    pending: Vec<u8>,
+    /// Position of the pointer relative to where the block started.
+    position: isize,
+    /// Cells whose values differ from `default`, where `None` means unknown.
+    cells: HashMap<isize, Option<u8>>,
+    /// The value of every cell not in `cells`.
+    default: Option<u8>,
+}
+
+impl Fuser {
+    fn new(default: Option<u8>) -> Self {
+        Fuser {
+            statements: Vec::new(),
+            pending: Vec::new(),
+            position: 0,
+            cells: HashMap::new(),
+            default,
+        }
+    }
+
+    fn into_program(mut self) -> Box<Program> {
+        self.flush();
+        self.statements.into_boxed_slice()
+    }
+
+    fn fuse(&mut self, program: &[Statement]) {
+        use self::Statement::*;
+        use common::Instruction::*;
+
+        for statement in program {
+            match *statement {
+                Instr(Add(amount)) => {
+                    let value = self.get(0).map(|value| value.wrapping_add(amount));
+                    self.set(0, value);
+                    self.push(Add(amount));
+                }
+
+                Instr(SetZero) => {
+                    self.set(0, Some(0));
+                    self.push(SetZero);
+                }
+
+                Instr(Out) => match self.get(0) {
+                    Some(value) => self.pending.push(value),
+                    None => self.issue(Out),
+                },
+
+                Instr(OutByte(value)) =>
+                    self.pending.push(value),
+
+                OutConst(ref bytes) =>
+                    self.pending.extend_from_slice(bytes),
+
+                Instr(Right(count)) => {
+                    self.issue(Right(count));
+                    self.position += count as isize;
+                }
+
+                Instr(Left(count)) => {
+                    self.issue(Left(count));
+                    self.position -= count as isize;
+                }
+
+                Instr(In) => {
+                    self.issue(In);
+                    self.set(0, None);
+                }
+
+                Instr(OffsetAddRight(offset)) => {
+                    self.issue(OffsetAddRight(offset));
+                    self.offset_add(offset as isize);
+                }
+
+                Instr(OffsetAddLeft(offset)) => {
+                    self.issue(OffsetAddLeft(offset));
+                    self.offset_add(-(offset as isize));
+                }
+
+                Instr(instr @ FindZeroRight(_)) | Instr(instr @ FindZeroLeft(_)) => {
+                    self.issue(instr);
+                    self.forget();
+                }
+
+                Instr(JumpZero(_)) | Instr(JumpNotZero(_)) =>
+                    panic!("unexpected jump instruction"),
+                Instr(OutString(_)) =>
+                    panic!("unexpected string instruction"),
+
+                Loop(ref body) => {
+                    self.flush();
+
+                    let mut fuser = Fuser::new(None);
+                    fuser.fuse(body);
+                    self.statements.push(Loop(fuser.into_program()));
+
+                    self.forget();
+                }
+            }
+        }
+    }
+
+    /// Gets the value of the cell at the given offset from the pointer, if known.
+    fn get(&self, offset: isize) -> Option<u8> {
+        match self.cells.get(&(self.position + offset)) {
+            Some(&value) => value,
+            None => self.default,
+        }
+    }
+
+    /// Sets the value of the cell at the given offset from the pointer.
+    fn set(&mut self, offset: isize, value: Option<u8>) {
+        self.cells.insert(self.position + offset, value);
+    }
+
+    /// Tracks the effect of moving the current cell’s value to the given offset.
+    fn offset_add(&mut self, offset: isize) {
+        let sum = match (self.get(0), self.get(offset)) {
+            (Some(value), Some(target)) => Some(value.wrapping_add(target)),
+            _ => None,
+        };
+        self.set(offset, sum);
+        self.set(0, Some(0));
+    }
+
+    /// Forgets everything but that the current cell is zero.
+    ///
+    /// This is the state after a loop or scan, which may move the pointer arbitrarily.
+    fn forget(&mut self) {
+        self.position = 0;
+        self.cells.clear();
+        self.default = None;
+        self.set(0, Some(0));
+    }
+
+    /// Issues an instruction that may not be reordered with output.
+    fn issue(&mut self, instr: Instruction) {
+        self.flush();
+        self.push(instr);
+    }
+
+    /// Issues an instruction that may be reordered with output.
+    fn push(&mut self, instr: Instruction) {
+        self.statements.push(Statement::Instr(instr));
+    }
+
+    /// Issues the pending output, if any.
+    fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            let bytes = mem::take(&mut self.pending);
+            self.statements.push(Statement::OutConst(bytes.into_boxed_slice()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Statement::*;
+    use common::Instruction::*;
+
+    #[test]
+    fn known_output_is_fused() {
+        assert_fuse(&[Instr(Add(3)), Instr(Out), Instr(Add(1)), Instr(Out), Instr(Out)],
+                    &[Instr(Add(3)), Instr(Add(1)), OutConst(vec![3, 4, 4].into_boxed_slice())]);
+    }
+
+    #[test]
+    fn input_flushes_output() {
+        assert_fuse(&[Instr(Add(3)), Instr(Out), Instr(In), Instr(Out)],
+                    &[Instr(Add(3)), OutConst(vec![3].into_boxed_slice()),
+                      Instr(In), Instr(Out)]);
+    }
+
+    #[test]
+    fn moves_preserve_known_cells() {
+        assert_fuse(&[Instr(Add(3)), Instr(Right(1)), Instr(Out), Instr(Left(1)), Instr(Out)],
+                    &[Instr(Add(3)), Instr(Right(1)), OutConst(vec![0].into_boxed_slice()),
+                      Instr(Left(1)), OutConst(vec![3].into_boxed_slice())]);
+    }
+
+    #[test]
+    fn loop_forgets_all_but_current() {
+        let body = vec![Instr(Add(255))].into_boxed_slice();
+        assert_fuse(&[Instr(Add(2)), Loop(body.clone()), Instr(Out), Instr(Right(1)), Instr(Out)],
+                    &[Instr(Add(2)), Loop(body), OutConst(vec![0].into_boxed_slice()),
+                      Instr(Right(1)), Instr(Out)]);
+    }
+
+    #[test]
+    fn loop_body_starts_unknown() {
+        let body = vec![Instr(Out), Instr(SetZero), Instr(Add(7)), Instr(Out)].into_boxed_slice();
+        let expected = vec![Instr(Out), Instr(SetZero), Instr(Add(7)),
+                            OutConst(vec![7].into_boxed_slice())].into_boxed_slice();
+        assert_fuse(&[Instr(In), Loop(body)], &[Instr(In), Loop(expected)]);
+    }
+
+    #[test]
+    fn memory_starts_unknown_unless_fresh() {
+        let program = [Instr(Add(3)), Instr(Out), Instr(SetZero), Instr(Add(4)), Instr(Out)];
+        assert_eq!(&*fuse_const_output(&program, false),
+                   &[Instr(Add(3)), Instr(Out), Instr(SetZero), Instr(Add(4)),
+                     OutConst(vec![4].into_boxed_slice())]);
+    }
+
+    #[test]
+    fn offset_add_sums_known_values() {
+        assert_fuse(&[Instr(Add(2)), Instr(Right(1)), Instr(Add(3)), Instr(OffsetAddLeft(1)),
+                      Instr(Left(1)), Instr(Out)],
+                    &[Instr(Add(2)), Instr(Right(1)), Instr(Add(3)), Instr(OffsetAddLeft(1)),
+                      Instr(Left(1)), OutConst(vec![5].into_boxed_slice())]);
+    }
+
+    fn assert_fuse(program: &[Statement], expected: &[Statement]) {
+        assert_eq!(&*fuse_const_output(program, true), expected);
+    }
+}
@@ -1,48 +1,59 @@
-use std::io::{Read, Write};
-
+use channel::Channel;
 use state::State;
-use common::BfResult;
+use common::{BfResult, Error};
+use config::CellWidth;
 use traits::Interpretable;
 use super::*;
 
+/// Fails with `Unsupported` if the state’s cells are wider than 8 bits.
 impl Interpretable for Program {
-    fn interpret_state<R: Read, W: Write>(
-        &self, mut state: State, mut input: R, mut output: W) -> BfResult<()>
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, channel: &mut C)
+        -> BfResult<()>
     {
-        interpret(self, &mut state, &mut input, &mut output)
+        if state.cell_width() != CellWidth::Eight {
+            return Err(Error::Unsupported);
+        }
+
+        interpret(self, state, channel)
     }
 }
 
-fn interpret<R, W>(instructions: &[Statement], state: &mut State,
-                   input: &mut R, output: &mut W)
-                   -> BfResult<()>
-    where R: Read, W: Write
+fn interpret<C>(instructions: &[Statement], state: &mut State, channel: &mut C)
+               -> BfResult<()>
+    where C: Channel + ?Sized
 {
     for instruction in instructions {
-        interpret_instruction(instruction, state, input, output)?;
+        interpret_instruction(instruction, state, channel)?;
     }
 
     Ok(())
 }
 
-fn interpret_instruction<R, W>(instructions: &Statement, state: &mut State,
-                               input: &mut R, output: &mut W)
-                               -> BfResult<()>
-    where R: Read, W: Write
+fn interpret_instruction<C>(instructions: &Statement, state: &mut State, channel: &mut C)
+                           -> BfResult<()>
+    where C: Channel + ?Sized
 {
     use super::Statement::*;
     use common::Instruction::*;
 
     match *instructions {
-        Instr(Left(count)) => state.left(count as usize)?,
+        Instr(Left(count)) => state.left(count)?,
 
-        Instr(Right(count)) => state.right(count as usize)?,
+        Instr(Right(count)) => state.right(count)?,
 
         Instr(Add(amount)) => state.up(amount),
 
-        Instr(In) => state.read(input),
+        Instr(In) => state.read(channel)?,
+
+        Instr(Out) => state.write(channel),
 
-        Instr(Out) => state.write(output),
+        Instr(OutByte(byte)) => {
+            channel.write_byte(byte);
+        }
+
+        OutConst(ref bytes) => {
+            channel.write_bytes(bytes);
+        }
 
         Instr(SetZero) => state.store(0),
 
@@ -50,7 +61,7 @@ fn interpret_instruction<R, W>(instructions: &Statement, state: &mut State,
             let value = state.load();
             if value != 0 {
                 state.store(0);
-                state.up_pos_offset(offset as usize, value)?;
+                state.up_pos_offset(offset, value)?;
             }
         }
 
@@ -58,28 +69,23 @@ fn interpret_instruction<R, W>(instructions: &Statement, state: &mut State,
             let value = state.load();
             if value != 0 {
                 state.store(0);
-                state.up_neg_offset(offset as usize, value)?;
+                state.up_neg_offset(offset, value)?;
             }
         }
 
-        Instr(FindZeroRight(skip)) => {
-            while state.load() != 0 {
-                state.right(skip as usize)?;
-            }
-        }
+        Instr(FindZeroRight(skip)) => state.find_zero_right(skip)?,
 
-        Instr(FindZeroLeft(skip)) => {
-            while state.load() != 0 {
-                state.left(skip as usize)?;
-            }
-        }
+        Instr(FindZeroLeft(skip)) => state.find_zero_left(skip)?,
 
         Instr(JumpZero(_)) | Instr(JumpNotZero(_)) =>
             panic!("unexpected jump instruction"),
+        Instr(OutString(_)) =>
+            panic!("unexpected string instruction"),
 
         Loop(ref body) => {
             while state.load() != 0 {
-                interpret(body, state, input, output)?;
+                state.step()?;
+                interpret(body, state, channel)?;
             }
         }
     }
@@ -90,12 +96,20 @@ fn interpret_instruction<R, W>(instructions: &Statement, state: &mut State,
 #[cfg(test)]
 mod tests {
     use test_helpers::*;
+    use config::Config;
 
     #[test]
     fn hello_world() {
         assert_parse_interpret(HELLO_WORLD_SRC, "", "Hello, World!");
     }
 
+    #[test]
+    fn constant_output() {
+        let mut program = vec![b'+'; 65];
+        program.extend(b".+.+.>,.");
+        assert_parse_interpret(&program, "!", "ABC!");
+    }
+
     #[test]
     fn factoring() {
         assert_parse_interpret(FACTOR_SRC, "2\n", "2: 2\n");
@@ -107,7 +121,7 @@ mod tests {
     fn assert_parse_interpret(program: &[u8], input: &str, output: &str) {
         let program = ::ast::parse_program(program).unwrap();
         let program = ::rle::compile(&program);
-        let program = ::peephole::compile(&program);
+        let program = ::peephole::compile(&program, &Config::new());
         assert_interpret(&*program, input.as_bytes(), output.as_bytes());
     }
 }
@@ -9,13 +9,18 @@
 //! zero, and replaces it with the [`SetZero`](../../src/bf/peephole/mod.rs.html#21-22)
 //! instruction. See the [`common::Instruction`](../common/enum.Instruction.html) enum for a list of
 //! the instructions produced by the [peephole compiler](fn.compile.html).
+//!
+//! Finally, output whose value can be determined at compile time is gathered into runs, so that
+//! a program that prints text performs one write per run rather than one per byte.
 
 use common;
 
 mod interpreter;
 mod compiler;
+mod const_output;
 
 pub use self::compiler::{compile, PeepholeCompilable};
+pub use self::const_output::fuse_const_output;
 
 /// At this level, a program is a rose tree of statements.
 ///
@@ -31,6 +36,8 @@ pub enum Statement {
     ///
     /// Should not contain a `JumpZero` or `JumpNotZero` instruction.
     Instr(common::Instruction),
+    /// Writes the given bytes of output, which were determined at compile time.
+    OutConst(Box<[u8]>),
     /// A loop.
     Loop(Box<[Statement]>),
 }
@@ -0,0 +1,401 @@
+//! An interactive session that runs Brainfuck a line at a time.
+//!
+//! A [`Session`](struct.Session.html) keeps one [`State`](../state/struct.State.html) for its
+//! whole life, so each line picks up where the previous one left memory and the pointer. A line
+//! that leaves a `[` open is held until later lines close it. A line starting with `:` is a
+//! command:
+//!
+//!  - `:reset` clears memory, moves the pointer back to cell 0, and drops any unfinished loop.
+//!  - `:tape` shows the used part of memory, as [`tape::dump`](../tape/fn.dump.html) does.
+//!  - `:load FILE` runs the code in a file.
+//!  - `:backend NAME` switches to another [backend](../engine/enum.Backend.html), and `:backend`
+//!    alone names the current one. `peep` and `byte` work as names too, as in `bfi`.
+//!  - `:help` lists the commands.
+//!
+//! ```
+//! use bf::channel::BufferedChannel;
+//! use bf::config::Config;
+//! use bf::engine::Backend;
+//! use bf::repl::{Response, Session};
+//!
+//! let mut session = Session::new(Backend::Bytecode, Config::new().memory_size(4));
+//! let mut output = Vec::new();
+//! let mut channel = BufferedChannel::new(&b""[..], &mut output);
+//!
+//! assert_eq!(session.eval_line("+++[>++", &mut channel).unwrap(), Response::Incomplete);
+//! assert_eq!(session.eval_line("<-]>", &mut channel).unwrap(), Response::Done);
+//! assert_eq!(session.window(), "0: 0 [6] 0 0");
+//! ```
+//!
+//! Since memory is not zero when a line starts, sessions compile each line with
+//! [`fresh_memory`](../config/struct.Config.html#structfield.fresh_memory) off.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use ast;
+use channel::{Channel, InputResult};
+use common::Error;
+use config::Config;
+use engine::{Backend, BACKENDS};
+use state::State;
+use tape::{self, TapeImage};
+
+/// How many cells on either side of the pointer a [`window`](struct.Session.html#method.window)
+/// shows.
+const WINDOW_RADIUS: usize = 8;
+
+/// An interactive session.
+#[derive(Debug)]
+pub struct Session {
+    backend: Backend,
+    config: Config,
+    state: State,
+    /// Lines of a loop that is not closed yet.
+    pending: Vec<u8>,
+    /// Whether the program’s output stopped partway through a line.
+    line_open: bool,
+}
+
+/// What a session did with a line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Response {
+    /// The line left a `[` open, so the session is waiting for more lines.
+    Incomplete,
+    /// The code ran, or the command finished.
+    Done,
+    /// A command’s answer, to show to the user.
+    Message(String),
+}
+
+/// The ways a line can fail.
+#[derive(Debug)]
+pub enum ReplError {
+    /// The code did not parse, or failed while running.
+    Bf(Error),
+    /// `:load` could not read its file.
+    Io(io::Error),
+    /// The line started with `:` but was not a command.
+    UnknownCommand(String),
+    /// `:backend` named a backend that is not enabled.
+    UnknownBackend(String),
+    /// A command was missing its argument.
+    MissingArgument(&'static str),
+}
+
+impl Session {
+    /// Creates a session with memory from the configuration.
+    pub fn new(backend: Backend, config: Config) -> Self {
+        let state = State::with_config(&config);
+        Self::with_state(backend, config, state)
+    }
+
+    /// Creates a session starting from the given state.
+    pub fn with_state(backend: Backend, config: Config, state: State) -> Self {
+        Session {
+            backend,
+            config: config.fresh_memory(false),
+            state,
+            pending: Vec::new(),
+            line_open: false,
+        }
+    }
+
+    /// The backend that runs each line.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// The memory and pointer as the last line left them.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Whether earlier lines left a `[` open.
+    pub fn is_incomplete(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Runs a line of code or a command, doing the code’s I/O through `channel`.
+    ///
+    /// When code fails while running, memory and the pointer stay as it left them.
+    pub fn eval_line(&mut self, line: &str, channel: &mut dyn Channel)
+                     -> Result<Response, ReplError> {
+        if let Some(command) = line.trim().strip_prefix(':') {
+            return self.command(command, channel);
+        }
+
+        self.pending.extend(line.as_bytes());
+        self.pending.push(b'\n');
+
+        match ast::parse_program(&self.pending) {
+            Err(Error::UnmatchedBegin) => Ok(Response::Incomplete),
+            Err(e) => {
+                self.pending.clear();
+                Err(ReplError::Bf(e))
+            }
+            Ok(program) => {
+                self.pending.clear();
+                self.run(program, channel)
+            }
+        }
+    }
+
+    /// A line showing the cells around the pointer, in the
+    /// [tape image](../tape/index.html) format, after the address of the first one. Cells wider
+    /// than 8 bits show only their low bytes.
+    pub fn window(&self) -> String {
+        let pointer = self.state.pointer();
+        let start = pointer.saturating_sub(WINDOW_RADIUS);
+        let end = (pointer + WINDOW_RADIUS + 1).min(self.state.capacity());
+        let image = TapeImage {
+            cells: self.state.as_bytes()[start .. end].to_vec(),
+            pointer: pointer - start,
+        };
+
+        format!("{}: {}", start, image)
+    }
+
+    /// Returns whether the program’s output stopped partway through a line, and forgets it, so
+    /// that the caller can end the line before showing something else.
+    pub fn take_open_line(&mut self) -> bool {
+        let result = self.line_open;
+        self.line_open = false;
+        result
+    }
+
+    fn command(&mut self, command: &str, channel: &mut dyn Channel)
+               -> Result<Response, ReplError> {
+        let mut words = command.splitn(2, char::is_whitespace);
+        let name = words.next().unwrap_or("");
+        let argument = words.next().map(str::trim).filter(|argument| !argument.is_empty());
+
+        match (name, argument) {
+            ("reset", _) => {
+                self.state = State::with_config(&self.config);
+                self.pending.clear();
+                Ok(Response::Done)
+            }
+
+            ("tape", _) => {
+                let mut bytes = Vec::new();
+                tape::dump(&self.state, &mut bytes).expect("writing to a Vec cannot fail");
+                Ok(Response::Message(String::from_utf8(bytes).expect("dumps are ASCII")))
+            }
+
+            ("load", Some(path)) => {
+                let program = ast::parse_program(&fs::read(path)?)?;
+                self.run(program, channel)
+            }
+            ("load", None) => Err(ReplError::MissingArgument("load")),
+
+            ("backend", Some(name)) => {
+                self.backend = backend_named(name)
+                    .ok_or_else(|| ReplError::UnknownBackend(name.to_owned()))?;
+                Ok(Response::Done)
+            }
+            ("backend", None) => {
+                let names: Vec<_> = BACKENDS.iter().map(|backend| backend.name()).collect();
+                Ok(Response::Message(format!("{} (available: {})\n",
+                                             self.backend, names.join(", "))))
+            }
+
+            ("help", _) => Ok(Response::Message(HELP.to_owned())),
+
+            _ => Err(ReplError::UnknownCommand(name.to_owned())),
+        }
+    }
+
+    fn run(&mut self, program: Box<ast::Program>, channel: &mut dyn Channel)
+           -> Result<Response, ReplError> {
+        let program = self.backend.compile_ast(program, &self.config);
+        let mut channel = LineTracker { channel, line_open: &mut self.line_open };
+        program.run_in_place(&mut self.state, &mut channel)?;
+        Ok(Response::Done)
+    }
+}
+
+const HELP: &str = "\
+:reset          clear memory and move the pointer to cell 0
+:tape           show the used part of memory
+:load FILE      run the code in FILE
+:backend [NAME] show or change the backend
+:help           show this list
+";
+
+/// Looks up a backend by its name or by its `bfi` flag.
+fn backend_named(name: &str) -> Option<Backend> {
+    match name {
+        "peep" => Some(Backend::Peephole),
+        "byte" => Some(Backend::Bytecode),
+        _ => Backend::from_name(name),
+    }
+}
+
+/// Forwards to a channel, noting whether the output stops partway through a line.
+struct LineTracker<'a> {
+    channel: &'a mut dyn Channel,
+    line_open: &'a mut bool,
+}
+
+impl<'a> Channel for LineTracker<'a> {
+    fn read_byte(&mut self) -> InputResult {
+        self.channel.read_byte()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        *self.line_open = byte != b'\n';
+        self.channel.write_byte(byte);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if let Some(&last) = bytes.last() {
+            *self.line_open = last != b'\n';
+        }
+        self.channel.write_bytes(bytes);
+    }
+
+    fn flush(&mut self) {
+        self.channel.flush();
+    }
+}
+
+impl From<Error> for ReplError {
+    fn from(error: Error) -> Self {
+        ReplError::Bf(error)
+    }
+}
+
+impl From<io::Error> for ReplError {
+    fn from(error: io::Error) -> Self {
+        ReplError::Io(error)
+    }
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplError::Bf(ref e) => write!(f, "{}", e),
+            ReplError::Io(ref e) => write!(f, "{}", e),
+            ReplError::UnknownCommand(ref name) => write!(f, "unknown command ‘:{}’", name),
+            ReplError::UnknownBackend(ref name) => write!(f, "unknown backend ‘{}’", name),
+            ReplError::MissingArgument(name) => write!(f, "‘:{}’ needs an argument", name),
+        }
+    }
+}
+
+impl error::Error for ReplError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use channel::BufferedChannel;
+
+    #[test]
+    fn state_persists_between_lines() {
+        let mut session = session();
+        assert_eq!(eval(&mut session, "++>+++"), (Response::Done, Vec::new()));
+        assert_eq!(eval(&mut session, "<[->+<]>."), (Response::Done, vec![5]));
+        assert_eq!(session.window(), "0: 0 [5] 0 0 0 0 0 0 0 0");
+        assert!(session.take_open_line());
+        assert!(!session.take_open_line());
+    }
+
+    #[test]
+    fn waits_for_loops_to_close() {
+        let mut session = session();
+        assert_eq!(eval(&mut session, "+++[>++"), (Response::Incomplete, Vec::new()));
+        assert!(session.is_incomplete());
+        assert_eq!(eval(&mut session, "[-]"), (Response::Incomplete, Vec::new()));
+        assert_eq!(eval(&mut session, "++<-]>"), (Response::Done, Vec::new()));
+        assert!(!session.is_incomplete());
+        assert_eq!(session.state().as_bytes()[1], 2);
+    }
+
+    #[test]
+    fn errors_keep_the_state() {
+        let mut session = session();
+        eval(&mut session, "+>++");
+        assert!(matches!(session.eval_line("]", &mut channel()),
+                         Err(ReplError::Bf(Error::UnmatchedEnd))));
+        assert!(matches!(session.eval_line("<<", &mut channel()),
+                         Err(ReplError::Bf(Error::PointerUnderflow))));
+        assert_eq!(session.window(), "0: 1 [2] 0 0 0 0 0 0 0 0");
+    }
+
+    #[test]
+    fn window_follows_the_pointer() {
+        let mut session = Session::new(Backend::Ast, Config::new().memory_size(30));
+        eval(&mut session, &">".repeat(20));
+        assert_eq!(session.window(), "12: 0 0 0 0 0 0 0 0 [0] 0 0 0 0 0 0 0 0");
+    }
+
+    #[test]
+    fn commands() {
+        let mut session = session();
+        eval(&mut session, "[+");
+        assert_eq!(eval(&mut session, ":reset"), (Response::Done, Vec::new()));
+        assert!(!session.is_incomplete());
+
+        eval(&mut session, "+++");
+        assert_eq!(eval(&mut session, ":reset"), (Response::Done, Vec::new()));
+        assert_eq!(session.state().as_bytes()[0], 0);
+
+        assert_eq!(eval(&mut session, ":backend peep"), (Response::Done, Vec::new()));
+        assert_eq!(session.backend(), Backend::Peephole);
+        assert_eq!(eval(&mut session, ":backend compact"), (Response::Done, Vec::new()));
+        assert_eq!(session.backend(), Backend::Compact);
+        assert!(matches!(session.eval_line(":backend nope", &mut channel()),
+                         Err(ReplError::UnknownBackend(_))));
+
+        assert!(matches!(session.eval_line(":frobnicate", &mut channel()),
+                         Err(ReplError::UnknownCommand(_))));
+        assert!(matches!(session.eval_line(":load", &mut channel()),
+                         Err(ReplError::MissingArgument("load"))));
+    }
+
+    #[test]
+    fn tape_command_dumps_memory() {
+        let mut session = session();
+        eval(&mut session, "+>++");
+        assert_eq!(eval(&mut session, ":tape").0,
+                   Response::Message(format!("00000000  01[02]{:42} |..|\n", "")));
+    }
+
+    #[test]
+    fn load_runs_a_file() {
+        let path = ::std::env::temp_dir()
+            .join(format!("bf-repl-test-{}.bf", ::std::process::id()));
+        fs::write(&path, "++++++[>++++++++<-]>+.").unwrap();
+
+        let mut session = session();
+        let line = format!(":load {}", path.display());
+        assert_eq!(eval(&mut session, &line), (Response::Done, b"1".to_vec()));
+        assert_eq!(session.state().pointer(), 1);
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(session.eval_line(&line, &mut channel()), Err(ReplError::Io(_))));
+    }
+
+    fn session() -> Session {
+        Session::new(Backend::Bytecode, Config::new().memory_size(10))
+    }
+
+    fn channel() -> BufferedChannel<&'static [u8], Vec<u8>> {
+        BufferedChannel::new(&b""[..], Vec::new())
+    }
+
+    fn eval(session: &mut Session, line: &str) -> (Response, Vec<u8>) {
+        let mut output = Vec::new();
+        let response = {
+            let mut channel = BufferedChannel::new(&b""[..], &mut output);
+            let response = session.eval_line(line, &mut channel).unwrap();
+            channel.flush();
+            response
+        };
+
+        (response, output)
+    }
+}
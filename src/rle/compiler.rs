@@ -82,6 +82,13 @@ impl Compiler {
     }
 }
 
+impl RleCompilable for ast::Program {
+    fn with_ast<F, R>(&self, k: F) -> R
+        where F: FnOnce(&ast::Program) -> R
+    {
+        k(self)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -127,12 +134,3 @@ mod tests {
         Obj::Loop(body.into_boxed_slice())
     }
 }
-
-impl RleCompilable for ast::Program {
-    fn with_ast<F, R>(&self, k: F) -> R
-        where F: FnOnce(&ast::Program) -> R
-    {
-        k(self)
-    }
-}
-
@@ -1,35 +1,32 @@
-use std::io::{Read, Write};
-
+use channel::Channel;
 use state::State;
 use common::BfResult;
 use traits::Interpretable;
 use super::*;
 
 impl Interpretable for Program {
-    fn interpret_state<R: Read, W: Write>(
-        &self, mut state: State, mut input: R, mut output: W) -> BfResult<()>
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, channel: &mut C)
+        -> BfResult<()>
     {
-        interpret(self, &mut state, &mut input, &mut output)
+        interpret(self, state, channel)
     }
 }
 
-fn interpret<R, W>(instructions: &Program, state: &mut State,
-                       input: &mut R, output: &mut W)
-                       -> BfResult<()>
-    where R: Read, W: Write
+fn interpret<C>(instructions: &Program, state: &mut State, channel: &mut C)
+               -> BfResult<()>
+    where C: Channel + ?Sized
 {
     for instruction in instructions {
-        interpret_instruction(instruction, state, input, output)?;
+        interpret_instruction(instruction, state, channel)?;
     }
 
     Ok(())
 }
 
 #[inline]
-fn interpret_instruction<R, W>(instruction: &Statement, state: &mut State,
-                               input: &mut R, output: &mut W)
-                               -> BfResult<()>
-    where R: Read, W: Write
+fn interpret_instruction<C>(instruction: &Statement, state: &mut State, channel: &mut C)
+                           -> BfResult<()>
+    where C: Channel + ?Sized
 {
     use self::Statement::*;
     use common::Command::*;
@@ -37,23 +34,24 @@ fn interpret_instruction<R, W>(instruction: &Statement, state: &mut State,
     match *instruction {
         Cmd(Left, count) => state.left(count)?,
         Cmd(Right, count) => state.right(count)?,
-        Cmd(Up, count) => state.up(count as u8),
-        Cmd(Down, count) => state.down(count as u8),
+        Cmd(Up, count) => state.up_cell(count as u32),
+        Cmd(Down, count) => state.down_cell(count as u32),
         Cmd(In, count) => {
             for _ in 0 .. count {
-                state.read(input);
+                state.read(channel)?;
             }
         }
         Cmd(Out, count) => {
             for _ in 0 .. count {
-                state.write(output);
+                state.write(channel);
             }
         }
         Cmd(Begin, _) | Cmd(End, _) =>
             panic!("Invalid opcode"),
         Loop(ref program) => {
-            while state.load() != 0  {
-                interpret(program, state, input, output)?;
+            while state.load_cell() != 0  {
+                state.step()?;
+                interpret(program, state, channel)?;
             }
         }
     }
@@ -3,13 +3,21 @@
 //! In Bendersky's first JIT, the program uses Linux system calls, but that's
 //! insufficiently portable. And maybe I could figure out Darwin system calls, but
 //! I’d rather not write retry loops anyway. The technique here is from [the `dynlib-rs`
-//! tutorial]. Instead, we store trait objects in [a struct](struct.RtsState.html), pass a pointer
-//! to that struct to the generated program, and then have the generated program pass the pointer
-//! to that struct to the RTS’s read and write functions.
+//! tutorial]. Instead, we store a [`Channel`](../channel/trait.Channel.html) trait object in
+//! [a struct](struct.RtsState.html), pass a pointer to that struct to the generated program, and
+//! then have the generated program pass the pointer to that struct to the RTS’s read and write
+//! functions. Since the channel is usually buffered, these calls are cheap.
+//!
+//! All of the functions here use the platform’s C calling convention, so generated code calls
+//! them the same way it would call into a C library.
 //!
 //! [the `dynlib-rs` tutorial]:(https://censoredusername.github.io/dynasm-rs/language/tutorial.html#advanced-usage)
 
-use std::io::{Read, Write};
+use std::{ptr, slice};
+
+use channel::{Channel, InputResult};
+use config::EofMode;
+use scan;
 
 /// The object code terminated successfully.
 pub const OKAY: u64      = 0;
@@ -20,40 +28,132 @@ pub const UNDERFLOW: u64 = 1;
 /// The pointer would have pointed above the allocated buffer had the program continued.
 pub const OVERFLOW: u64  = 2;
 
+/// The program stopped at a `,` because no input was available yet.
+pub const WOULD_BLOCK: u64 = 3;
+
+/// What [`RtsState::read`](struct.RtsState.html#method.read) returns instead of a byte when
+/// input would block; generated code then stops with [`WOULD_BLOCK`](constant.WOULD_BLOCK.html).
+pub const READ_WOULD_BLOCK: u32 = 0x100;
+
 /// Minimal state for our minimal run-time system.
 ///
-/// Trait objects providing channels for standard input and output.
+/// A trait object providing the channel for input and output, what to store at the end of
+/// the input, and the pointer, which generated code reads when it starts and reports when it
+/// stops, along with the highest address that it used.
 pub struct RtsState<'a> {
-    /// Input channel for the `,` operation.
-    input:  &'a mut Read,
-    /// Output channel for the `.` operation.
-    output: &'a mut Write,
+    /// Channel for the `,` and `.` operations.
+    channel: &'a mut (dyn Channel + 'a),
+    /// What `,` stores at the end of the input.
+    eof: EofMode,
+    /// The pointer, as an offset from the start of memory.
+    pointer: usize,
+    /// The highest address that the program used, as an offset from the start of memory.
+    max_pointer: usize,
 }
 
 impl<'a> RtsState<'a> {
-    pub fn new<R: Read, W: Write>(input: &'a mut R, output: &'a mut W) -> Self {
-        RtsState { input, output,
+    /// Creates a run-time system state that stores 0 at the end of the input.
+    pub fn new<C: Channel + 'a>(channel: &'a mut C) -> Self {
+        Self::with_eof(channel, EofMode::Zero)
+    }
+
+    /// Creates a run-time system state with the given EOF mode.
+    pub fn with_eof<C: Channel + 'a>(channel: &'a mut C, eof: EofMode) -> Self {
+        RtsState { channel, eof, pointer: 0, max_pointer: 0 }
+    }
+
+    /// The pointer that generated code reported with [`set_pointer`](#method.set_pointer).
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    /// The highest address that generated code reported with
+    /// [`set_max_pointer`](#method.set_max_pointer), or where it started if that is higher.
+    pub fn max_pointer(&self) -> usize {
+        self.max_pointer
+    }
+
+    /// Sets where generated code starts the pointer, as an offset from the start of memory.
+    ///
+    /// The default is 0.
+    pub fn start_at(&mut self, pointer: usize) {
+        self.pointer = pointer;
+        self.max_pointer = pointer;
+    }
+
+    /// Reads a byte for `,`, given the current value of the cell that it will be stored in.
+    ///
+    /// Returns [`READ_WOULD_BLOCK`](constant.READ_WOULD_BLOCK.html) if the channel has no input
+    /// available yet.
+    pub extern "C" fn read(&mut self, current: u8) -> u32 {
+        match self.channel.read_byte() {
+            InputResult::Byte(byte) => u32::from(byte),
+            InputResult::Eof => u32::from(self.eof.value(current)),
+            InputResult::WouldBlock => READ_WOULD_BLOCK,
         }
     }
 
-    pub extern "win64" fn read(&mut self) -> u8 {
-        let mut buf = [0];
-        let _ = self.input.read_exact(&mut buf);
-        buf[0]
+    pub extern "C" fn write(&mut self, byte: u8) {
+        self.channel.write_byte(byte);
     }
 
-    pub extern "win64" fn write(&mut self, byte: u8) {
-        let _ = self.output.write_all(&[byte]);
+    /// Writes a run of bytes whose values were known at compile time with a single call.
+    ///
+    /// # Safety
+    ///
+    /// `bytes` must point to `len` readable bytes.
+    pub unsafe extern "C" fn write_bytes(&mut self, bytes: *const u8, len: u64) {
+        let bytes = slice::from_raw_parts(bytes, len as usize);
+        self.channel.write_bytes(bytes);
     }
 
-    pub extern "C" fn read_c(&mut self) -> u8 {
-        let mut buf = [0];
-        let _ = self.input.read_exact(&mut buf);
-        buf[0]
+    /// Returns where the program starts the pointer, as an offset from the start of memory.
+    pub extern "C" fn get_pointer(&self) -> u64 {
+        self.pointer as u64
     }
 
-    pub extern "C" fn write_c(&mut self, byte: u8) {
-        let _ = self.output.write_all(&[byte]);
+    /// Records the pointer, as an offset from the start of memory, when the program stops.
+    pub extern "C" fn set_pointer(&mut self, pointer: u64) {
+        self.pointer = pointer as usize;
+    }
+
+    /// Records the highest address, as an offset from the start of memory, that the program
+    /// used, when it stops.
+    pub extern "C" fn set_max_pointer(&mut self, max_pointer: u64) {
+        self.max_pointer = self.max_pointer.max(max_pointer as usize);
+    }
+}
+
+
+/// Finds the nearest zero byte at a multiple of `stride` to the right of `pointer`.
+///
+/// Returns a pointer to the zero byte, or null if there is none before `limit`.
+///
+/// # Safety
+///
+/// The memory from `pointer` up to (but excluding) `limit` must be readable.
+pub unsafe extern "C" fn find_zero_right(pointer: *mut u8, limit: *mut u8, stride: u64)
+                                         -> *mut u8 {
+    let memory = slice::from_raw_parts(pointer, limit as usize - pointer as usize);
+    match scan::find_zero_right(memory, 0, stride as usize) {
+        Some(offset) => pointer.add(offset),
+        None => ptr::null_mut(),
     }
 }
 
+/// Finds the nearest zero byte at a multiple of `stride` to the left of `pointer`.
+///
+/// Returns a pointer to the zero byte, or null if there is none at or after `start`.
+///
+/// # Safety
+///
+/// The memory from `start` up to and including `pointer` must be readable.
+pub unsafe extern "C" fn find_zero_left(pointer: *mut u8, start: *mut u8, stride: u64)
+                                        -> *mut u8 {
+    let offset = pointer as usize - start as usize;
+    let memory = slice::from_raw_parts(start, offset + 1);
+    match scan::find_zero_left(memory, offset, stride as usize) {
+        Some(offset) => start.add(offset),
+        None => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,340 @@
+//! Compiles peephole-optimized AST to Rust source code.
+//!
+//! The generated code defines a single function,
+//!
+//! ```ignore
+//! pub fn run<R: Read, W: Write>(state: &mut State, input: R, output: W) -> BfResult<()>
+//! ```
+//!
+//! which runs the program against the given [`State`](../state/struct.State.html) using the same
+//! state operations and [buffered channel](../channel/struct.BufferedChannel.html) as the
+//! interpreters, so it behaves exactly like them, except that it fails with `Unsupported` on
+//! cells wider than 8 bits. The easiest way to use it is from a build script:
+//!
+//! ```ignore
+//! // build.rs
+//! extern crate bf;
+//!
+//! fn main() {
+//!     bf::rust::build("src/hello.bf", "hello.rs").unwrap();
+//! }
+//! ```
+//!
+//! ```ignore
+//! // src/lib.rs
+//! mod hello {
+//!     include!(concat!(env!("OUT_DIR"), "/hello.rs"));
+//! }
+//! ```
+//!
+//! In `bfi`, pass `--emit rust` to print the generated code.
+
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ast;
+use common::Count;
+use config::Config;
+use peephole::{self, PeepholeCompilable, Statement};
+
+/// Program forms that can be compiled to Rust.
+pub trait RustCompilable {
+    /// Compile the given program into the peephole AST to prepare for Rust code generation.
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R;
+
+    /// Compile the given program to Rust code that refers to this crate as `::bf`.
+    ///
+    /// Only the configuration’s optimization level matters here, since the generated code gets
+    /// the rest from the `State` it runs against.
+    fn rust_compile(&self, config: &Config) -> String {
+        self.with_peephole(config, |ast| compile(ast, "::bf"))
+    }
+}
+
+/// Compiles peephole-optimized AST to Rust code.
+///
+/// `crate_path` is the path of this crate from the generated code, such as `"::bf"`; it is
+/// empty for code included in this crate itself.
+pub fn compile(program: &peephole::Program, crate_path: &str) -> String {
+    let mut compiler = Compiler::new(crate_path);
+    compiler.emit_prologue();
+    compiler.compile(program);
+    compiler.emit_epilogue();
+    compiler.code
+}
+
+/// Compiles the Brainfuck program in the file `source` to Rust, writing it to the file `name`
+/// in the directory given by the `OUT_DIR` environment variable.
+///
+/// This is meant to be called from a build script, and it tells Cargo to rerun the build script
+/// when `source` changes. Returns the path of the generated file.
+///
+/// # Errors
+///
+/// Fails if `OUT_DIR` is unset, if a file cannot be read or written, or if the program does not
+/// parse.
+pub fn build<P: AsRef<Path>>(source: P, name: &str) -> io::Result<PathBuf> {
+    let out_dir = env::var_os("OUT_DIR")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "OUT_DIR is not set"))?;
+
+    println!("cargo:rerun-if-changed={}", source.as_ref().display());
+
+    build_to(source.as_ref(), &Path::new(&out_dir).join(name))
+}
+
+/// Compiles the Brainfuck program in the file `source` to Rust in the file `target`.
+fn build_to(source: &Path, target: &Path) -> io::Result<PathBuf> {
+    let mut text = Vec::new();
+    File::open(source)?.read_to_end(&mut text)?;
+
+    let program = ast::parse_program(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                                    format!("{}: {}", source.display(), e)))?;
+    let code = compile(&program.peephole_compile(&Config::new()), "::bf");
+
+    File::create(target)?.write_all(code.as_bytes())?;
+    Ok(target.to_owned())
+}
+
+/// The compiler state.
+struct Compiler<'a> {
+    /// The Rust code so far.
+    code: String,
+    /// The path to this crate from the generated code.
+    crate_path: &'a str,
+    /// The current indentation level.
+    depth: usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(crate_path: &'a str) -> Self {
+        Compiler {
+            code: String::new(),
+            crate_path,
+            depth: 1,
+        }
+    }
+
+    fn emit_prologue(&mut self) {
+        let path = self.crate_path;
+        let _ = write!(self.code,
+                       "// Generated by `bf::rust::compile`. Do not edit.\n\
+                        \n\
+                        /// Runs the compiled Brainfuck program.\n\
+                        #[allow(dead_code)]\n\
+                        pub fn run<R, W>(state: &mut {0}::state::State, input: R, output: W)\n    \
+                            -> {0}::common::BfResult<()>\n    \
+                            where R: ::std::io::Read, W: ::std::io::Write\n\
+                        {{\n    \
+                            #[allow(unused_imports)]\n    \
+                            use {0}::channel::Channel;\n\
+                        \n    \
+                            if state.cell_width() != {0}::config::CellWidth::Eight {{\n        \
+                                return Err({0}::common::Error::Unsupported);\n    \
+                            }}\n\
+                        \n    \
+                            #[allow(unused_mut, unused_variables)]\n    \
+                            let mut channel = {0}::channel::BufferedChannel::new(input, output);\n\
+                        \n",
+                       path);
+    }
+
+    fn emit_epilogue(&mut self) {
+        self.code.push_str("\n    Ok(())\n}\n");
+    }
+
+    fn compile(&mut self, program: &[Statement]) {
+        for statement in program {
+            self.compile_statement(statement);
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) {
+        use common::Instruction::*;
+
+        match *statement {
+            Statement::Instr(Right(count)) =>
+                self.line(&format!("state.right({})?;", literal(count))),
+
+            Statement::Instr(Left(count)) =>
+                self.line(&format!("state.left({})?;", literal(count))),
+
+            Statement::Instr(Add(amount)) =>
+                self.line(&format!("state.up({});", amount)),
+
+            Statement::Instr(In) =>
+                self.line("state.read(&mut channel)?;"),
+
+            Statement::Instr(Out) =>
+                self.line("state.write(&mut channel);"),
+
+            Statement::Instr(OutByte(byte)) =>
+                self.line(&format!("channel.write_byte({});", byte)),
+
+            Statement::OutConst(ref bytes) =>
+                self.line(&format!("channel.write_bytes(b\"{}\");", escape(bytes))),
+
+            Statement::Instr(SetZero) =>
+                self.line("state.store(0);"),
+
+            Statement::Instr(OffsetAddRight(offset)) =>
+                self.offset_add("up_pos_offset", offset),
+
+            Statement::Instr(OffsetAddLeft(offset)) =>
+                self.offset_add("up_neg_offset", offset),
+
+            Statement::Instr(FindZeroRight(skip)) =>
+                self.line(&format!("state.find_zero_right({})?;", literal(skip))),
+
+            Statement::Instr(FindZeroLeft(skip)) =>
+                self.line(&format!("state.find_zero_left({})?;", literal(skip))),
+
+            Statement::Instr(JumpZero(_)) | Statement::Instr(JumpNotZero(_)) =>
+                panic!("unexpected jump instruction"),
+            Statement::Instr(OutString(_)) =>
+                panic!("unexpected string instruction"),
+
+            Statement::Loop(ref body) => {
+                self.open("while state.load() != 0 {");
+                self.line("state.step()?;");
+                self.compile(body);
+                self.close();
+            }
+        }
+    }
+
+    fn offset_add(&mut self, method: &str, offset: Count) {
+        self.open("if state.load() != 0 {");
+        self.line("let value = state.load();");
+        self.line("state.store(0);");
+        self.line(&format!("state.{}({}, value)?;", method, literal(offset)));
+        self.close();
+    }
+
+    fn open(&mut self, line: &str) {
+        self.line(line);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("}");
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0 .. self.depth {
+            self.code.push_str("    ");
+        }
+        self.code.push_str(line);
+        self.code.push('\n');
+    }
+}
+
+/// A count as a Rust literal of type `usize`.
+fn literal(count: Count) -> String {
+    format!("{}usize", count)
+}
+
+/// Escapes bytes for a Rust byte string literal.
+fn escape(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                result.push('\\');
+                result.push(byte as char);
+            }
+            b' ' ..= b'~' => result.push(byte as char),
+            _ => {
+                let _ = write!(result, "\\x{:02X}", byte);
+            }
+        }
+    }
+
+    result
+}
+
+impl RustCompilable for peephole::Program {
+    fn with_peephole<F, R>(&self, _config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R
+    {
+        k(self)
+    }
+}
+
+impl<T: peephole::PeepholeCompilable + ?Sized> RustCompilable for T {
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R
+    {
+        k(&self.peephole_compile(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Error;
+    use config::CellWidth;
+    use state::State;
+    use traits::{BytecodeCompilable, Interpretable};
+
+    /// `../bf/hello.bf` compiled to Rust for use inside this crate.
+    mod hello {
+        include!("../bf/hello.rs");
+    }
+
+    const HELLO_SRC: &[u8] = include_bytes!("../bf/hello.bf");
+
+    #[test]
+    fn golden_file_is_current() {
+        let program = ast::parse_program(HELLO_SRC).unwrap();
+        assert_eq!(compile(&program.peephole_compile(&Config::new()), ""),
+                   include_str!("../bf/hello.rs"),
+                   "regenerate bf/hello.rs from bf/hello.bf");
+    }
+
+    #[test]
+    fn golden_file_agrees_with_interpreter() {
+        let program = ast::parse_program(HELLO_SRC).unwrap();
+        let config = Config::new();
+        let expected = program.bytecode_compile(&config).interpret_memory(&config, b"").unwrap();
+
+        let mut output = Vec::new();
+        hello::run(&mut State::new(), &b""[..], &mut output).unwrap();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn wide_cells_are_unsupported() {
+        let mut state = State::with_config(&Config::new().cell_width(CellWidth::Sixteen));
+        assert_eq!(hello::run(&mut state, &b""[..], Vec::new()), Err(Error::Unsupported));
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape(b"a\"b\\c\n\x7F"), "a\\\"b\\\\c\\x0A\\x7F");
+    }
+
+    #[test]
+    fn build_writes_file() {
+        let dir = env::temp_dir().join(format!("bf-rust-test-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&dir).unwrap();
+
+        let source = dir.join("echo.bf");
+        File::create(&source).unwrap().write_all(b",[.,]").unwrap();
+        let target = build_to(&source, &dir.join("echo.rs")).unwrap();
+
+        let mut code = String::new();
+        File::open(&target).unwrap().read_to_string(&mut code).unwrap();
+        ::std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(code.contains("pub fn run<R, W>(state: &mut ::bf::state::State"));
+        assert!(code.contains("state.read(&mut channel)?;"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Fast searches for zero bytes, which implement the `FindZeroRight` and `FindZeroLeft`
+//! instructions.
+//!
+//! Like `memchr` and `memrchr`, these examine memory a word at a time, using the usual bit trick
+//! to tell whether any byte of a word is zero. Strides that divide the word size are handled by
+//! forcing the bytes in between to be non-zero before the test. The interpreters reach these
+//! through [`State`](../state/struct.State.html), and the JIT calls them through the
+//! [run-time system](../rts/index.html).
+
+use std::mem;
+
+/// The low bit of every byte of a word.
+const LOW_BITS: u64 = 0x0101_0101_0101_0101;
+
+/// The high bit of every byte of a word.
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+/// The number of bytes examined at once.
+const WORD_SIZE: usize = mem::size_of::<u64>();
+
+/// Finds the first zero byte at `start + k * stride` for some `k ≥ 0`.
+///
+/// Returns `None` if there is no such byte before the end of `memory`.
+///
+/// # Panics
+///
+/// Panics if `start` is out of bounds.
+pub fn find_zero_right(memory: &[u8], start: usize, stride: usize) -> Option<usize> {
+    let mut position = start;
+
+    if let Some(ignore) = ignore_mask(stride) {
+        while position + WORD_SIZE <= memory.len() {
+            if has_zero(read_word(&memory[position ..]) | ignore) {
+                break;
+            }
+            position += WORD_SIZE;
+        }
+    }
+
+    while position < memory.len() {
+        if memory[position] == 0 {
+            return Some(position);
+        }
+        position += stride;
+    }
+
+    None
+}
+
+/// Finds the first zero byte at `start - k * stride` for some `k ≥ 0`.
+///
+/// Returns `None` if there is no such byte before the start of `memory`.
+///
+/// # Panics
+///
+/// Panics if `start` is out of bounds.
+pub fn find_zero_left(memory: &[u8], start: usize, stride: usize) -> Option<usize> {
+    let mut position = start;
+
+    if let Some(ignore) = ignore_mask(stride) {
+        // The word ending at `position` has the bytes of interest at the other end.
+        let ignore = ignore.swap_bytes();
+
+        while position + 1 >= WORD_SIZE {
+            if has_zero(read_word(&memory[position + 1 - WORD_SIZE ..]) | ignore) {
+                break;
+            }
+            if position < WORD_SIZE {
+                return None;
+            }
+            position -= WORD_SIZE;
+        }
+    }
+
+    loop {
+        if memory[position] == 0 {
+            return Some(position);
+        }
+        if position < stride {
+            return None;
+        }
+        position -= stride;
+    }
+}
+
+/// Does searching with the given stride examine a word at a time?
+pub fn scans_by_word(stride: usize) -> bool {
+    ignore_mask(stride).is_some()
+}
+
+/// The bytes to skip when scanning a word starting at a position of interest, or `None` if the
+/// stride is not worth scanning a word at a time.
+fn ignore_mask(stride: usize) -> Option<u64> {
+    match stride {
+        1 => Some(0),
+        2 => Some(0xFF00_FF00_FF00_FF00),
+        4 => Some(0xFFFF_FF00_FFFF_FF00),
+        _ => None,
+    }
+}
+
+/// Does the word contain a zero byte?
+#[inline]
+fn has_zero(word: u64) -> bool {
+    word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS != 0
+}
+
+/// Reads a little-endian word from the start of the slice.
+#[inline]
+fn read_word(bytes: &[u8]) -> u64 {
+    let mut word = [0; WORD_SIZE];
+    word.copy_from_slice(&bytes[.. WORD_SIZE]);
+    u64::from_le_bytes(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_current_byte() {
+        assert_eq!(find_zero_right(&[1, 0, 1], 1, 1), Some(1));
+        assert_eq!(find_zero_left(&[1, 0, 1], 1, 1), Some(1));
+    }
+
+    #[test]
+    fn agrees_with_naive_search() {
+        for &len in &[1, 7, 8, 9, 31, 64, 65] {
+            for zero in 0 .. len + 1 {
+                let mut memory = vec![1u8; len];
+                if zero < len {
+                    memory[zero] = 0;
+                }
+
+                for start in 0 .. len {
+                    for stride in 1 .. 9 {
+                        assert_eq!(find_zero_right(&memory, start, stride),
+                                   naive_right(&memory, start, stride),
+                                   "right: len {} zero {} start {} stride {}",
+                                   len, zero, start, stride);
+                        assert_eq!(find_zero_left(&memory, start, stride),
+                                   naive_left(&memory, start, stride),
+                                   "left: len {} zero {} start {} stride {}",
+                                   len, zero, start, stride);
+                    }
+                }
+            }
+        }
+    }
+
+    fn naive_right(memory: &[u8], start: usize, stride: usize) -> Option<usize> {
+        (start .. memory.len()).step_by(stride).find(|&i| memory[i] == 0)
+    }
+
+    fn naive_left(memory: &[u8], start: usize, stride: usize) -> Option<usize> {
+        (0 .. start + 1).rev().step_by(stride).find(|&i| memory[i] == 0)
+    }
+}
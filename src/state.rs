@@ -4,21 +4,51 @@
 //! interpreters to access the state.
 
 use std::default::Default;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::num::Wrapping;
+use std::slice;
 
-use common::{BfResult, Error};
+use channel::{Channel, InputResult};
+use common::{invalid_data, BfResult, Error};
+use config::{CellWidth, Config, EofMode, TapeMode};
+use scan;
 use traits::IntoUsize;
 
 /// (`== 30_000`) The default number of 8-bit memory cells, as used by
 /// [`State::new`](struct.State.html#method.new).
 pub const DEFAULT_CAPACITY: usize = 30_000;
 
+/// (`== 1 << 30`) The largest memory size that
+/// [`State::read_from`](struct.State.html#method.read_from) accepts.
+///
+/// A saved state stores only the memory up to its last non-zero byte, so without a limit a few
+/// bytes of input could ask for any amount of memory.
+pub const MAX_READ_CAPACITY: usize = 1 << 30;
+
 /// The Brainfuck machine state.
+///
+/// Besides the memory and pointer, the state carries the run-time options from a
+/// [`Config`](../config/struct.Config.html): the cell width, the tape mode, the EOF mode, and how
+/// many loop iterations remain before the step limit. It also records the highest address that
+/// the program has used, so that the used part of memory can be shown after a run.
+///
+/// The byte-sized operations, such as [`load`](#method.load) and [`up`](#method.up), work on
+/// the low byte of each cell, and are only correct for 8-bit cells. The cell-sized operations,
+/// such as [`load_cell`](#method.load_cell) and [`up_cell`](#method.up_cell), work for any
+/// width.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct State {
-    memory: Box<[Wrapping<u8>]>,
+    /// The low byte of each cell.
+    memory: Vec<Wrapping<u8>>,
+    /// The rest of each cell, shifted right by 8, if the cells are wider than 8 bits; otherwise
+    /// empty.
+    high: Vec<u32>,
+    cell_width: CellWidth,
     pointer: usize,
+    max_pointer: usize,
+    tape_mode: TapeMode,
+    eof: EofMode,
+    steps_remaining: Option<u64>,
 }
 
 impl State {
@@ -30,12 +60,58 @@ impl State {
 
     /// Creates a new BF machine state with the given memory capacity.
     pub fn with_capacity(memory_size: usize) -> Self {
+        Self::with_config(&Config::new().memory_size(memory_size))
+    }
+
+    /// Creates a new BF machine state with the memory size and run-time options of the given
+    /// configuration.
+    pub fn with_config(config: &Config) -> Self {
+        let high_size = if config.cell_width == CellWidth::Eight { 0 } else { config.memory_size };
+
         State {
-            memory: vec![Wrapping(0); memory_size].into_boxed_slice(),
+            memory: vec![Wrapping(0); config.memory_size],
+            high: vec![0; high_size],
+            cell_width: config.cell_width,
             pointer: 0,
+            max_pointer: 0,
+            tape_mode: config.tape_mode,
+            eof: config.eof,
+            steps_remaining: config.step_limit,
         }
     }
 
+    /// Creates a BF machine state whose memory is the given bytes, with the pointer at
+    /// `pointer`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pointer` is not less than `memory.len()`.
+    pub fn from_bytes(memory: &[u8], pointer: usize) -> Self {
+        Self::from_bytes_with_config(memory, pointer, &Config::new().memory_size(memory.len()))
+    }
+
+    /// Creates a BF machine state with the run-time options of the given configuration, whose
+    /// memory starts with the given bytes, with the pointer at `pointer`.
+    ///
+    /// The memory is padded with zeros to the configuration’s memory size if `memory` is
+    /// shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pointer` is past the end of the memory.
+    pub fn from_bytes_with_config(memory: &[u8], pointer: usize, config: &Config) -> Self {
+        let mut result = Self::with_config(&config.memory_size(config.memory_size
+                                                                      .max(memory.len())));
+        assert!(pointer < result.memory.len(), "pointer out of range");
+
+        for (cell, &byte) in result.memory.iter_mut().zip(memory) {
+            *cell = Wrapping(byte);
+        }
+        result.pointer = pointer;
+        result.max_pointer = pointer;
+        result
+    }
+
     /// Decrements/decreases the pointer.
     ///
     /// # Errors
@@ -58,16 +134,88 @@ impl State {
         Ok(())
     }
 
+    /// Moves the pointer right by multiples of `skip` until it reaches a zero byte.
+    ///
+    /// # Errors
+    ///
+    /// Return `Err` if there is no zero byte before the end of the memory, leaving the pointer
+    /// where moving right once more would go past the end.
+    pub fn find_zero_right<C: IntoUsize>(&mut self, skip: C) -> BfResult<()> {
+        let skip = skip.into_usize();
+
+        match scan::find_zero_right(self.as_bytes(), self.pointer, skip) {
+            Some(pointer) => {
+                self.move_right_to(pointer);
+                Ok(())
+            }
+            None if self.tape_mode == TapeMode::Growable => {
+                // The first cell past the end of memory is zero.
+                let pointer = self.pointer
+                    + (self.memory.len() - self.pointer).div_ceil(skip) * skip;
+                self.grow_to(pointer);
+                self.move_right_to(pointer);
+                Ok(())
+            }
+            None => {
+                let pointer = self.pointer + (self.memory.len() - 1 - self.pointer) / skip * skip;
+                self.move_right_to(pointer);
+                Err(Error::PointerOverflow)
+            }
+        }
+    }
+
     #[inline]
-    fn pos_offset<C: IntoUsize>(&self, offset: C) -> BfResult<usize> {
-        let offset = offset.into_usize();
-        if self.pointer + offset < self.memory.len() {
-            Ok(self.pointer + offset)
+    fn move_right_to(&mut self, pointer: usize) {
+        self.pointer = pointer;
+        self.max_pointer = self.max_pointer.max(pointer);
+    }
+
+    /// Moves the pointer left by multiples of `skip` until it reaches a zero byte.
+    ///
+    /// # Errors
+    ///
+    /// Return `Err` if there is no zero byte before the start of the memory, leaving the pointer
+    /// where moving left once more would go below 0.
+    pub fn find_zero_left<C: IntoUsize>(&mut self, skip: C) -> BfResult<()> {
+        let skip = skip.into_usize();
+
+        match scan::find_zero_left(self.as_bytes(), self.pointer, skip) {
+            Some(pointer) => {
+                self.pointer = pointer;
+                Ok(())
+            }
+            None => {
+                self.pointer %= skip;
+                Err(Error::PointerUnderflow)
+            }
+        }
+    }
+
+    #[inline]
+    fn pos_offset<C: IntoUsize>(&mut self, offset: C) -> BfResult<usize> {
+        let address = self.pointer + offset.into_usize();
+        if address < self.memory.len() {
+            self.max_pointer = self.max_pointer.max(address);
+            Ok(address)
+        } else if self.tape_mode == TapeMode::Growable {
+            self.grow_to(address);
+            self.max_pointer = address;
+            Ok(address)
         } else {
             Err(Error::PointerOverflow)
         }
     }
 
+    /// Grows memory so that `address` is in range, at least doubling its size to keep the
+    /// amortized cost down.
+    fn grow_to(&mut self, address: usize) {
+        let size = (address + 1).max(2 * self.memory.len());
+        self.memory.resize(size, Wrapping(0));
+        if !self.high.is_empty() {
+            self.high.resize(size, 0);
+        }
+    }
+
     #[inline]
     fn neg_offset<C: IntoUsize>(&self, offset: C) -> BfResult<usize> {
         let offset = offset.into_usize();
@@ -106,6 +254,37 @@ impl State {
         self.memory[self.pointer] = Wrapping(value);
     }
 
+    /// Gets the value of the cell at the pointer.
+    #[inline]
+    pub fn load_cell(&self) -> u32 {
+        let high = self.high.get(self.pointer).map_or(0, |&high| high << 8);
+        u32::from(self.memory[self.pointer].0) | high
+    }
+
+    /// Sets the value of the cell at the pointer, truncated to the cell width.
+    #[inline]
+    pub fn store_cell(&mut self, value: u32) {
+        let value = value & self.cell_width.max_value();
+        self.memory[self.pointer] = Wrapping(value as u8);
+        if let Some(high) = self.high.get_mut(self.pointer) {
+            *high = value >> 8;
+        }
+    }
+
+    /// Increases the cell at the pointer, wrapping around at the cell width.
+    #[inline]
+    pub fn up_cell(&mut self, count: u32) {
+        let value = self.load_cell().wrapping_add(count);
+        self.store_cell(value);
+    }
+
+    /// Decreases the cell at the pointer, wrapping around at the cell width.
+    #[inline]
+    pub fn down_cell(&mut self, count: u32) {
+        let value = self.load_cell().wrapping_sub(count);
+        self.store_cell(value);
+    }
+
     /// Adds the given value at the given positive offset from the pointer.
     #[inline]
     pub fn up_pos_offset<C: IntoUsize>(&mut self, offset: C, value: u8) -> BfResult<()> {
@@ -122,18 +301,50 @@ impl State {
         Ok(())
     }
 
-    /// Reads from a `Read` into the byte at the pointer.
+    /// Reads from a `Channel` into the cell at the pointer.
+    ///
+    /// At the end of the input, stores what the [EOF mode](../config/enum.EofMode.html) says.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(WouldBlock)`, leaving the cell unchanged, if the channel has no input
+    /// available yet.
     #[inline]
-    pub fn read<R: Read>(&mut self, input: &mut R) {
-        let mut byte = [0];
-        let _ = input.read_exact(&mut byte);
-        self.store(byte[0]);
+    pub fn read<C: Channel + ?Sized>(&mut self, channel: &mut C) -> BfResult<()> {
+        let value = match channel.read_byte() {
+            InputResult::Byte(byte) => u32::from(byte),
+            InputResult::Eof => match self.eof {
+                EofMode::Zero => 0,
+                EofMode::MinusOne => self.cell_width.max_value(),
+                EofMode::Unchanged => return Ok(()),
+            },
+            InputResult::WouldBlock => return Err(Error::WouldBlock),
+        };
+        self.store_cell(value);
+        Ok(())
     }
 
-    /// Writes to a `Write` from the byte at the pointer.
+    /// Writes to a `Channel` from the byte at the pointer, which is the low byte of the cell.
     #[inline]
-    pub fn write<W: Write>(&self, output: &mut W) {
-        let _ = output.write_all(&[self.load()]);
+    pub fn write<C: Channel + ?Sized>(&self, channel: &mut C) {
+        channel.write_byte(self.load());
+    }
+
+    /// Counts one loop iteration against the step limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the step limit has been reached.
+    #[inline]
+    pub fn step(&mut self) -> BfResult<()> {
+        if let Some(ref mut remaining) = self.steps_remaining {
+            if *remaining == 0 {
+                return Err(Error::StepLimitExceeded);
+            }
+            *remaining -= 1;
+        }
+
+        Ok(())
     }
 
     /// The memory capacity.
@@ -141,6 +352,185 @@ impl State {
         self.memory.len()
     }
 
+    /// How many bits each cell holds.
+    pub fn cell_width(&self) -> CellWidth {
+        self.cell_width
+    }
+
+    /// The position of the pointer in memory.
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    /// Moves the pointer to the given position.
+    ///
+    /// This is for backends that keep the pointer elsewhere while running, such as the JIT
+    /// compilers, to report where it ended up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pointer` is past the end of memory.
+    pub fn set_pointer(&mut self, pointer: usize) {
+        assert!(pointer < self.memory.len(), "pointer out of range");
+        self.pointer = pointer;
+        self.max_pointer = self.max_pointer.max(pointer);
+    }
+
+    /// Records that the program has used addresses up to `max_pointer`.
+    ///
+    /// This is for backends that keep the pointer elsewhere while running, such as the JIT
+    /// compilers, to report how far right it went. It never lowers the recorded address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_pointer` is past the end of memory.
+    pub fn set_max_pointer(&mut self, max_pointer: usize) {
+        assert!(max_pointer < self.memory.len(), "pointer out of range");
+        self.max_pointer = self.max_pointer.max(max_pointer);
+    }
+
+    /// The highest address that the program has used, by moving the pointer there or by
+    /// adding to it at an offset.
+    pub fn max_pointer(&self) -> usize {
+        self.max_pointer
+    }
+
+    /// What happens when the pointer goes past the end of memory.
+    pub fn tape_mode(&self) -> TapeMode {
+        self.tape_mode
+    }
+
+    /// What `,` stores at the end of the input.
+    pub fn eof_mode(&self) -> EofMode {
+        self.eof
+    }
+
+    /// The number of loop iterations remaining, or `None` if there is no step limit.
+    pub fn steps_remaining(&self) -> Option<u64> {
+        self.steps_remaining
+    }
+
+    /// Sets the number of loop iterations remaining, or removes the step limit.
+    pub fn set_steps_remaining(&mut self, steps_remaining: Option<u64>) {
+        self.steps_remaining = steps_remaining;
+    }
+
+    /// Writes the state to `output`, so that [`read_from`](#method.read_from) can restore it.
+    ///
+    /// The format, with integers in little-endian order, is the tape mode, the EOF mode, and
+    /// whether there is a step limit, as a byte each; the remaining steps, the pointer, the
+    /// highest address used, the memory size, and the length of the memory up to its last
+    /// non-zero byte, as `u64`s; and then that much memory. The rest of the memory is zero, so it
+    /// isn’t stored.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidInput` if the cells are wider than 8 bits, which the format can’t hold.
+    pub fn write_to<W: Write>(&self, mut output: W) -> io::Result<()> {
+        if self.cell_width != CellWidth::Eight {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "cannot save a state with cells wider than 8 bits"));
+        }
+
+        let tape_mode = match self.tape_mode {
+            TapeMode::Fixed => 0,
+            TapeMode::Growable => 1,
+        };
+        let eof = match self.eof {
+            EofMode::Zero => 0,
+            EofMode::MinusOne => 1,
+            EofMode::Unchanged => 2,
+        };
+
+        let memory = self.as_bytes();
+        let used = memory.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+
+        let mut header = Vec::with_capacity(43);
+        header.extend_from_slice(&[tape_mode, eof, self.steps_remaining.is_some() as u8]);
+        header.extend_from_slice(&self.steps_remaining.unwrap_or(0).to_le_bytes());
+        header.extend_from_slice(&(self.pointer as u64).to_le_bytes());
+        header.extend_from_slice(&(self.max_pointer as u64).to_le_bytes());
+        header.extend_from_slice(&(memory.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(used as u64).to_le_bytes());
+
+        output.write_all(&header)?;
+        output.write_all(&memory[.. used])
+    }
+
+    /// Reads a state written by [`write_to`](#method.write_to).
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidData` if the input is not a saved state, or if its memory size is over
+    /// [`MAX_READ_CAPACITY`](constant.MAX_READ_CAPACITY.html).
+    pub fn read_from<R: Read>(mut input: R) -> io::Result<State> {
+        let mut header = [0; 43];
+        input.read_exact(&mut header)?;
+
+        let u64_at = |offset: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&header[offset .. offset + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        let tape_mode = match header[0] {
+            0 => TapeMode::Fixed,
+            1 => TapeMode::Growable,
+            _ => return Err(invalid_data("unknown tape mode")),
+        };
+        let eof = match header[1] {
+            0 => EofMode::Zero,
+            1 => EofMode::MinusOne,
+            2 => EofMode::Unchanged,
+            _ => return Err(invalid_data("unknown EOF mode")),
+        };
+        let steps_remaining = match header[2] {
+            0 => None,
+            1 => Some(u64_at(3)),
+            _ => return Err(invalid_data("bad step limit flag")),
+        };
+        let pointer = u64_at(11);
+        let max_pointer = u64_at(19);
+        let len = u64_at(27);
+        let used = u64_at(35);
+
+        if len > MAX_READ_CAPACITY as u64 {
+            return Err(invalid_data("memory size too large"));
+        }
+        if pointer > max_pointer || max_pointer >= len {
+            return Err(invalid_data("pointer out of range"));
+        }
+        if used > len {
+            return Err(invalid_data("stored memory larger than memory size"));
+        }
+
+        // Reading through `take` bounds the allocation by the actual input.
+        let mut memory = Vec::new();
+        input.take(used).read_to_end(&mut memory)?;
+        if memory.len() as u64 != used {
+            return Err(invalid_data("truncated memory"));
+        }
+        memory.resize(len as usize, 0);
+
+        Ok(State {
+            memory: memory.into_iter().map(Wrapping).collect(),
+            high: Vec::new(),
+            cell_width: CellWidth::Eight,
+            pointer: pointer as usize,
+            max_pointer: max_pointer as usize,
+            tape_mode,
+            eof,
+            steps_remaining,
+        })
+    }
+
+    /// Views the memory as bytes, which are the low bytes of the cells if they are wider than 8
+    /// bits.
+    pub fn as_bytes(&self) -> &[u8] {
+        // Assumes that Wrapping<u8> == u8:
+        unsafe { slice::from_raw_parts(self.memory.as_ptr() as *const u8, self.memory.len()) }
+    }
+
     /// Gets a mutable, raw pointer to the start of memory.
     ///
     /// This is used by the JIT RTS to pass the memory pointer to the generated code.
@@ -159,6 +549,7 @@ impl Default for State {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_helpers::assert_load_rejects_corruption;
 
     #[test]
     fn right_moves_right() {
@@ -173,7 +564,7 @@ mod tests {
     #[test]
     fn right_then_left_restores() {
         let mut actual = make(&[0, 0, 0], 0);
-        let expected = make(&[0, 0, 0], 0);
+        let expected = make(&[0, 0, 0], 0).reached(1);
 
         actual.right(1usize).unwrap();
         actual.left(1usize).unwrap();
@@ -255,10 +646,204 @@ mod tests {
         machine.left(1usize).unwrap();
     }
 
+    #[test]
+    fn find_zero_right_stops_at_zero() {
+        let mut actual = make(&[1, 1, 0, 1, 1, 0], 1);
+        actual.find_zero_right(2usize).unwrap();
+        assert_eq!(actual, make(&[1, 1, 0, 1, 1, 0], 5));
+    }
+
+    #[test]
+    fn find_zero_left_stops_at_zero() {
+        let mut actual = make(&[0, 1, 1, 1, 1, 1], 5);
+        actual.find_zero_left(1usize).unwrap();
+        assert_eq!(actual, make(&[0, 1, 1, 1, 1, 1], 0).reached(5));
+    }
+
+    #[test]
+    fn find_zero_right_past_edge_is_error() {
+        let mut actual = make(&[1, 1, 1, 1, 1, 1], 1);
+        assert_eq!(actual.find_zero_right(2usize), Err(Error::PointerOverflow));
+        assert_eq!(actual, make(&[1, 1, 1, 1, 1, 1], 5));
+    }
+
+    #[test]
+    fn find_zero_left_past_edge_is_error() {
+        let mut actual = make(&[1, 1, 1, 1, 1, 1], 5);
+        assert_eq!(actual.find_zero_left(3usize), Err(Error::PointerUnderflow));
+        assert_eq!(actual, make(&[1, 1, 1, 1, 1, 1], 2).reached(5));
+    }
+
+    #[test]
+    fn growable_memory_grows() {
+        let mut actual = make(&[0, 0, 0], 2).growable();
+        actual.right(2usize).unwrap();
+        actual.up(1);
+        assert_eq!(actual.capacity(), 6);
+        assert_eq!(actual.load(), 1);
+        actual.up_pos_offset(5usize, 7).unwrap();
+        assert_eq!(actual.capacity(), 12);
+    }
+
+    #[test]
+    fn find_zero_right_grows_growable_memory() {
+        let mut actual = make(&[1, 1, 1, 1], 1).growable();
+        actual.find_zero_right(2usize).unwrap();
+        assert_eq!(actual.pointer, 5);
+        assert_eq!(actual.load(), 0);
+    }
+
+    #[test]
+    fn read_at_eof_follows_eof_mode() {
+        let mut channel = ::channel::BufferedChannel::new(&b""[..], Vec::new());
+
+        for &(eof, expected) in &[(EofMode::Zero, 0), (EofMode::MinusOne, 255),
+                                  (EofMode::Unchanged, 7)] {
+            let mut state = State::with_config(&Config::new().memory_size(1).eof(eof));
+            state.store(7);
+            state.read(&mut channel).unwrap();
+            assert_eq!(state.load(), expected);
+        }
+    }
+
+    #[test]
+    fn write_and_read_round_trip() {
+        let config = Config::new().memory_size(5).eof(EofMode::Unchanged).step_limit(9);
+        let mut state = State::with_config(&config);
+        state.up(3);
+        state.right(2usize).unwrap();
+        state.up(4);
+
+        let mut bytes = Vec::new();
+        state.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 43 + 3);
+        assert_eq!(State::read_from(&bytes[..]).unwrap(), state);
+
+        assert_load_rejects_corruption(&bytes, |bytes| State::read_from(bytes));
+        bytes[11] = 5;
+        assert!(State::read_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn read_rejects_huge_memory() {
+        let mut bytes = Vec::new();
+        State::with_config(&Config::new().memory_size(5)).write_to(&mut bytes).unwrap();
+        bytes[27 .. 35].copy_from_slice(&(MAX_READ_CAPACITY as u64 + 1).to_le_bytes());
+
+        let error = State::read_from(&bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn max_pointer_tracks_the_used_range() {
+        let mut state = make(&[1, 1, 0, 0, 0, 0], 0);
+        state.find_zero_right(1usize).unwrap();
+        state.up_pos_offset(2usize, 1).unwrap();
+        state.left(2usize).unwrap();
+        assert_eq!(state.max_pointer(), 4);
+
+        state.set_pointer(5);
+        assert_eq!(state.max_pointer(), 5);
+    }
+
+    #[test]
+    fn step_limit_is_enforced() {
+        let mut state = State::with_config(&Config::new().step_limit(2));
+        assert_eq!(state.step(), Ok(()));
+        assert_eq!(state.step(), Ok(()));
+        assert_eq!(state.step(), Err(Error::StepLimitExceeded));
+    }
+
+    #[test]
+    fn from_bytes_pads_to_memory_size() {
+        assert_eq!(State::from_bytes(&[1, 2, 3], 2), make(&[1, 2, 3], 2));
+
+        let config = Config::new().memory_size(4).eof(EofMode::Unchanged);
+        let state = State::from_bytes_with_config(&[1, 2], 3, &config);
+        assert_eq!(state.as_bytes(), &[1, 2, 0, 0]);
+        assert_eq!(state.pointer(), 3);
+        assert_eq!(state.eof_mode(), EofMode::Unchanged);
+
+        let state = State::from_bytes_with_config(&[1, 2, 3], 0, &Config::new().memory_size(2));
+        assert_eq!(state.capacity(), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_bytes_checks_pointer() {
+        State::from_bytes(&[0, 0], 2);
+    }
+
+    #[test]
+    fn wide_cells_wrap_at_their_width() {
+        let mut state = State::with_config(&Config::new().memory_size(2)
+                                                         .cell_width(CellWidth::Sixteen));
+        state.up_cell(300);
+        assert_eq!(state.load_cell(), 300);
+        assert_eq!(state.as_bytes(), &[44, 0]);
+        state.down_cell(301);
+        assert_eq!(state.load_cell(), 0xFFFF);
+        state.up_cell(1);
+        assert_eq!(state.load_cell(), 0);
+
+        let mut state = State::with_config(&Config::new().memory_size(1)
+                                                         .cell_width(CellWidth::ThirtyTwo));
+        state.down_cell(1);
+        assert_eq!(state.load_cell(), u32::MAX);
+
+        let mut state = State::with_config(&Config::new().memory_size(1));
+        state.up_cell(257);
+        assert_eq!(state.load_cell(), 1);
+    }
+
+    #[test]
+    fn wide_cells_grow() {
+        let config = Config::new().memory_size(1).tape_mode(TapeMode::Growable)
+                                  .cell_width(CellWidth::Sixteen);
+        let mut state = State::with_config(&config);
+        state.right(3usize).unwrap();
+        state.up_cell(1000);
+        assert_eq!(state.load_cell(), 1000);
+    }
+
+    #[test]
+    fn wide_cells_read_eof() {
+        let config = Config::new().memory_size(1).cell_width(CellWidth::Sixteen)
+                                  .eof(EofMode::MinusOne);
+        let mut state = State::with_config(&config);
+        state.read(&mut ::channel::BufferedChannel::new(&b""[..], Vec::new())).unwrap();
+        assert_eq!(state.load_cell(), 0xFFFF);
+    }
+
+    #[test]
+    fn wide_cells_cannot_be_saved() {
+        let state = State::with_config(&Config::new().cell_width(CellWidth::Sixteen));
+        let error = state.write_to(Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
     fn make(memory: &[u8], pointer: usize) -> State {
         State {
-            memory: memory.iter().map(|&b| Wrapping(b)).collect::<Vec<_>>().into_boxed_slice(),
-            pointer: pointer,
+            memory: memory.iter().map(|&b| Wrapping(b)).collect(),
+            high: Vec::new(),
+            cell_width: CellWidth::Eight,
+            pointer,
+            max_pointer: pointer,
+            tape_mode: TapeMode::Fixed,
+            eof: EofMode::Zero,
+            steps_remaining: None,
+        }
+    }
+
+    impl State {
+        fn growable(mut self) -> Self {
+            self.tape_mode = TapeMode::Growable;
+            self
+        }
+
+        fn reached(mut self, max_pointer: usize) -> Self {
+            self.max_pointer = max_pointer;
+            self
         }
     }
 }
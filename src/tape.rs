@@ -0,0 +1,273 @@
+//! A text format for tape images.
+//!
+//! A tape image lists memory cells from the start of memory as decimal numbers from 0 to 255,
+//! separated by whitespace, with the cell under the pointer in square brackets:
+//!
+//! ```text
+//! 0 0 [5] 12 0
+//! ```
+//!
+//! If no cell is bracketed, the pointer is at the first cell. A `#` starts a comment that runs
+//! to the end of the line. Loading an image into a [`State`](../state/struct.State.html) pads it
+//! with zeros to the configured memory size, which makes images handy for testing a subroutine
+//! against a prepared tape. (Programs run this way must not be compiled with
+//! [`fresh_memory`](../config/struct.Config.html#structfield.fresh_memory), which lets the
+//! optimizer assume that memory starts out zero.)
+//!
+//! ```
+//! use bf::config::Config;
+//! use bf::tape::TapeImage;
+//!
+//! let image: TapeImage = "3 [4] 0".parse().unwrap();
+//! let state = image.to_state(&Config::new().memory_size(10));
+//! assert_eq!(state.pointer(), 1);
+//! assert_eq!(&state.as_bytes()[.. 4], &[3, 4, 0, 0]);
+//! assert_eq!(TapeImage::from_state(&state).to_string(), "3 [4]");
+//! ```
+//!
+//! For looking at memory after a run, [`dump`](fn.dump.html) writes a hex-and-ASCII view
+//! instead.
+
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use config::Config;
+use state::State;
+
+/// The contents of memory, and where the pointer is.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct TapeImage {
+    /// The cells, starting from the start of memory.
+    pub cells: Vec<u8>,
+    /// The index of the cell under the pointer.
+    pub pointer: usize,
+}
+
+/// The number of cells on each line of a [`dump`](fn.dump.html).
+const DUMP_WIDTH: usize = 16;
+
+/// An error parsing a [`TapeImage`](struct.TapeImage.html).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseTapeError {
+    /// The line where the error is, counting from 1.
+    pub line: usize,
+    /// What went wrong.
+    pub kind: ParseTapeErrorKind,
+}
+
+/// The kinds of errors in tape images.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseTapeErrorKind {
+    /// A cell was not a number from 0 to 255.
+    BadCell,
+    /// A bracket was missing its partner.
+    UnmatchedBracket,
+    /// More than one cell was bracketed.
+    MultiplePointers,
+}
+
+impl TapeImage {
+    /// The image of a state’s memory, up to the pointer or the last non-zero cell, whichever is
+    /// further. Cells wider than 8 bits show only their low bytes.
+    pub fn from_state(state: &State) -> Self {
+        let memory = state.as_bytes();
+        let end = memory.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1)
+            .max(state.pointer() + 1);
+
+        TapeImage {
+            cells: memory[.. end].to_vec(),
+            pointer: state.pointer(),
+        }
+    }
+
+    /// A state with the run-time options of the given configuration, whose memory starts with
+    /// the image.
+    ///
+    /// Memory is padded with zeros to the configuration’s memory size, and extended if the image
+    /// is larger.
+    pub fn to_state(&self, config: &Config) -> State {
+        let memory_size = config.memory_size.max(self.pointer + 1);
+        State::from_bytes_with_config(&self.cells, self.pointer, &config.memory_size(memory_size))
+    }
+}
+
+/// Writes a hex-and-ASCII view of the used part of a state’s memory, with the cell under the
+/// pointer in brackets.
+///
+/// The used part runs from the start of memory through the highest address that the program
+/// used or the last non-zero cell, whichever is further. Each line shows the address of its
+/// first cell, up to 16 cells in hex, and the same cells as text, with `.` for bytes that are
+/// not printable ASCII. Cells wider than 8 bits show only their low bytes:
+///
+/// ```text
+/// 00000000  48 65 6c 6c 6f 2c 20 57 6f 72 6c 64 21 0a[00]01  |Hello, World!...|
+/// ```
+pub fn dump<W: Write>(state: &State, mut output: W) -> io::Result<()> {
+    let memory = state.as_bytes();
+    let pointer = state.pointer();
+    let used = memory.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1)
+        .max(state.max_pointer() + 1);
+
+    for (row, cells) in memory[.. used].chunks(DUMP_WIDTH).enumerate() {
+        let start = row * DUMP_WIDTH;
+        write!(output, "{:08x} ", start)?;
+
+        // The brackets replace the spaces on either side of the pointer’s cell.
+        for (index, cell) in cells.iter().enumerate() {
+            let separator = if start + index == pointer {
+                '['
+            } else if index > 0 && start + index == pointer + 1 {
+                ']'
+            } else {
+                ' '
+            };
+            write!(output, "{}{:02x}", separator, cell)?;
+        }
+
+        let end = if start + cells.len() == pointer + 1 { ']' } else { ' ' };
+        write!(output, "{}{:width$} |", end, "", width = 3 * (DUMP_WIDTH - cells.len()))?;
+
+        for &cell in cells {
+            let shown = if cell.is_ascii_graphic() || cell == b' ' { cell as char } else { '.' };
+            write!(output, "{}", shown)?;
+        }
+
+        writeln!(output, "|")?;
+    }
+
+    Ok(())
+}
+
+impl FromStr for TapeImage {
+    type Err = ParseTapeError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut result = TapeImage::default();
+        let mut pointer = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |kind| ParseTapeError { line: index + 1, kind };
+            let line = line.split('#').next().unwrap_or("");
+
+            for word in line.split_whitespace() {
+                let (word, bracketed) = if word.starts_with('[') && word.ends_with(']') {
+                    (&word[1 .. word.len() - 1], true)
+                } else if word.contains('[') || word.contains(']') {
+                    return Err(error(ParseTapeErrorKind::UnmatchedBracket));
+                } else {
+                    (word, false)
+                };
+
+                if bracketed {
+                    if pointer.is_some() {
+                        return Err(error(ParseTapeErrorKind::MultiplePointers));
+                    }
+                    pointer = Some(result.cells.len());
+                }
+
+                let cell = word.parse().map_err(|_| error(ParseTapeErrorKind::BadCell))?;
+                result.cells.push(cell);
+            }
+        }
+
+        result.pointer = pointer.unwrap_or(0);
+        Ok(result)
+    }
+}
+
+impl fmt::Display for TapeImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, cell) in self.cells.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+
+            if index == self.pointer {
+                write!(f, "[{}]", cell)?;
+            } else {
+                write!(f, "{}", cell)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ParseTapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self.kind {
+            ParseTapeErrorKind::BadCell => "cells must be numbers from 0 to 255",
+            ParseTapeErrorKind::UnmatchedBracket => "unmatched bracket",
+            ParseTapeErrorKind::MultiplePointers => "more than one cell is bracketed",
+        };
+
+        write!(f, "line {}: {}", self.line, message)
+    }
+}
+
+impl error::Error for ParseTapeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("0 0 [5] 12 0".parse(), Ok(image(&[0, 0, 5, 12, 0], 2)));
+        assert_eq!("1 2\n3 # comment [\n  4".parse(), Ok(image(&[1, 2, 3, 4], 0)));
+        assert_eq!("".parse(), Ok(image(&[], 0)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("1 256".parse::<TapeImage>().unwrap_err().kind, ParseTapeErrorKind::BadCell);
+        assert_eq!("1\n[2".parse::<TapeImage>(),
+                   Err(ParseTapeError { line: 2, kind: ParseTapeErrorKind::UnmatchedBracket }));
+        assert_eq!("[1] [2]".parse::<TapeImage>().unwrap_err().kind,
+                   ParseTapeErrorKind::MultiplePointers);
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = image(&[7, 0, 255, 0], 3);
+        assert_eq!(image.to_string(), "7 0 255 [0]");
+        assert_eq!(image.to_string().parse(), Ok(image.clone()));
+
+        let state = image.to_state(&Config::new().memory_size(10));
+        assert_eq!(state.capacity(), 10);
+        assert_eq!(TapeImage::from_state(&state), image);
+    }
+
+    #[test]
+    fn pointer_past_cells() {
+        let state = image(&[1], 5).to_state(&Config::new().memory_size(2));
+        assert_eq!(state.capacity(), 6);
+        assert_eq!(state.pointer(), 5);
+    }
+
+    #[test]
+    fn dump_shows_the_used_range() {
+        let mut state = State::from_bytes_with_config(b"Hello, World!\n", 17,
+                                                      &Config::new().memory_size(40));
+        state.set_pointer(14);
+
+        let mut output = Vec::new();
+        dump(&state, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "00000000  48 65 6c 6c 6f 2c 20 57 6f 72 6c 64 21 0a[00]00  |Hello, World!...|\n\
+                    00000010  00 00                                            |..|\n");
+    }
+
+    #[test]
+    fn dump_brackets_the_end_of_a_line() {
+        let mut output = Vec::new();
+        dump(&State::from_bytes(&[0; 20], 15), &mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains(" 00[00] |"));
+    }
+
+    fn image(cells: &[u8], pointer: usize) -> TapeImage {
+        TapeImage { cells: cells.to_vec(), pointer }
+    }
+}
@@ -1,13 +1,18 @@
 //! Helper definitions for testing both inside and outside (e.g., benches) the crate.
 
+use std::io;
 use std::str;
 
 use common::BfResult;
+use config::Config;
 use traits::Interpretable;
 
 /// Source of the factoring program from `../bf/factor.bf`.
 pub const FACTOR_SRC: &[u8] = include_bytes!("../bf/factor.bf");
 
+/// Source of the Mandelbrot program from `../bf/mandelbrot-quiet.bf`, which prints nothing.
+pub const MANDELBROT_SRC: &[u8] = include_bytes!("../bf/mandelbrot-quiet.bf");
+
 /// Source of a “hello world” program.
 pub const HELLO_WORLD_SRC: &[u8] =
     b"++++++[>++++++++++++<-]>.\
@@ -25,10 +30,22 @@ pub fn assert_interpret<I: Interpretable + ?Sized>(program: &I, input: &[u8], ou
 pub fn assert_interpret_result<I>(program: &I, input: &[u8], output: BfResult<&[u8]>)
     where I: Interpretable + ?Sized
 {
-    let actual_bytes = program.interpret_memory(None, input);
+    let actual_bytes = program.interpret_memory(&Config::new(), input);
     let actual = actual_bytes.map(|bytes| str::from_utf8(&bytes).unwrap().to_owned());
     let expected = output.map(|bytes| str::from_utf8(bytes).unwrap().to_owned());
 
     assert_eq!(actual, expected);
 
 }
+
+/// Asserts that `load` accepts `bytes`, but rejects every truncation of them and input that
+/// isn’t in the format at all.
+pub fn assert_load_rejects_corruption<T, F>(bytes: &[u8], load: F)
+    where F: Fn(&[u8]) -> io::Result<T>
+{
+    assert!(load(bytes).is_ok());
+    for len in 0 .. bytes.len() {
+        assert!(load(&bytes[.. len]).is_err(), "truncated to {} bytes", len);
+    }
+    assert!(load(b"not a file in this format at all").is_err());
+}
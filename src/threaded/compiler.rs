@@ -0,0 +1,67 @@
+use super::*;
+use super::interpreter as handlers;
+use bytecode;
+use common::Instruction;
+use config::Config;
+use traits::IntoUsize;
+
+/// Program forms that can be compiled to threaded code.
+pub trait ThreadedCompilable {
+    /// Compile the given program to bytecode to prepare for threading.
+    fn with_bytecode<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&bytecode::Program) -> R;
+
+    /// Compile the given program to threaded code.
+    fn threaded_compile(&self, config: &Config) -> Program {
+        self.with_bytecode(config, compile)
+    }
+}
+
+/// Decodes a bytecode program into threaded code.
+pub fn compile(src: &bytecode::Program) -> Program {
+    Program {
+        ops: src.iter().map(|&instruction| decode(instruction)).collect::<Vec<_>>()
+            .into_boxed_slice(),
+        strings: src.strings().into(),
+    }
+}
+
+/// Finds the handler and operand for a bytecode instruction.
+fn decode(instruction: Instruction) -> Op {
+    use common::Instruction::*;
+
+    let (handler, operand): (Handler, usize) = match instruction {
+        Left(count)             => (handlers::left, count.into_usize()),
+        Right(count)            => (handlers::right, count.into_usize()),
+        Add(amount)             => (handlers::add, amount as usize),
+        In                      => (handlers::read, 0),
+        Out                     => (handlers::write, 0),
+        OutByte(byte)           => (handlers::write_byte, byte as usize),
+        OutString(index)        => (handlers::write_string, index.into_usize()),
+        JumpZero(address)       => (handlers::jump_zero, address.into_usize()),
+        JumpNotZero(address)    => (handlers::jump_not_zero, address.into_usize()),
+        SetZero                 => (handlers::set_zero, 0),
+        OffsetAddRight(offset)  => (handlers::offset_add_right, offset.into_usize()),
+        OffsetAddLeft(offset)   => (handlers::offset_add_left, offset.into_usize()),
+        FindZeroRight(skip)     => (handlers::find_zero_right, skip.into_usize()),
+        FindZeroLeft(skip)      => (handlers::find_zero_left, skip.into_usize()),
+    };
+
+    Op { handler, operand }
+}
+
+impl ThreadedCompilable for bytecode::Program {
+    fn with_bytecode<F, R>(&self, _config: &Config, k: F) -> R
+        where F: FnOnce(&bytecode::Program) -> R
+    {
+        k(self)
+    }
+}
+
+impl<T: bytecode::BytecodeCompilable + ?Sized> ThreadedCompilable for T {
+    fn with_bytecode<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&bytecode::Program) -> R
+    {
+        k(&self.bytecode_compile(config))
+    }
+}
@@ -0,0 +1,172 @@
+use channel::Channel;
+use state::State;
+use common::{BfResult, Error};
+use config::CellWidth;
+use traits::Interpretable;
+use super::*;
+
+/// Fails with `Unsupported` if the state’s cells are wider than 8 bits.
+impl Interpretable for Program {
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, mut channel: &mut C)
+        -> BfResult<()>
+    {
+        if state.cell_width() != CellWidth::Eight {
+            return Err(Error::Unsupported);
+        }
+
+        interpret(&self.ops, &self.strings, state, &mut channel)
+    }
+}
+
+fn interpret(ops: &[Op], strings: &[Box<[u8]>], state: &mut State, channel: &mut dyn Channel)
+             -> BfResult<()> {
+    let mut pc = 0;
+
+    while let Some(op) = ops.get(pc) {
+        pc = (op.handler)(op.operand, pc, state, channel, strings)?;
+    }
+
+    Ok(())
+}
+
+// The handlers all have type `Handler`, even when they ignore some of their arguments.
+
+pub fn left(count: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+            _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.left(count)?;
+    Ok(pc + 1)
+}
+
+pub fn right(count: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+             _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.right(count)?;
+    Ok(pc + 1)
+}
+
+pub fn add(amount: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+           _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.up(amount as u8);
+    Ok(pc + 1)
+}
+
+pub fn read(_: usize, pc: usize, state: &mut State, channel: &mut dyn Channel,
+            _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.read(channel)?;
+    Ok(pc + 1)
+}
+
+pub fn write(_: usize, pc: usize, state: &mut State, channel: &mut dyn Channel,
+             _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.write(channel);
+    Ok(pc + 1)
+}
+
+pub fn write_byte(byte: usize, pc: usize, _: &mut State, channel: &mut dyn Channel,
+                  _: &[Box<[u8]>]) -> BfResult<usize> {
+    channel.write_byte(byte as u8);
+    Ok(pc + 1)
+}
+
+pub fn write_string(index: usize, pc: usize, _: &mut State, channel: &mut dyn Channel,
+                    strings: &[Box<[u8]>]) -> BfResult<usize> {
+    channel.write_bytes(&strings[index]);
+    Ok(pc + 1)
+}
+
+pub fn jump_zero(address: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+                 _: &[Box<[u8]>]) -> BfResult<usize> {
+    if state.load() == 0 {
+        Ok(address + 1)
+    } else {
+        state.step()?;
+        Ok(pc + 1)
+    }
+}
+
+pub fn jump_not_zero(address: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+                     _: &[Box<[u8]>]) -> BfResult<usize> {
+    if state.load() != 0 {
+        state.step()?;
+        Ok(address + 1)
+    } else {
+        Ok(pc + 1)
+    }
+}
+
+pub fn set_zero(_: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+                _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.store(0);
+    Ok(pc + 1)
+}
+
+pub fn offset_add_right(offset: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+                        _: &[Box<[u8]>]) -> BfResult<usize> {
+    let value = state.load();
+    if value != 0 {
+        state.store(0);
+        state.up_pos_offset(offset, value)?;
+    }
+    Ok(pc + 1)
+}
+
+pub fn offset_add_left(offset: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+                       _: &[Box<[u8]>]) -> BfResult<usize> {
+    let value = state.load();
+    if value != 0 {
+        state.store(0);
+        state.up_neg_offset(offset, value)?;
+    }
+    Ok(pc + 1)
+}
+
+pub fn find_zero_right(skip: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+                       _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.find_zero_right(skip)?;
+    Ok(pc + 1)
+}
+
+pub fn find_zero_left(skip: usize, pc: usize, state: &mut State, _: &mut dyn Channel,
+                      _: &[Box<[u8]>]) -> BfResult<usize> {
+    state.find_zero_left(skip)?;
+    Ok(pc + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::*;
+    use common::Error;
+    use config::Config;
+
+    #[test]
+    fn hello_world() {
+        assert_parse_interpret(HELLO_WORLD_SRC, "", "Hello, World!");
+    }
+
+    #[test]
+    fn factoring() {
+        assert_parse_interpret(FACTOR_SRC, "2\n", "2: 2\n");
+        assert_parse_interpret(FACTOR_SRC, "3\n", "3: 3\n");
+        assert_parse_interpret(FACTOR_SRC, "6\n", "6: 2 3\n");
+        assert_parse_interpret(FACTOR_SRC, "100\n", "100: 2 2 5 5\n");
+    }
+
+    #[test]
+    fn pointer_errors() {
+        let program = ::ast::parse_program(b"<").unwrap();
+        let program = ::threaded::ThreadedCompilable::threaded_compile(&*program, &Config::new());
+        assert_interpret_result(&program, b"", Err(Error::PointerUnderflow));
+
+        let program = ::ast::parse_program(b"+[>+]").unwrap();
+        let program = ::threaded::ThreadedCompilable::threaded_compile(&*program, &Config::new());
+        assert_interpret_result(&program, b"", Err(Error::PointerOverflow));
+    }
+
+    fn assert_parse_interpret(program: &[u8], input: &str, output: &str) {
+        let program = ::ast::parse_program(program).unwrap();
+        let program = ::rle::compile(&program);
+        let program = ::peephole::compile(&program, &Config::new());
+        let program = ::bytecode::compile(&program);
+        let program = ::threaded::compile(&program);
+        assert_interpret(&program, input.as_bytes(), output.as_bytes());
+    }
+}
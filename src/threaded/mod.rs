@@ -0,0 +1,42 @@
+//! A threaded interpreter for bytecode.
+//!
+//! The fastest implementation, the [JIT](../jit/index.html), needs x86-64 and nightly Rust. This
+//! pass is portable and runs on stable Rust. It decodes [bytecode](../bytecode/index.html) ahead
+//! of time into an array of operations, each of which is a pointer to the function that carries
+//! it out together with its operand. Each function returns the address of the next operation,
+//! so the interpreter’s inner loop does no decoding at all; it just calls through the array. (In
+//! other words, this is call threading, since Rust cannot guarantee the tail calls that direct
+//! threading needs.)
+//!
+//! In `bfi`, pass the `--threaded` flag to use this interpreter.
+
+mod compiler;
+mod interpreter;
+
+pub use self::compiler::{compile, ThreadedCompilable};
+
+use channel::Channel;
+use common::BfResult;
+use state::State;
+
+/// A threaded program is an array of pre-decoded operations, along with the bytecode’s string
+/// table.
+#[derive(Clone, Debug)]
+pub struct Program {
+    ops: Box<[Op]>,
+    strings: Box<[Box<[u8]>]>,
+}
+
+/// A pre-decoded instruction.
+#[derive(Clone, Copy, Debug)]
+struct Op {
+    /// The function that carries out the instruction.
+    handler: Handler,
+    /// The instruction’s count, offset, address, byte, or string index.
+    operand: usize,
+}
+
+/// Carries out one instruction, given its operand and address, returning the address of the next
+/// instruction to run.
+type Handler = fn(operand: usize, pc: usize, state: &mut State, channel: &mut dyn Channel,
+                  strings: &[Box<[u8]>]) -> BfResult<usize>;
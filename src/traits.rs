@@ -3,47 +3,156 @@
 
 use std::io::{Cursor, Read, Write, stdin, stdout};
 
+use channel::{Buffering, BufferedChannel, Channel, Io, IoChannel};
 use common::BfResult;
+use config::Config;
 use state::State;
 
 pub use rle::RleCompilable;
 pub use peephole::PeepholeCompilable;
 pub use bytecode::BytecodeCompilable;
+pub use threaded::ThreadedCompilable;
+pub use compact::CompactCompilable;
+pub use c::CCompilable;
+pub use rust::RustCompilable;
+pub use wasm::WasmCompilable;
 #[cfg(feature = "jit")]
 pub use jit::JitCompilable;
 #[cfg(feature = "llvm")]
 pub use llvm::LlvmCompilable;
+#[cfg(feature = "async")]
+pub use bytecode::asynchronous::AsyncRunnable;
 
 /// Program forms that can be interpreted.
 pub trait Interpretable {
-    /// Interprets a program against the given state.
+    /// Interprets a program against the given state, doing I/O through the given channel, and
+    /// leaving the state as the program left it, whether it finished or failed.
+    ///
+    /// This does not flush the channel when the program finishes.
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, channel: &mut C)
+        -> BfResult<()>;
+
+    /// Interprets a program against the given state, doing I/O through the given channel.
+    ///
+    /// This does not flush the channel when the program finishes.
+    fn interpret_channel<C: Channel + ?Sized>(&self, mut state: State, channel: &mut C)
+        -> BfResult<()>
+    {
+        self.interpret_in_place(&mut state, channel)
+    }
+
+    /// Interprets a program against the given state, with the given buffering for I/O.
+    ///
+    /// Output is flushed when the program finishes, whether successfully or not.
+    fn interpret_buffered<R: Read, W: Write>(&self, state: State,
+                                             input: R, output: W, buffering: Buffering)
+        -> BfResult<()>
+    {
+        let mut channel = BufferedChannel::with_buffering(input, output, buffering);
+        let result = self.interpret_channel(state, &mut channel);
+        channel.flush();
+        result
+    }
+
+    /// Interprets a program against the given state, doing I/O through the given callbacks.
+    ///
+    /// If input would block, this stops at the `,` with `Err(WouldBlock)`.
+    fn interpret_io<H: Io + ?Sized>(&self, state: State, io: &mut H) -> BfResult<()> {
+        self.interpret_channel(state, &mut IoChannel::new(io))
+    }
+
+    /// Interprets a program against the given state, with the default buffering.
     fn interpret_state<R: Read, W: Write>(&self, state: State,
                                           input: R, output: W)
-        -> BfResult<()>;
+        -> BfResult<()>
+    {
+        self.interpret_buffered(state, input, output, Buffering::default())
+    }
 
-    /// Interprets a program. If the given `size` is `None`, the default memory size.
-    fn interpret<R: Read, W: Write>(
-        &self, size: Option<usize>, input: R, output: W) -> BfResult<()>
+    /// Interprets a program with a fresh state for the given configuration.
+    fn interpret<R: Read, W: Write>(&self, config: &Config, input: R, output: W)
+        -> BfResult<()>
     {
-        let state = size.map(State::with_capacity).unwrap_or_else(State::new);
-        self.interpret_state(state, input, output)
+        self.interpret_state(State::with_config(config), input, output)
     }
 
     /// Interprets a program using stdin and stdout for input and output.
-    fn interpret_stdin(&self, size: Option<usize>) -> BfResult<()> {
-        self.interpret(size, stdin(), stdout())
+    fn interpret_stdin(&self, config: &Config) -> BfResult<()> {
+        self.interpret(config, stdin(), stdout())
     }
 
     /// Interprets a program from memory, returning a vector of its output.
-    fn interpret_memory(&self, size: Option<usize>, input: &[u8]) -> BfResult<Vec<u8>> {
+    fn interpret_memory(&self, config: &Config, input: &[u8]) -> BfResult<Vec<u8>> {
         let input = Cursor::new(input);
         let mut output = Cursor::new(Vec::new());
 
-        self.interpret(size, input, &mut output)?;
+        self.interpret(config, input, &mut output)?;
         Ok(output.into_inner())
     }
 }
 
+impl<P: Interpretable + ?Sized> Interpretable for Box<P> {
+    fn interpret_in_place<C: Channel + ?Sized>(&self, state: &mut State, channel: &mut C)
+        -> BfResult<()>
+    {
+        (**self).interpret_in_place(state, channel)
+    }
+}
+
+/// An object-safe companion to [`Interpretable`](trait.Interpretable.html).
+///
+/// `Interpretable`’s methods are generic over their I/O, so it can’t be made into a trait
+/// object. Every `Interpretable` program form is also `Runnable`, which takes its I/O as trait
+/// objects instead, so a `Box<dyn Runnable>` can hold a program whose form is chosen at run
+/// time. See [`engine`](../engine/index.html).
+pub trait Runnable {
+    /// Runs a program against the given state, doing I/O through the given channel, and
+    /// leaving the state as the program left it.
+    ///
+    /// This does not flush the channel when the program finishes.
+    fn run_in_place(&self, state: &mut State, channel: &mut dyn Channel) -> BfResult<()>;
+
+    /// Runs a program against the given state, doing I/O through the given channel.
+    ///
+    /// This does not flush the channel when the program finishes.
+    fn run_channel(&self, state: State, channel: &mut dyn Channel) -> BfResult<()>;
+
+    /// Runs a program against the given state, with the given buffering for I/O.
+    ///
+    /// Output is flushed when the program finishes, whether successfully or not.
+    fn run(&self, state: State, input: &mut dyn Read, output: &mut dyn Write,
+           buffering: Buffering) -> BfResult<()>;
+
+    /// Runs a program against the given state, doing I/O through the given callbacks.
+    fn run_io(&self, state: State, io: &mut dyn Io) -> BfResult<()>;
+
+    /// Runs a program from memory, returning a vector of its output.
+    fn run_memory(&self, config: &Config, input: &[u8]) -> BfResult<Vec<u8>>;
+}
+
+impl<P: Interpretable + ?Sized> Runnable for P {
+    fn run_in_place(&self, state: &mut State, channel: &mut dyn Channel) -> BfResult<()> {
+        self.interpret_in_place(state, channel)
+    }
+
+    fn run_channel(&self, state: State, channel: &mut dyn Channel) -> BfResult<()> {
+        self.interpret_channel(state, channel)
+    }
+
+    fn run(&self, state: State, input: &mut dyn Read, output: &mut dyn Write,
+           buffering: Buffering) -> BfResult<()> {
+        self.interpret_buffered(state, input, output, buffering)
+    }
+
+    fn run_io(&self, state: State, io: &mut dyn Io) -> BfResult<()> {
+        self.interpret_io(state, io)
+    }
+
+    fn run_memory(&self, config: &Config, input: &[u8]) -> BfResult<Vec<u8>> {
+        self.interpret_memory(config, input)
+    }
+}
+
 /// For converting smaller numeric types into `usize`.
 pub trait IntoUsize {
     fn into_usize(self) -> usize;
@@ -0,0 +1,295 @@
+use super::*;
+
+/// The value type `i32`.
+const I32: u8 = 0x7F;
+
+/// The empty block type.
+const EMPTY: u8 = 0x40;
+
+/// Writes a module in the WebAssembly binary format.
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"\0asm");
+    out.extend_from_slice(&[1, 0, 0, 0]);
+
+    // Types: 0 is [] -> [i32], for `read` and `run`; 1 is [i32] -> [], for `write`; 2 is
+    // [i32 i32] -> [], for `write_bytes`.
+    section(&mut out, 1, |buf| {
+        unsigned(buf, 3);
+        buf.extend_from_slice(&[0x60, 0, 1, I32]);
+        buf.extend_from_slice(&[0x60, 1, I32, 0]);
+        buf.extend_from_slice(&[0x60, 2, I32, I32, 0]);
+    });
+
+    section(&mut out, 2, |buf| {
+        unsigned(buf, 3);
+        import(buf, Import::Read, 0);
+        import(buf, Import::Write, 1);
+        import(buf, Import::WriteBytes, 2);
+    });
+
+    // Functions: `run` has type 0.
+    section(&mut out, 3, |buf| {
+        unsigned(buf, 1);
+        unsigned(buf, 0);
+    });
+
+    // Memory: one, with a minimum and no maximum.
+    section(&mut out, 5, |buf| {
+        unsigned(buf, 1);
+        buf.push(0x00);
+        unsigned(buf, module.pages as u64);
+    });
+
+    // Exports: `run` comes after the three imports in the function index space.
+    section(&mut out, 7, |buf| {
+        unsigned(buf, 2);
+        name(buf, "run");
+        buf.push(0x00);
+        unsigned(buf, 3);
+        name(buf, "memory");
+        buf.push(0x02);
+        unsigned(buf, 0);
+    });
+
+    section(&mut out, 10, |buf| {
+        let mut body = Vec::new();
+        // One local, the pointer.
+        body.extend_from_slice(&[1, 1, I32]);
+        for &instr in &module.code {
+            encode_instr(&mut body, instr);
+        }
+        body.push(0x0B);
+
+        unsigned(buf, 1);
+        unsigned(buf, body.len() as u64);
+        buf.extend_from_slice(&body);
+    });
+
+    // Data: one active segment for memory 0, at the constant address after the tape.
+    if !module.data.is_empty() {
+        section(&mut out, 11, |buf| {
+            unsigned(buf, 1);
+            buf.push(0x00);
+            encode_instr(buf, Instr::Const(module.data_address as i32));
+            buf.push(0x0B);
+            unsigned(buf, module.data.len() as u64);
+            buf.extend_from_slice(&module.data);
+        });
+    }
+
+    out
+}
+
+fn encode_instr(buf: &mut Vec<u8>, instr: Instr) {
+    use super::Instr::*;
+
+    match instr {
+        Block => buf.extend_from_slice(&[0x02, EMPTY]),
+        Loop => buf.extend_from_slice(&[0x03, EMPTY]),
+        If => buf.extend_from_slice(&[0x04, EMPTY]),
+        End => buf.push(0x0B),
+
+        Br(depth) => {
+            buf.push(0x0C);
+            unsigned(buf, depth as u64);
+        }
+
+        BrIf(depth) => {
+            buf.push(0x0D);
+            unsigned(buf, depth as u64);
+        }
+
+        Return => buf.push(0x0F),
+
+        Call(import) => {
+            buf.push(0x10);
+            unsigned(buf, import.index() as u64);
+        }
+
+        GetPointer => buf.extend_from_slice(&[0x20, 0]),
+        SetPointer => buf.extend_from_slice(&[0x21, 0]),
+
+        // The memory argument is the alignment exponent, then the offset.
+        Load8(offset) => {
+            buf.extend_from_slice(&[0x2D, 0]);
+            unsigned(buf, offset as u64);
+        }
+
+        Store8(offset) => {
+            buf.extend_from_slice(&[0x3A, 0]);
+            unsigned(buf, offset as u64);
+        }
+
+        Const(value) => {
+            buf.push(0x41);
+            signed(buf, value as i64);
+        }
+
+        Eqz => buf.push(0x45),
+        LtU => buf.push(0x49),
+        GeU => buf.push(0x4F),
+        Add => buf.push(0x6A),
+        Sub => buf.push(0x6B),
+    }
+}
+
+/// Writes a section with the given id, whose contents are written by `contents`.
+fn section<F: FnOnce(&mut Vec<u8>)>(out: &mut Vec<u8>, id: u8, contents: F) {
+    let mut buf = Vec::new();
+    contents(&mut buf);
+    out.push(id);
+    unsigned(out, buf.len() as u64);
+    out.extend_from_slice(&buf);
+}
+
+fn import(buf: &mut Vec<u8>, import: Import, type_index: u64) {
+    name(buf, "bf");
+    name(buf, import.name());
+    buf.push(0x00);
+    unsigned(buf, type_index);
+}
+
+fn name(buf: &mut Vec<u8>, name: &str) {
+    unsigned(buf, name.len() as u64);
+    buf.extend_from_slice(name.as_bytes());
+}
+
+/// Writes an unsigned LEB128 number.
+fn unsigned(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Writes a signed LEB128 number.
+fn signed(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = value as u8 & 0x7F;
+        value >>= 7;
+
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            buf.push(byte);
+            return;
+        }
+
+        buf.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{BfResult, Error};
+    use config::Config;
+    use rts;
+    use test_helpers::*;
+    use traits::{BytecodeCompilable, Interpretable, WasmCompilable};
+
+    use std::fs;
+    use std::process::Command;
+
+    /// A Node.js host for the module, which runs it on the input in its second argument and
+    /// exits with the status that `run` returns.
+    const HOST_JS: &str = "\
+const bytes = require('fs').readFileSync(process.argv[2]);
+const input = Buffer.from(process.argv[3]);
+const output = [];
+let pos = 0, memory;
+const bf = {
+  read: () => pos < input.length ? input[pos++] : 0,
+  write: byte => output.push(byte & 255),
+  write_bytes: (address, len) => output.push(...new Uint8Array(memory.buffer, address, len)),
+};
+WebAssembly.instantiate(bytes, {bf}).then(({instance}) => {
+  memory = instance.exports.memory;
+  const status = instance.exports.run();
+  process.stdout.write(Buffer.from(output));
+  process.exit(status);
+});
+";
+
+    #[test]
+    fn leb128() {
+        let mut buf = Vec::new();
+        unsigned(&mut buf, 624_485);
+        assert_eq!(buf, [0xE5, 0x8E, 0x26]);
+
+        buf.clear();
+        signed(&mut buf, -123_456);
+        assert_eq!(buf, [0xC0, 0xBB, 0x78]);
+
+        buf.clear();
+        signed(&mut buf, 64);
+        assert_eq!(buf, [0xC0, 0x00]);
+    }
+
+    #[test]
+    fn empty_module() {
+        let module = Module { pages: 1, data_address: 0, data: Vec::new(),
+                              code: vec![Instr::Const(0)] };
+        let binary = encode(&module);
+
+        assert_eq!(&binary[.. 8], b"\0asm\x01\0\0\0");
+        // The code section: one body of 6 bytes with one local, `i32.const 0`, and `end`.
+        assert!(binary.ends_with(&[10, 8, 1, 6, 1, 1, I32, 0x41, 0, 0x0B]));
+    }
+
+    #[test]
+    fn hello_world() {
+        assert_compile_run("hello", HELLO_WORLD_SRC, "");
+    }
+
+    #[test]
+    fn factoring() {
+        assert_compile_run("factor", FACTOR_SRC, "100\n");
+    }
+
+    #[test]
+    fn pointer_errors() {
+        assert_compile_run("underflow", b"<", "");
+        assert_compile_run("overflow", b"+[>+]", "");
+        assert_compile_run("offset", b"+[-<+>]", "");
+    }
+
+    /// Runs the program’s binary module with Node.js, if it is installed, and checks that it
+    /// behaves like the bytecode interpreter.
+    fn assert_compile_run(name: &str, program: &[u8], input: &str) {
+        if Command::new("node").arg("--version").output().is_err() {
+            return;
+        }
+
+        let config = Config::new();
+        let program = ::ast::parse_program(program).unwrap();
+
+        let expected = program.bytecode_compile(&config)
+            .interpret_memory(&config, input.as_bytes());
+        let actual = run(name, &program.wasm_compile(&config).to_binary(), input);
+
+        assert_eq!(actual, expected);
+    }
+
+    fn run(name: &str, binary: &[u8], input: &str) -> BfResult<Vec<u8>> {
+        let dir = ::std::env::temp_dir()
+            .join(format!("bf-wasm-test-{}-{}", ::std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let host = dir.join("host.js");
+        let module = dir.join("program.wasm");
+
+        fs::write(&host, HOST_JS).unwrap();
+        fs::write(&module, binary).unwrap();
+        let result = Command::new("node").arg(&host).arg(&module).arg(input).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result.status.code().unwrap() as u64 {
+            rts::OKAY      => Ok(result.stdout),
+            rts::UNDERFLOW => Err(Error::PointerUnderflow),
+            rts::OVERFLOW  => Err(Error::PointerOverflow),
+            code => panic!("node failed with exit code {}: {}", code,
+                           String::from_utf8_lossy(&result.stderr)),
+        }
+    }
+}
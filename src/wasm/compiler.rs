@@ -0,0 +1,227 @@
+use super::*;
+use super::Instr::*;
+use common::Count;
+use config::Config;
+use peephole::{self, Statement};
+use rts;
+use traits::IntoUsize;
+
+/// Program forms that can be compiled to WebAssembly.
+pub trait WasmCompilable {
+    /// Compile the given program into the peephole AST to prepare for WebAssembly generation.
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R;
+
+    /// Compile the given program to a WebAssembly module with the configuration’s amount of
+    /// memory.
+    fn wasm_compile(&self, config: &Config) -> Module {
+        // The module defines its own memory, which starts out zero.
+        let config = &config.fresh_memory(true);
+        self.with_peephole(config, |ast| compile(ast, config))
+    }
+}
+
+/// Compiles peephole-optimized AST to a WebAssembly module with the configuration’s amount of
+/// memory.
+///
+/// What `,` stores at the end of the input is up to the host’s `read` function, so the
+/// configuration’s EOF mode is ignored.
+///
+/// # Panics
+///
+/// Panics if the memory size does not fit in 32-bit linear memory.
+pub fn compile(program: &peephole::Program, config: &Config) -> Module {
+    let memory_size = config.memory_size;
+    assert!(memory_size <= i32::MAX as usize, "memory too large for WebAssembly");
+
+    let mut compiler = Compiler {
+        code: Vec::new(),
+        data: Vec::new(),
+        checked: config.checked,
+        memory_size,
+    };
+
+    compiler.compile(program);
+    compiler.code.push(Const(rts::OKAY as i32));
+
+    let total_size = memory_size + compiler.data.len();
+    assert!(total_size <= i32::MAX as usize, "memory too large for WebAssembly");
+
+    Module {
+        pages: total_size.div_ceil(PAGE_SIZE).max(1) as u32,
+        data_address: memory_size as u32,
+        data: compiler.data,
+        code: compiler.code,
+    }
+}
+
+/// The compiler state.
+struct Compiler {
+    /// The body of `run` so far.
+    code: Vec<Instr>,
+    /// The constant output strings so far, to be stored after the tape.
+    data: Vec<u8>,
+    /// Whether we are emitting bounds checks.
+    checked: bool,
+    /// The size of the tape.
+    memory_size: usize,
+}
+
+impl Compiler {
+    fn compile(&mut self, program: &[Statement]) {
+        for statement in program {
+            self.compile_statement(statement);
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) {
+        use common::Instruction::*;
+
+        match *statement {
+            Statement::Instr(Right(count)) => self.move_right(count),
+            Statement::Instr(Left(count)) => self.move_left(count),
+
+            Statement::Instr(Add(amount)) => self.emit(&[
+                GetPointer, GetPointer, Load8(0), Const(amount as i32), Instr::Add, Store8(0),
+            ]),
+
+            Statement::Instr(In) =>
+                self.emit(&[GetPointer, Call(Import::Read), Store8(0)]),
+
+            Statement::Instr(Out) =>
+                self.emit(&[GetPointer, Load8(0), Call(Import::Write)]),
+
+            Statement::Instr(OutByte(byte)) =>
+                self.emit(&[Const(byte as i32), Call(Import::Write)]),
+
+            Statement::OutConst(ref bytes) => {
+                let address = self.memory_size + self.data.len();
+                self.data.extend_from_slice(bytes);
+                self.emit(&[Const(address as i32), Const(bytes.len() as i32),
+                            Call(Import::WriteBytes)]);
+            }
+
+            Statement::Instr(SetZero) =>
+                self.emit(&[GetPointer, Const(0), Store8(0)]),
+
+            Statement::Instr(OffsetAddRight(offset)) => {
+                let offset = offset.into_usize();
+                self.emit(&[GetPointer, Load8(0), If]);
+                self.check_right(offset);
+                // The offset fits in the memory argument, since the check passed.
+                self.emit(&[
+                    GetPointer,
+                    GetPointer, Load8(offset as u32), GetPointer, Load8(0), Instr::Add,
+                    Store8(offset as u32),
+                ]);
+                self.emit(&[GetPointer, Const(0), Store8(0), End]);
+            }
+
+            Statement::Instr(OffsetAddLeft(offset)) => {
+                let offset = offset.into_usize();
+                self.emit(&[GetPointer, Load8(0), If]);
+                self.check_left(offset);
+                self.emit(&[
+                    GetPointer, Const(offset as i32), Sub,
+                    GetPointer, Const(offset as i32), Sub, Load8(0),
+                    GetPointer, Load8(0), Instr::Add,
+                    Store8(0),
+                ]);
+                self.emit(&[GetPointer, Const(0), Store8(0), End]);
+            }
+
+            Statement::Instr(FindZeroRight(skip)) => {
+                self.begin_loop();
+                self.move_right(skip);
+                self.end_loop();
+            }
+
+            Statement::Instr(FindZeroLeft(skip)) => {
+                self.begin_loop();
+                self.move_left(skip);
+                self.end_loop();
+            }
+
+            Statement::Instr(JumpZero(_)) | Statement::Instr(JumpNotZero(_)) =>
+                panic!("unexpected jump instruction"),
+            Statement::Instr(OutString(_)) =>
+                panic!("unexpected string instruction"),
+
+            Statement::Loop(ref body) => {
+                self.begin_loop();
+                self.compile(body);
+                self.end_loop();
+            }
+        }
+    }
+
+    fn move_right(&mut self, count: Count) {
+        let count = count.into_usize();
+        self.check_right(count);
+        self.emit(&[GetPointer, Const(count as i32), Instr::Add, SetPointer]);
+    }
+
+    fn move_left(&mut self, count: Count) {
+        let count = count.into_usize();
+        self.check_left(count);
+        self.emit(&[GetPointer, Const(count as i32), Sub, SetPointer]);
+    }
+
+    /// Returns `OVERFLOW` if the pointer cannot move right by `count`.
+    fn check_right(&mut self, count: usize) {
+        if !self.checked {
+            return;
+        }
+
+        if count >= self.memory_size {
+            self.emit(&[Const(rts::OVERFLOW as i32), Return]);
+        } else {
+            let limit = (self.memory_size - count) as i32;
+            self.emit(&[GetPointer, Const(limit), GeU, If,
+                        Const(rts::OVERFLOW as i32), Return, End]);
+        }
+    }
+
+    /// Returns `UNDERFLOW` if the pointer cannot move left by `count`.
+    fn check_left(&mut self, count: usize) {
+        if !self.checked {
+            return;
+        }
+
+        if count >= self.memory_size {
+            self.emit(&[Const(rts::UNDERFLOW as i32), Return]);
+        } else {
+            self.emit(&[GetPointer, Const(count as i32), LtU, If,
+                        Const(rts::UNDERFLOW as i32), Return, End]);
+        }
+    }
+
+    /// Starts a loop that runs while the current cell is non-zero.
+    fn begin_loop(&mut self) {
+        self.emit(&[Block, Loop, GetPointer, Load8(0), Eqz, BrIf(1)]);
+    }
+
+    fn end_loop(&mut self) {
+        self.emit(&[Br(0), End, End]);
+    }
+
+    fn emit(&mut self, instrs: &[Instr]) {
+        self.code.extend_from_slice(instrs);
+    }
+}
+
+impl WasmCompilable for peephole::Program {
+    fn with_peephole<F, R>(&self, _config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R
+    {
+        k(self)
+    }
+}
+
+impl<T: peephole::PeepholeCompilable + ?Sized> WasmCompilable for T {
+    fn with_peephole<F, R>(&self, config: &Config, k: F) -> R
+        where F: FnOnce(&peephole::Program) -> R
+    {
+        k(&self.peephole_compile(config))
+    }
+}
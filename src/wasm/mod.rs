@@ -0,0 +1,116 @@
+//! Compiles peephole-optimized AST to WebAssembly.
+//!
+//! The generated module imports three functions from module `bf`, mirroring
+//! [`RtsState::read`](../rts/struct.RtsState.html#method.read),
+//! [`RtsState::write`](../rts/struct.RtsState.html#method.write), and
+//! [`RtsState::write_bytes`](../rts/struct.RtsState.html#method.write_bytes):
+//!
+//!   - `read: [] -> [i32]` returns the next byte of input, or 0 at the end of the input;
+//!   - `write: [i32] -> []` writes the low byte of its argument;
+//!   - `write_bytes: [i32 i32] -> []` writes the given number of bytes from the given address in
+//!     `memory`.
+//!
+//! It exports its linear memory as `memory`. The first `memory_size` bytes serve as the tape,
+//! and the statically known output that `write_bytes` writes is stored right after them, where
+//! only an unchecked program can reach it. The module exports the program itself
+//! as `run: [] -> [i32]`. In checked mode, `run` returns [`rts::OKAY`](../rts/constant.OKAY.html),
+//! or [`rts::UNDERFLOW`](../rts/constant.UNDERFLOW.html) or
+//! [`rts::OVERFLOW`](../rts/constant.OVERFLOW.html) if the pointer would leave the first
+//! `memory_size` bytes; in unchecked mode, only the bounds of linear memory itself are checked,
+//! and by trapping.
+//!
+//! A [`Module`](struct.Module.html) can be written both as `.wat` text and as a `.wasm` binary,
+//! which is encoded by hand so that no external toolchain is needed. In `bfi`, pass `--emit wat`
+//! or `--emit wasm`.
+
+mod compiler;
+mod text;
+mod binary;
+
+pub use self::compiler::{compile, WasmCompilable};
+
+/// (`== 65_536`) The size of a page of WebAssembly linear memory.
+pub const PAGE_SIZE: usize = 0x1_0000;
+
+/// A WebAssembly module containing a compiled program.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Module {
+    /// The number of pages of linear memory.
+    pages: u32,
+    /// The address of `data` in linear memory.
+    data_address: u32,
+    /// The constant output strings, which are loaded into memory after the tape.
+    data: Vec<u8>,
+    /// The body of the `run` function.
+    code: Vec<Instr>,
+}
+
+impl Module {
+    /// The module in the WebAssembly text format.
+    pub fn to_text(&self) -> String {
+        text::encode(self)
+    }
+
+    /// The module in the WebAssembly binary format.
+    pub fn to_binary(&self) -> Vec<u8> {
+        binary::encode(self)
+    }
+}
+
+/// The subset of WebAssembly instructions used by the compiler.
+///
+/// Blocks have no parameters or results, the only local is the pointer, and the only functions
+/// called are the imports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Instr {
+    Block,
+    Loop,
+    If,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(Import),
+    /// Gets the pointer.
+    GetPointer,
+    /// Sets the pointer.
+    SetPointer,
+    /// Loads a byte from the given offset past the address on the stack.
+    Load8(u32),
+    /// Stores a byte to the given offset past the address on the stack.
+    Store8(u32),
+    Const(i32),
+    Eqz,
+    LtU,
+    GeU,
+    Add,
+    Sub,
+}
+
+/// The imported functions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Import {
+    Read,
+    Write,
+    WriteBytes,
+}
+
+impl Import {
+    /// The function’s name in the `bf` module.
+    fn name(self) -> &'static str {
+        match self {
+            Import::Read => "read",
+            Import::Write => "write",
+            Import::WriteBytes => "write_bytes",
+        }
+    }
+
+    /// The function’s index in this module.
+    fn index(self) -> u32 {
+        match self {
+            Import::Read => 0,
+            Import::Write => 1,
+            Import::WriteBytes => 2,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::Write;
+
+use super::*;
+
+/// Writes a module in the WebAssembly text format.
+pub fn encode(module: &Module) -> String {
+    let mut out = String::new();
+
+    out.push_str("(module\n");
+    out.push_str("  (import \"bf\" \"read\" (func $read (result i32)))\n");
+    out.push_str("  (import \"bf\" \"write\" (func $write (param i32)))\n");
+    out.push_str("  (import \"bf\" \"write_bytes\" (func $write_bytes (param i32 i32)))\n");
+    let _ = writeln!(out, "  (memory (export \"memory\") {})", module.pages);
+    if !module.data.is_empty() {
+        let _ = writeln!(out, "  (data (i32.const {}) \"{}\")",
+                         module.data_address, escape(&module.data));
+    }
+    out.push_str("  (func (export \"run\") (result i32)\n");
+    out.push_str("    (local $p i32)\n");
+
+    let mut depth = 2;
+
+    for &instr in &module.code {
+        if instr == Instr::End {
+            depth -= 1;
+        }
+
+        for _ in 0 .. depth {
+            out.push_str("  ");
+        }
+        write_instr(&mut out, instr);
+        out.push('\n');
+
+        match instr {
+            Instr::Block | Instr::Loop | Instr::If => depth += 1,
+            _ => (),
+        }
+    }
+
+    out.push_str("  )\n)\n");
+    out
+}
+
+/// Escapes bytes for a WebAssembly string literal.
+fn escape(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                result.push('\\');
+                result.push(byte as char);
+            }
+            b' ' ..= b'~' => result.push(byte as char),
+            _ => {
+                let _ = write!(result, "\\{:02x}", byte);
+            }
+        }
+    }
+
+    result
+}
+
+fn write_instr(out: &mut String, instr: Instr) {
+    use super::Instr::*;
+
+    let _ = match instr {
+        Block => write!(out, "block"),
+        Loop => write!(out, "loop"),
+        If => write!(out, "if"),
+        End => write!(out, "end"),
+        Br(depth) => write!(out, "br {}", depth),
+        BrIf(depth) => write!(out, "br_if {}", depth),
+        Return => write!(out, "return"),
+        Call(import) => write!(out, "call ${}", import.name()),
+        GetPointer => write!(out, "local.get $p"),
+        SetPointer => write!(out, "local.set $p"),
+        Load8(0) => write!(out, "i32.load8_u"),
+        Load8(offset) => write!(out, "i32.load8_u offset={}", offset),
+        Store8(0) => write!(out, "i32.store8"),
+        Store8(offset) => write!(out, "i32.store8 offset={}", offset),
+        Const(value) => write!(out, "i32.const {}", value),
+        Eqz => write!(out, "i32.eqz"),
+        LtU => write!(out, "i32.lt_u"),
+        GeU => write!(out, "i32.ge_u"),
+        Add => write!(out, "i32.add"),
+        Sub => write!(out, "i32.sub"),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::compile;
+    use common::Instruction::*;
+    use config::Config;
+    use peephole::Statement::*;
+
+    #[test]
+    fn blocks_are_indented() {
+        let body = vec![Instr(Add(255))].into_boxed_slice();
+        let text = compile(&[Loop(body)], &Config::new().checked(false).memory_size(1)).to_text();
+
+        assert!(text.contains("    block\n      loop\n        local.get $p\n"));
+        assert!(text.ends_with("      end\n    end\n    i32.const 0\n  )\n)\n"));
+    }
+
+    #[test]
+    fn constant_output_is_data() {
+        let program = [OutConst(Box::from(&b"Hi\"\n"[..]))];
+        let text = compile(&program, &Config::new().memory_size(10)).to_text();
+
+        assert!(text.contains("  (data (i32.const 10) \"Hi\\\"\\0a\")\n"));
+        assert!(text.contains("    i32.const 10\n    i32.const 4\n    call $write_bytes\n"));
+    }
+}